@@ -54,6 +54,13 @@ unsafe fn switch_to_el1() {
     }
 }
 
+unsafe fn init_fpu() {
+    // Do not trap FP/SIMD instructions of user programs. The kernel itself is
+    // built without them, see `save_fp` and `restore_fp` of `Task`.
+    CPACR_EL1.write(CPACR_EL1::FPEN::TrapNothing);
+    barrier::isb(barrier::SY);
+}
+
 unsafe fn init_mmu() {
     // Device-nGnRE memory
     let attr0 = MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck;
@@ -117,6 +124,7 @@ unsafe extern "C" fn _start() -> ! {
         adrp    x8, boot_stack_top
         mov     sp, x8
         bl      {switch_to_el1}
+        bl      {init_fpu}
         bl      {init_boot_page_table}
         bl      {init_mmu}
         ldr     x8, =boot_stack_top
@@ -125,6 +133,7 @@ unsafe extern "C" fn _start() -> ! {
         br      x8
         b       .",
         switch_to_el1 = sym switch_to_el1,
+        init_fpu = sym init_fpu,
         init_boot_page_table = sym init_boot_page_table,
         init_mmu = sym init_mmu,
         rust_main = sym crate::rust_main,
@@ -33,6 +33,13 @@ pub unsafe fn set_thread_pointer(tp: usize) {
     TPIDR_EL1.set(tp as _)
 }
 
+/// Thread pointer of the user task, in `TPIDR_EL0`.
+pub fn user_thread_pointer() -> usize {
+    let tp;
+    unsafe { asm!("mrs {}, tpidr_el0", out(reg) tp) };
+    tp
+}
+
 pub unsafe fn activate_paging(page_table_root: usize, is_kernel: bool) {
     if is_kernel {
         // kernel space use TTBR1 (0xffff_0000_0000_0000..0xffff_ffff_ffff_ffff)
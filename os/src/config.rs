@@ -1,6 +1,11 @@
 pub const BOOT_KERNEL_STACK_SIZE: usize = 4096 * 4; // 16K
 pub const USER_STACK_SIZE: usize = 4096 * 4; // 16K
+/// The room that `argv` and `envp` can take on the user stack of a new program.
+pub const MAX_ARG_SIZE: usize = USER_STACK_SIZE / 4;
 pub const USER_STACK_TOP: usize = 0x8000_0000_0000;
+/// The thread stacks are below `USER_STACK_TOP`, the heap and the `mmap`
+/// areas below this.
+pub const USER_MMAP_TOP: usize = 0x4000_0000_0000;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 4; // 16K
 pub const KERNEL_HEAP_SIZE: usize = 0x40_0000; // 4M
 
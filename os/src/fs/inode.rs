@@ -7,6 +7,8 @@
 use super::File;
 use crate::drivers::BLOCK_DEVICE;
 use crate::sync::Mutex;
+use crate::syscall::errno::EFBIG;
+use crate::task::{CurrentTask, SignalFlags, RLIMIT_FSIZE};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
@@ -76,10 +78,14 @@ bitflags! {
         const WRONLY = 1 << 0;
         ///Read & Write
         const RDWR = 1 << 1;
+        ///Fail if the named object exists, with `CREATE`
+        const EXCL = 1 << 7;
         ///Allow create
         const CREATE = 1 << 9;
         ///Clear file and return an empty one
         const TRUNC = 1 << 10;
+        ///Fail with `EAGAIN` instead of blocking, for message queues
+        const NONBLOCK = 1 << 11;
     }
 }
 
@@ -127,16 +133,25 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, buf: &mut [u8]) -> usize {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
         let mut inner = self.inner.lock();
         let read_size = inner.inode.read_at(inner.offset, buf);
         inner.offset += read_size;
-        read_size
+        Ok(read_size)
     }
-    fn write(&self, buf: &[u8]) -> usize {
+    /// Writes no further than `RLIMIT_FSIZE`, and sends `SIGXFSZ` and fails
+    /// with `EFBIG` if nothing can be written.
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
+        let proc = CurrentTask::get().proc();
+        let max_size = proc.rlimits.lock().get(RLIMIT_FSIZE);
         let mut inner = self.inner.lock();
-        let read_size = inner.inode.write_at(inner.offset, buf);
+        if inner.offset >= max_size {
+            proc.send_signal(SignalFlags::SIGXFSZ);
+            return Err(-EFBIG);
+        }
+        let len = buf.len().min(max_size - inner.offset);
+        let read_size = inner.inode.write_at(inner.offset, &buf[..len]);
         inner.offset += read_size;
-        read_size
+        Ok(read_size)
     }
 }
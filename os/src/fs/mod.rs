@@ -3,18 +3,24 @@ mod inode;
 mod pipe;
 mod stdio;
 
+use crate::syscall::errno::ENOTTY;
+
 /// File trait
 pub trait File: Send + Sync {
     /// If readable
     fn readable(&self) -> bool;
     /// If writable
     fn writable(&self) -> bool;
-    /// Read file to `UserBuffer`
-    fn read(&self, buf: &mut [u8]) -> usize;
-    /// Write `UserBuffer` to file
-    fn write(&self, buf: &[u8]) -> usize;
+    /// Read file to `UserBuffer`, returns the size read or a negated errno
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize>;
+    /// Write `UserBuffer` to file, returns the size written or a negated errno
+    fn write(&self, buf: &[u8]) -> Result<usize, isize>;
+    /// Device-specific control, only the terminal supports it
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> isize {
+        -ENOTTY
+    }
 }
 
 pub use inode::{list_apps, open_file, OSInode, OpenFlags};
 pub use pipe::{make_pipe, Pipe};
-pub use stdio::{Stdin, Stdout};
+pub use stdio::{tty_poll, Stdin, Stdout};
@@ -1,6 +1,7 @@
 use super::File;
-use crate::sync::Mutex;
-use crate::task::CurrentTask;
+use crate::sync::{Mutex, WaitQueue};
+use crate::syscall::errno::{EPIPE, ERESTARTSYS};
+use crate::task::{CurrentTask, SigInfo, SignalFlags};
 use alloc::sync::{Arc, Weak};
 
 pub struct Pipe {
@@ -40,7 +41,11 @@ pub struct PipeRingBuffer {
     head: usize,
     tail: usize,
     status: RingBufferStatus,
+    read_end: Option<Weak<Pipe>>,
     write_end: Option<Weak<Pipe>>,
+    // the readers waiting for data, and the writers waiting for room
+    read_queue: Arc<WaitQueue>,
+    write_queue: Arc<WaitQueue>,
 }
 
 impl PipeRingBuffer {
@@ -50,9 +55,15 @@ impl PipeRingBuffer {
             head: 0,
             tail: 0,
             status: RingBufferStatus::Empty,
+            read_end: None,
             write_end: None,
+            read_queue: Arc::new(WaitQueue::new()),
+            write_queue: Arc::new(WaitQueue::new()),
         }
     }
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
@@ -63,6 +74,7 @@ impl PipeRingBuffer {
         if self.tail == self.head {
             self.status = RingBufferStatus::Full;
         }
+        self.read_queue.notify_all();
     }
     pub fn read_byte(&mut self) -> u8 {
         self.status = RingBufferStatus::Normal;
@@ -71,6 +83,7 @@ impl PipeRingBuffer {
         if self.head == self.tail {
             self.status = RingBufferStatus::Empty;
         }
+        self.write_queue.notify_all();
         c
     }
     pub fn available_read(&self) -> usize {
@@ -89,20 +102,43 @@ impl PipeRingBuffer {
             RING_BUFFER_SIZE - self.available_read()
         }
     }
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
+    }
     pub fn all_write_ends_closed(&self) -> bool {
         self.write_end.as_ref().unwrap().upgrade().is_none()
     }
 }
 
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        // the other end may wait for this one to be closed
+        let ring_buffer = self.buffer.lock();
+        ring_buffer.read_queue.notify_all();
+        ring_buffer.write_queue.notify_all();
+    }
+}
+
 /// Return (read_end, write_end)
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
-    buffer.lock().set_write_end(&write_end);
+    let mut ring_buffer = buffer.lock();
+    ring_buffer.set_read_end(&read_end);
+    ring_buffer.set_write_end(&write_end);
     (read_end, write_end)
 }
 
+/// The result of a transfer interrupted after `size` bytes.
+fn interrupted(size: usize) -> Result<usize, isize> {
+    if size > 0 {
+        Ok(size)
+    } else {
+        Err(-ERESTARTSYS)
+    }
+}
+
 impl File for Pipe {
     fn readable(&self) -> bool {
         self.readable
@@ -110,7 +146,9 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, buf: &mut [u8]) -> usize {
+    /// Interrupted by a signal, returns what has been read so far, or fails
+    /// with `ERESTARTSYS` if nothing.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
         assert!(self.readable());
         let mut buf_iter = buf.iter_mut();
         let mut read_size = 0usize;
@@ -119,10 +157,17 @@ impl File for Pipe {
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
                 if ring_buffer.all_write_ends_closed() {
-                    return read_size;
+                    return Ok(read_size);
                 }
+                let read_queue = ring_buffer.read_queue.clone();
                 drop(ring_buffer);
-                CurrentTask::get().yield_now();
+                let waited = read_queue.wait_until_interruptible(|| {
+                    let ring_buffer = self.buffer.lock();
+                    ring_buffer.available_read() > 0 || ring_buffer.all_write_ends_closed()
+                });
+                if waited.is_err() {
+                    return interrupted(read_size);
+                }
                 continue;
             }
             // read at most loop_read bytes
@@ -131,21 +176,42 @@ impl File for Pipe {
                     *byte_ref = ring_buffer.read_byte();
                     read_size += 1;
                 } else {
-                    return read_size;
+                    return Ok(read_size);
                 }
             }
         }
     }
-    fn write(&self, buf: &[u8]) -> usize {
+    /// Interrupted by a signal, returns what has been written so far, or
+    /// fails with `ERESTARTSYS` if nothing. Once all read ends are closed,
+    /// sends `SIGPIPE` to the writer, and likewise returns what has been
+    /// written or fails with `EPIPE`.
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
         assert!(self.writable());
+        let task = CurrentTask::get();
         let mut buf_iter = buf.iter();
         let mut write_size = 0usize;
         loop {
             let mut ring_buffer = self.buffer.lock();
+            if ring_buffer.all_read_ends_closed() {
+                drop(ring_buffer);
+                task.send_signal_info(SigInfo::kernel(SignalFlags::SIGPIPE));
+                return if write_size > 0 {
+                    Ok(write_size)
+                } else {
+                    Err(-EPIPE)
+                };
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                let write_queue = ring_buffer.write_queue.clone();
                 drop(ring_buffer);
-                CurrentTask::get().yield_now();
+                let waited = write_queue.wait_until_interruptible(|| {
+                    let ring_buffer = self.buffer.lock();
+                    ring_buffer.available_write() > 0 || ring_buffer.all_read_ends_closed()
+                });
+                if waited.is_err() {
+                    return interrupted(write_size);
+                }
                 continue;
             }
             // write at most loop_write bytes
@@ -154,7 +220,7 @@ impl File for Pipe {
                     ring_buffer.write_byte(*byte_ref);
                     write_size += 1;
                 } else {
-                    return write_size;
+                    return Ok(write_size);
                 }
             }
         }
@@ -1,13 +1,115 @@
 //!Stdin & Stdout
 use super::File;
 use crate::arch::console_getchar;
-use crate::task::{CurrentTask, SignalFlags};
+use crate::mm::{UserInPtr, UserOutPtr};
+use crate::sync::Mutex;
+use crate::syscall::errno::{EFAULT, EINVAL, EIO, ENOTTY, EPERM, ERESTARTSYS};
+use crate::task::{kill_pgrp, CurrentTask, SigInfo, SignalFlags};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Get the foreground process group of the terminal.
+const TIOCGPGRP: usize = 0x540f;
+/// Set the foreground process group of the terminal.
+const TIOCSPGRP: usize = 0x5410;
+
+/// Ctrl-C, sends `SIGINT` to the foreground process group.
+const CTRL_C: u8 = 3;
+/// Ctrl-Z, sends `SIGTSTP` to the foreground process group.
+const CTRL_Z: u8 = 26;
 
 ///Standard input
 pub struct Stdin;
 ///Standard output
 pub struct Stdout;
 
+/// The console, which is the controlling terminal of all processes.
+struct Tty {
+    /// Characters received but not read yet.
+    input: VecDeque<u8>,
+    /// The foreground process group, 0 if not set.
+    fg_pgid: usize,
+}
+
+lazy_static! {
+    static ref TTY: Mutex<Tty> = Mutex::new(Tty {
+        input: VecDeque::new(),
+        fg_pgid: 0,
+    });
+}
+
+impl Tty {
+    /// Moves the received characters into the input buffer, returns the
+    /// signals of the control characters among them.
+    fn poll(&mut self) -> Vec<SignalFlags> {
+        let mut signals = Vec::new();
+        while let Some(c) = console_getchar() {
+            match c {
+                CTRL_C => signals.push(SignalFlags::SIGINT),
+                CTRL_Z => signals.push(SignalFlags::SIGTSTP),
+                _ => self.input.push_back(c),
+            }
+        }
+        signals
+    }
+}
+
+/// Sends the signals of control characters to the foreground process group
+/// `fg_pgid`. Called with the terminal unlocked, as sending a signal takes
+/// the locks of the receivers.
+fn send_tty_signals(fg_pgid: usize, signals: Vec<SignalFlags>) {
+    if fg_pgid != 0 {
+        for signal in signals {
+            kill_pgrp(fg_pgid, SigInfo::kernel(signal));
+        }
+    }
+}
+
+/// Polls the console, called on every timer tick so that Ctrl-C and Ctrl-Z
+/// also work while nobody is reading the console.
+pub fn tty_poll() {
+    let mut tty = TTY.lock();
+    let signals = tty.poll();
+    let fg_pgid = tty.fg_pgid;
+    drop(tty);
+    send_tty_signals(fg_pgid, signals);
+}
+
+fn tty_ioctl(cmd: usize, arg: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    match cmd {
+        TIOCGPGRP => {
+            let mut pgid_ptr: UserOutPtr<i32> = arg.into();
+            if !pgid_ptr.check() {
+                return -EFAULT;
+            }
+            pgid_ptr.write(TTY.lock().fg_pgid as i32);
+            0
+        }
+        TIOCSPGRP => {
+            let pgid_ptr: UserInPtr<i32> = arg.into();
+            if !pgid_ptr.check() {
+                return -EFAULT;
+            }
+            let pgid = pgid_ptr.read();
+            if pgid < 0 {
+                return -EINVAL;
+            }
+            // the new foreground group must be in the session of the caller
+            let in_session = crate::task::pgid2procs(pgid as usize)
+                .iter()
+                .any(|p| p.sid() == proc.sid());
+            if !in_session {
+                return -EPERM;
+            }
+            TTY.lock().fg_pgid = pgid as usize;
+            0
+        }
+        _ => -ENOTTY,
+    }
+}
+
 impl File for Stdin {
     fn readable(&self) -> bool {
         true
@@ -15,30 +117,47 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    fn read(&self, buf: &mut [u8]) -> usize {
+    /// Fails with `ERESTARTSYS` when interrupted by a signal, so that the
+    /// read is tried again after e.g. a stopped background job is continued.
+    /// A background job which blocks or ignores `SIGTTIN` fails with `EIO`.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, isize> {
         assert_eq!(buf.len(), 1);
+        let task = CurrentTask::get();
+        let pgid = task.proc().pgid();
         // busy loop
         let ch = loop {
-            match console_getchar() {
-                None => {
-                    CurrentTask::get().yield_now();
-                }
-                // 3 is Crtl-C
-                Some(3) => {
-                    CurrentTask::get().set_singal(SignalFlags::SIGINT);
-                    break 3;
-                }
-                Some(c) => {
-                    break c;
+            let mut tty = TTY.lock();
+            if tty.fg_pgid != 0 && tty.fg_pgid != pgid {
+                // a background job reading the terminal is stopped
+                drop(tty);
+                if task.blocks_or_ignores(SignalFlags::SIGTTIN) {
+                    return Err(-EIO);
                 }
+                kill_pgrp(pgid, SigInfo::kernel(SignalFlags::SIGTTIN));
+                return Err(-ERESTARTSYS);
+            }
+            let signals = tty.poll();
+            let (fg_pgid, c) = (tty.fg_pgid, tty.input.pop_front());
+            drop(tty);
+            send_tty_signals(fg_pgid, signals);
+            if let Some(c) = c {
+                break c;
             }
+            if task.is_interrupted() {
+                // return to user mode to handle the signal, e.g. Ctrl-C
+                return Err(-ERESTARTSYS);
+            }
+            task.yield_now();
         };
         buf[0] = ch;
-        1
+        Ok(1)
     }
-    fn write(&self, _buf: &[u8]) -> usize {
+    fn write(&self, _buf: &[u8]) -> Result<usize, isize> {
         panic!("Cannot write to stdin!");
     }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        tty_ioctl(cmd, arg)
+    }
 }
 
 impl File for Stdout {
@@ -48,11 +167,14 @@ impl File for Stdout {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _buf: &mut [u8]) -> usize {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, isize> {
         panic!("Cannot read from stdout!");
     }
-    fn write(&self, buf: &[u8]) -> usize {
+    fn write(&self, buf: &[u8]) -> Result<usize, isize> {
         print!("{}", core::str::from_utf8(buf).unwrap());
-        buf.len()
+        Ok(buf.len())
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        tty_ioctl(cmd, arg)
     }
 }
@@ -1,10 +1,11 @@
 use alloc::collections::btree_map::{BTreeMap, Entry};
-use core::fmt;
+use alloc::collections::BTreeSet;
+use core::{cmp::Ordering, fmt};
 
-use super::address::{align_down, is_aligned, phys_to_virt, virt_to_phys};
+use super::address::{align_down, align_up, is_aligned, phys_to_virt, virt_to_phys};
 use super::{MemFlags, PageTable, PhysFrame, PAGE_SIZE};
 use crate::arch;
-use crate::config::{MEMORY_END, MMIO_REGIONS, USER_STACK_SIZE, USER_STACK_TOP};
+use crate::config::{MEMORY_END, MMIO_REGIONS, USER_MMAP_TOP, USER_STACK_SIZE, USER_STACK_TOP};
 use crate::mm::{PhysAddr, VirtAddr};
 use crate::sync::LazyInit;
 
@@ -39,6 +40,20 @@ pub struct MapArea {
 pub struct MemorySet {
     pt: PageTable,
     areas: BTreeMap<VirtAddr, MapArea>,
+    // slots of the thread stacks mapped by `map_thread_stack`
+    stack_slots: BTreeSet<usize>,
+    // the heap, from the end of the image to the program break
+    heap_bottom: usize,
+    brk: usize,
+    // the areas mapped by `mmap`, the only ones which `munmap` removes
+    mmap_areas: BTreeSet<VirtAddr>,
+}
+
+/// The bottom and top of the user stack in `slot`. Slot 0 is the stack of
+/// the image, and the others are below it, with a guard page in between.
+fn thread_stack(slot: usize) -> (usize, usize) {
+    let top = USER_STACK_TOP - slot * (USER_STACK_SIZE + PAGE_SIZE);
+    (top - USER_STACK_SIZE, top)
 }
 
 impl MapArea {
@@ -95,12 +110,17 @@ impl MapArea {
     }
 
     pub fn map(&mut self, vaddr: VirtAddr) -> PhysAddr {
+        self.try_map(vaddr).unwrap()
+    }
+
+    /// Like `map`, but returns `None` if the frames run out.
+    pub fn try_map(&mut self, vaddr: VirtAddr) -> Option<PhysAddr> {
         assert!(vaddr.is_aligned());
         match &mut self.mapper {
-            Mapper::Offset(off) => PhysAddr::new(vaddr.as_usize() - *off),
+            Mapper::Offset(off) => Some(PhysAddr::new(vaddr.as_usize() - *off)),
             Mapper::Framed(frames) => match frames.entry(vaddr) {
-                Entry::Occupied(e) => e.get().start_paddr(),
-                Entry::Vacant(e) => e.insert(PhysFrame::alloc_zero().unwrap()).start_paddr(),
+                Entry::Occupied(e) => Some(e.get().start_paddr()),
+                Entry::Vacant(e) => Some(e.insert(PhysFrame::alloc_zero()?).start_paddr()),
             },
         }
     }
@@ -140,6 +160,10 @@ impl MemorySet {
         Self {
             pt: PageTable::new(),
             areas: BTreeMap::new(),
+            stack_slots: BTreeSet::new(),
+            heap_bottom: 0,
+            brk: 0,
+            mmap_areas: BTreeSet::new(),
         }
     }
 
@@ -194,6 +218,7 @@ impl MemorySet {
             }
         }
 
+        let mut image_end = 0;
         for ph in elf.program_iter() {
             if ph.get_type() != Ok(Type::Load) {
                 continue;
@@ -215,7 +240,11 @@ impl MemorySet {
             area.write_data(offset, data);
             self.insert(area);
             crate::arch::flush_icache_all();
+            image_end = image_end.max(area_end.as_usize());
         }
+        // the heap is empty until `sbrk`
+        self.heap_bottom = image_end;
+        self.brk = image_end;
 
         // user stack
         self.insert(MapArea::new_framed(
@@ -229,11 +258,214 @@ impl MemorySet {
         (entry, ustack_top)
     }
 
+    /// Size of the address space which `load_user` would map for the ELF,
+    /// including the user stack.
+    pub fn user_size(elf_data: &[u8]) -> usize {
+        use xmas_elf::program::Type;
+        use xmas_elf::ElfFile;
+
+        let elf = ElfFile::new(elf_data).expect("invalid ELF file");
+        let mut size = USER_STACK_SIZE;
+        for ph in elf.program_iter() {
+            if ph.get_type() != Ok(Type::Load) {
+                continue;
+            }
+            let area_start = VirtAddr::new(ph.virtual_addr() as usize).align_down();
+            let area_end = VirtAddr::new((ph.virtual_addr() + ph.mem_size()) as usize).align_up();
+            size += area_end.as_usize() - area_start.as_usize();
+        }
+        size
+    }
+
+    /// Writes `data` to the user address `vaddr` through the physical frames,
+    /// so the memory set needs not be active.
+    pub fn write_data(&mut self, vaddr: VirtAddr, data: &[u8]) {
+        let (_, area) = self
+            .areas
+            .range_mut(..=vaddr)
+            .next_back()
+            .expect("MemorySet::write_data: address is not mapped");
+        area.write_data(vaddr.as_usize() - area.start.as_usize(), data);
+    }
+
+    /// Unmaps the area starting from `start`, returns false if there is no
+    /// such area.
+    pub fn remove(&mut self, start: VirtAddr) -> bool {
+        if let Some(mut area) = self.areas.remove(&start) {
+            self.pt.unmap_area(&mut area);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Maps a user stack for a new thread in the lowest free slot, returns
+    /// the slot and the top of the stack. The slots belong to the address
+    /// space, so the processes sharing it by `CLONE_VM` never map the same.
+    pub fn map_thread_stack(&mut self) -> (usize, usize) {
+        let slot = (1..).find(|slot| !self.stack_slots.contains(slot)).unwrap();
+        let (bottom, top) = thread_stack(slot);
+        self.insert(MapArea::new_framed(
+            VirtAddr::new(bottom),
+            USER_STACK_SIZE,
+            MemFlags::READ | MemFlags::WRITE | MemFlags::USER,
+        ));
+        self.stack_slots.insert(slot);
+        (slot, top)
+    }
+
+    /// Unmaps the thread stack in `slot` mapped by `map_thread_stack`.
+    pub fn unmap_thread_stack(&mut self, slot: usize) {
+        assert!(self.stack_slots.remove(&slot));
+        self.remove(VirtAddr::new(thread_stack(slot).0));
+    }
+
+    /// Maps the pages of `area` in `[start, end)`, returns false and unmaps
+    /// them again if the frames run out.
+    fn map_pages(pt: &mut PageTable, area: &mut MapArea, start: usize, end: usize) -> bool {
+        for vaddr in (start..end).step_by(PAGE_SIZE) {
+            match area.try_map(VirtAddr::new(vaddr)) {
+                Some(paddr) => pt.map(VirtAddr::new(vaddr), paddr, area.flags),
+                None => {
+                    Self::unmap_pages(pt, area, start, vaddr);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn unmap_pages(pt: &mut PageTable, area: &mut MapArea, start: usize, end: usize) {
+        for vaddr in (start..end).step_by(PAGE_SIZE) {
+            area.unmap(VirtAddr::new(vaddr));
+            pt.unmap(VirtAddr::new(vaddr));
+        }
+    }
+
+    /// The bottom of the heap, where the image ends.
+    pub fn heap_bottom(&self) -> usize {
+        self.heap_bottom
+    }
+
+    /// The program break, where the heap ends.
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+
+    /// Moves the program break to `brk`, mapping or unmapping the pages of
+    /// the heap. Returns false if `brk` is below the bottom of the heap, if
+    /// the heap would reach another area, or if the frames run out.
+    pub fn set_brk(&mut self, brk: usize) -> bool {
+        if brk < self.heap_bottom || brk > USER_MMAP_TOP {
+            return false;
+        }
+        let bottom = VirtAddr::new(self.heap_bottom);
+        let old_top = align_up(self.brk, PAGE_SIZE);
+        let new_top = align_up(brk, PAGE_SIZE);
+        match new_top.cmp(&old_top) {
+            Ordering::Greater => {
+                let mmap_areas = &self.mmap_areas;
+                if self
+                    .areas
+                    .range(bottom..VirtAddr::new(new_top))
+                    .any(|(start, _)| *start != bottom || mmap_areas.contains(start))
+                {
+                    return false;
+                }
+                let area = self.areas.entry(bottom).or_insert_with(|| {
+                    MapArea::new_framed(
+                        bottom,
+                        0,
+                        MemFlags::READ | MemFlags::WRITE | MemFlags::USER,
+                    )
+                });
+                if !Self::map_pages(&mut self.pt, area, old_top, new_top) {
+                    if area.size == 0 {
+                        self.areas.remove(&bottom);
+                    }
+                    return false;
+                }
+                area.size = new_top - self.heap_bottom;
+            }
+            Ordering::Less => {
+                let area = self.areas.get_mut(&bottom).unwrap();
+                Self::unmap_pages(&mut self.pt, area, new_top, old_top);
+                area.size = new_top - self.heap_bottom;
+                if area.size == 0 {
+                    self.areas.remove(&bottom);
+                }
+                arch::flush_tlb_all();
+            }
+            Ordering::Equal => {}
+        }
+        self.brk = brk;
+        true
+    }
+
+    /// Maps `size` bytes of zeroed memory at the page aligned `start`, returns
+    /// false if the range overlaps an area, or if the frames run out.
+    pub fn mmap(&mut self, start: VirtAddr, size: usize, flags: MemFlags) -> bool {
+        let end = start.as_usize() + size;
+        let overlaps = self
+            .areas
+            .range(..VirtAddr::new(end))
+            .next_back()
+            .map_or(false, |(area_start, area)| {
+                area_start.as_usize() + area.size > start.as_usize()
+            });
+        // the heap would grow into it
+        if overlaps || start.as_usize() == self.heap_bottom {
+            return false;
+        }
+        let mut area = MapArea::new_framed(start, size, flags);
+        if !Self::map_pages(&mut self.pt, &mut area, start.as_usize(), end) {
+            return false;
+        }
+        self.areas.insert(start, area);
+        self.mmap_areas.insert(start);
+        true
+    }
+
+    /// Unmaps the area mapped by `mmap` at `start`, returns false if there is
+    /// no such area of `size` bytes.
+    pub fn munmap(&mut self, start: VirtAddr, size: usize) -> bool {
+        if !self.mmap_areas.contains(&start) || self.areas[&start].size != size {
+            return false;
+        }
+        self.mmap_areas.remove(&start);
+        self.remove(start);
+        arch::flush_tlb_all();
+        true
+    }
+
+    /// Translates the user address `vaddr`, returns `None` if it is not mapped.
+    pub fn translate(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        self.pt.query(vaddr).map(|(paddr, _)| paddr)
+    }
+
+    /// Whether all the pages of the user range `[vaddr, vaddr + len)` are
+    /// mapped.
+    pub fn is_mapped(&self, vaddr: VirtAddr, len: usize) -> bool {
+        let start = vaddr.align_down().as_usize();
+        (start..vaddr.as_usize() + len)
+            .step_by(PAGE_SIZE)
+            .all(|page| self.translate(VirtAddr::new(page)).is_some())
+    }
+
+    /// Total size of the mapped areas.
+    pub fn size(&self) -> usize {
+        self.areas.values().map(|area| area.size).sum()
+    }
+
     pub fn clear(&mut self) {
         for area in self.areas.values_mut() {
             self.pt.unmap_area(area);
         }
         self.areas.clear();
+        self.stack_slots.clear();
+        self.heap_bottom = 0;
+        self.brk = 0;
+        self.mmap_areas.clear();
     }
 
     pub fn page_table_root(&self) -> PhysAddr {
@@ -247,6 +479,10 @@ impl Clone for MemorySet {
         for area in self.areas.values() {
             ms.insert(area.clone());
         }
+        ms.stack_slots = self.stack_slots.clone();
+        ms.heap_bottom = self.heap_bottom;
+        ms.brk = self.brk;
+        ms.mmap_areas = self.mmap_areas.clone();
         ms
     }
 }
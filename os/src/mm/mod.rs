@@ -5,7 +5,7 @@ mod memory_set;
 mod page_table;
 mod uaccess;
 
-pub use address::{phys_to_virt, virt_to_phys, PhysAddr, VirtAddr};
+pub use address::{align_up, phys_to_virt, virt_to_phys, PhysAddr, VirtAddr};
 pub use frame_allocator::{frame_alloc, frame_dealloc, PhysFrame};
 pub use memory_set::{remap_test, MapArea, MemorySet};
 pub use page_table::{PageTable, PageTableEntry};
@@ -84,6 +84,19 @@ impl<T, P: Policy> UserPtr<T, P> {
         uaccess_ok(vaddr, 1) && (vaddr % align_of::<T>() == 0)
     }
 
+    /// Like `check`, but for the `len` values from the pointer on.
+    pub fn check_len(&self, len: usize) -> bool {
+        let vaddr = self.ptr as usize;
+        match len.checked_mul(size_of::<T>()) {
+            Some(size) => {
+                size <= USER_ASPACE_RANGE.end
+                    && uaccess_ok(vaddr, size)
+                    && (vaddr % align_of::<T>() == 0)
+            }
+            None => false,
+        }
+    }
+
     pub fn as_ptr(&self) -> *const T {
         self.ptr
     }
@@ -95,9 +108,10 @@ impl<T, P: Policy> UserPtr<T, P> {
     pub fn as_slice(&self, len: usize) -> Result<&'static [T], &'static str> {
         if len == 0 {
             Ok(&[])
-        } else {
-            assert!(uaccess_ok(self.ptr as usize, len));
+        } else if self.check_len(len) {
             Ok(unsafe { core::slice::from_raw_parts(self.ptr, len) })
+        } else {
+            Err("Invalid user range")
         }
     }
 
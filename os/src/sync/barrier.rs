@@ -0,0 +1,45 @@
+use super::Mutex;
+use crate::task::{CurrentTask, Task};
+use alloc::{collections::VecDeque, sync::Arc};
+
+pub struct Barrier {
+    pub inner: Mutex<BarrierInner>,
+}
+
+pub struct BarrierInner {
+    pub count: usize,
+    pub arrived: usize,
+    pub wait_queue: VecDeque<Arc<Task>>,
+}
+
+impl Barrier {
+    pub fn new(count: usize) -> Self {
+        Self {
+            inner: Mutex::new(BarrierInner {
+                count,
+                arrived: 0,
+                wait_queue: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Blocks until `count` tasks have arrived, then the barrier can be used
+    /// again. Returns true for the last task arrived.
+    pub fn wait(&self) -> bool {
+        let mut inner = self.inner.lock();
+        inner.arrived += 1;
+        if inner.arrived == inner.count {
+            inner.arrived = 0;
+            while let Some(task) = inner.wait_queue.pop_front() {
+                task.wake();
+            }
+            true
+        } else {
+            let task = CurrentTask::get();
+            inner.wait_queue.push_back(task.clone());
+            drop(inner);
+            task.block_and_yield();
+            false
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use crate::sync::{Mutex, UserMutex};
+use crate::sync::{wait_error, Mutex, UserMutex};
 use crate::task::{CurrentTask, Task};
+use crate::timer::{add_timer, remove_timer};
 use alloc::{collections::VecDeque, sync::Arc};
 
 pub struct Condvar {
@@ -22,17 +23,51 @@ impl Condvar {
     pub fn signal(&self) {
         let mut inner = self.inner.lock();
         if let Some(task) = inner.wait_queue.pop_front() {
-            task.resume();
+            task.wake();
         }
     }
 
-    pub fn wait(&self, mutex: Arc<dyn UserMutex>) {
-        mutex.unlock();
+    pub fn broadcast(&self) {
+        let mut inner = self.inner.lock();
+        while let Some(task) = inner.wait_queue.pop_front() {
+            task.wake();
+        }
+    }
+
+    /// Gives up waiting at `deadline_ms` if given with `ETIMEDOUT`, or when a
+    /// signal interrupts the wait, see `wait_error`. The mutex, unlocked by
+    /// the caller, is locked again either way.
+    pub fn wait_timeout(
+        &self,
+        mutex: Arc<dyn UserMutex>,
+        deadline_ms: Option<usize>,
+    ) -> Result<(), isize> {
         let mut inner = self.inner.lock();
         let curr_task = CurrentTask::get();
         inner.wait_queue.push_back(curr_task.clone());
         drop(inner);
-        curr_task.block_and_yield();
+        if let Some(deadline_ms) = deadline_ms {
+            add_timer(deadline_ms, curr_task.clone());
+        }
+        curr_task.block_interruptible();
+        if deadline_ms.is_some() {
+            remove_timer(curr_task.clone());
+        }
+        // still queued if woken by the timer or a signal
+        let mut inner = self.inner.lock();
+        let ret = match inner
+            .wait_queue
+            .iter()
+            .position(|t| Arc::ptr_eq(t, &curr_task))
+        {
+            Some(idx) => {
+                inner.wait_queue.remove(idx);
+                Err(wait_error(&curr_task, deadline_ms))
+            }
+            None => Ok(()),
+        };
+        drop(inner);
         mutex.lock();
+        ret
     }
 }
@@ -0,0 +1,160 @@
+//! Deadlock detection by the banker's algorithm, over the mutexes and
+//! semaphores of a process.
+
+use super::{Semaphore, UserMutex};
+use crate::task::{HandleTable, Object};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+
+/// A mutex or a semaphore, by the address of the object rather than by a
+/// handle, so that all the handles of an object are the same resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    Mutex(usize),
+    Semaphore(usize),
+}
+
+impl Resource {
+    pub fn mutex(mutex: &Arc<dyn UserMutex>) -> Self {
+        Self::Mutex(Arc::as_ptr(mutex) as *const () as usize)
+    }
+
+    pub fn semaphore(sem: &Arc<Semaphore>) -> Self {
+        Self::Semaphore(Arc::as_ptr(sem) as usize)
+    }
+
+    /// The resource of `object`, if it is a mutex or a semaphore.
+    pub fn of(object: &Object) -> Option<Self> {
+        match object {
+            Object::Mutex(mutex) => Some(Self::mutex(mutex)),
+            Object::Semaphore(sem) => Some(Self::semaphore(sem)),
+            _ => None,
+        }
+    }
+}
+
+/// Available units of each resource, and the units which each task holds
+/// or waits for. Kept up to date even if the detection is disabled, so it
+/// can be enabled at any time.
+#[derive(Default)]
+pub struct DeadlockDetector {
+    enabled: bool,
+    available: BTreeMap<Resource, usize>,
+    // (tid, resource) -> units
+    allocation: BTreeMap<(usize, Resource), usize>,
+    need: BTreeMap<(usize, Resource), usize>,
+}
+
+impl DeadlockDetector {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Starts tracking `res` with `count` units available, unless it is
+    /// already tracked through another handle.
+    pub fn add_resource(&mut self, res: Resource, count: usize) {
+        self.available.entry(res).or_insert(count);
+    }
+
+    /// Forgets `res`, whose last handle is closed.
+    pub fn remove_resource(&mut self, res: Resource) {
+        self.available.remove(&res);
+        self.allocation.retain(|(_, r), _| *r != res);
+        self.need.retain(|(_, r), _| *r != res);
+    }
+
+    /// The detector of a new process with `handle_table`, whose tasks hold
+    /// nothing yet. The units held by the tasks of other processes are not
+    /// available.
+    pub fn with_handles(handle_table: &HandleTable) -> Self {
+        let mut detector = Self::default();
+        for object in handle_table.objects() {
+            let count = match object {
+                Object::Mutex(mutex) => !mutex.is_locked() as usize,
+                Object::Semaphore(sem) => sem.inner.lock().count.max(0) as usize,
+                _ => continue,
+            };
+            detector.add_resource(Resource::of(object).unwrap(), count);
+        }
+        detector
+    }
+
+    /// Forgets what task `tid` holds and waits for, as it exits and its tid
+    /// may be reused. The units which it holds stay unavailable, as they are
+    /// not given back.
+    pub fn remove_task(&mut self, tid: usize) {
+        self.allocation.retain(|(t, _), _| *t != tid);
+        self.need.retain(|(t, _), _| *t != tid);
+    }
+
+    /// Records that task `tid` waits for a unit of `res`. Returns false and
+    /// records nothing if the detection is enabled and the state would be
+    /// unsafe.
+    pub fn request(&mut self, tid: usize, res: Resource) -> bool {
+        *self.need.entry((tid, res)).or_default() += 1;
+        if self.enabled && !self.is_safe() {
+            Self::take(&mut self.need, (tid, res));
+            return false;
+        }
+        true
+    }
+
+    /// Records that task `tid` no longer waits for `res`, as it timed out.
+    pub fn cancel(&mut self, tid: usize, res: Resource) {
+        Self::take(&mut self.need, (tid, res));
+    }
+
+    /// Records that task `tid` got a unit of `res`.
+    pub fn acquire(&mut self, tid: usize, res: Resource) {
+        Self::take(&mut self.need, (tid, res));
+        *self.allocation.entry((tid, res)).or_default() += 1;
+        if let Some(available) = self.available.get_mut(&res) {
+            *available = available.saturating_sub(1);
+        }
+    }
+
+    /// Records that task `tid` gave back a unit of `res`. A semaphore may be
+    /// released by a task which does not hold it.
+    pub fn release(&mut self, tid: usize, res: Resource) {
+        Self::take(&mut self.allocation, (tid, res));
+        *self.available.entry(res).or_default() += 1;
+    }
+
+    fn take(units: &mut BTreeMap<(usize, Resource), usize>, key: (usize, Resource)) {
+        if let Some(n) = units.get_mut(&key) {
+            *n -= 1;
+            if *n == 0 {
+                units.remove(&key);
+            }
+        }
+    }
+
+    /// Whether all the tasks can finish in some order, each one getting what
+    /// it needs from the available units and those given back before it.
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut unfinished: BTreeSet<usize> = self
+            .need
+            .keys()
+            .chain(self.allocation.keys())
+            .map(|(tid, _)| *tid)
+            .collect();
+        loop {
+            let can_finish = unfinished.iter().copied().find(|tid| {
+                self.need
+                    .iter()
+                    .filter(|((t, _), _)| t == tid)
+                    .all(|((_, res), n)| work.get(res).copied().unwrap_or(0) >= *n)
+            });
+            match can_finish {
+                Some(tid) => {
+                    for ((_, res), n) in self.allocation.iter().filter(|((t, _), _)| *t == tid) {
+                        *work.entry(*res).or_default() += n;
+                    }
+                    unfinished.remove(&tid);
+                }
+                None => break unfinished.is_empty(),
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Futex wait queues, keyed by the physical address of the futex word, so
+//! tasks which share the memory find the same queue.
+
+use super::{wait_error, Mutex};
+use crate::mm::PhysAddr;
+use crate::task::{CurrentTask, Task, TaskState};
+use crate::timer::{add_timer, remove_timer};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+/// A task waiting on a futex, `woken` is set by `futex_wake`, so that the
+/// task tells it apart from a timeout or a signal.
+struct Waiter {
+    task: Arc<Task>,
+    woken: AtomicBool,
+}
+
+lazy_static! {
+    static ref FUTEX_QUEUES: Mutex<BTreeMap<usize, VecDeque<Arc<Waiter>>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Blocks the current task on the futex `key` until it is woken, or until
+/// `deadline_ms` if given. Fails with `ETIMEDOUT` if it timed out, and with
+/// `EINTR` or `ERESTARTSYS` if interrupted by a signal, see `wait_error`.
+///
+/// The caller checks the futex word with IRQs disabled, so a wakeup can not
+/// slip in between the check and the wait.
+pub fn futex_wait(key: PhysAddr, deadline_ms: Option<usize>) -> Result<(), isize> {
+    let curr_task = CurrentTask::get();
+    let waiter = Arc::new(Waiter {
+        task: curr_task.clone(),
+        woken: AtomicBool::new(false),
+    });
+    FUTEX_QUEUES
+        .lock()
+        .entry(key.as_usize())
+        .or_default()
+        .push_back(waiter.clone());
+    if let Some(deadline_ms) = deadline_ms {
+        add_timer(deadline_ms, curr_task.clone());
+    }
+    curr_task.block_interruptible();
+    if deadline_ms.is_some() {
+        remove_timer(curr_task.clone());
+    }
+    if waiter.woken.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    // still queued, maybe requeued to another futex, if woken by the timer
+    // or by a signal
+    FUTEX_QUEUES.lock().retain(|_, queue| {
+        queue.retain(|w| !Arc::ptr_eq(w, &waiter));
+        !queue.is_empty()
+    });
+    Err(wait_error(&curr_task, deadline_ms))
+}
+
+/// Wakes at most `count` tasks waiting on the futex `key`, returns the
+/// number of woken tasks.
+pub fn futex_wake(key: PhysAddr, count: usize) -> usize {
+    let mut queues = FUTEX_QUEUES.lock();
+    let queue = match queues.get_mut(&key.as_usize()) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+    let mut woken = 0;
+    while woken < count {
+        match queue.pop_front() {
+            // not blocking if already woken by the timer or by a signal
+            Some(waiter) if waiter.task.state() == TaskState::Blocking => {
+                waiter.woken.store(true, Ordering::SeqCst);
+                waiter.task.resume();
+                woken += 1;
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    if queue.is_empty() {
+        queues.remove(&key.as_usize());
+    }
+    woken
+}
+
+/// Wakes at most `count` tasks waiting on the futex `key`, and moves at most
+/// `requeue_count` of the others to wait on `key2`. Returns the number of
+/// woken and moved tasks.
+pub fn futex_requeue(key: PhysAddr, count: usize, key2: PhysAddr, requeue_count: usize) -> usize {
+    let woken = futex_wake(key, count);
+    if key == key2 {
+        return woken;
+    }
+    let mut queues = FUTEX_QUEUES.lock();
+    let mut moved = VecDeque::new();
+    if let Some(queue) = queues.get_mut(&key.as_usize()) {
+        let n = requeue_count.min(queue.len());
+        moved.extend(queue.drain(..n));
+        if queue.is_empty() {
+            queues.remove(&key.as_usize());
+        }
+    }
+    let n = moved.len();
+    if n > 0 {
+        queues
+            .entry(key2.as_usize())
+            .or_default()
+            .append(&mut moved);
+    }
+    woken + n
+}
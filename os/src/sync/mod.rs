@@ -1,13 +1,43 @@
+mod barrier;
 mod condvar;
+mod deadlock;
+mod futex;
 mod lazy_init;
+mod mqueue;
 mod mutex;
+mod named;
+mod rwlock;
 mod semaphore;
 mod spin;
 mod user_mutex;
+mod wait_queue;
 
+pub use barrier::Barrier;
 pub use condvar::Condvar;
+pub use deadlock::{DeadlockDetector, Resource};
+pub use futex::{futex_requeue, futex_wait, futex_wake};
 pub use lazy_init::LazyInit;
+pub use mqueue::MessageQueue;
 pub use mutex::Mutex;
+pub use named::{Namespace, MESSAGE_QUEUES, NAMED_SEMAPHORES, NAME_MAX};
+pub use rwlock::RwLock;
 pub use semaphore::Semaphore;
 pub use spin::SpinNoIrqLock;
-pub use user_mutex::{MutexBlocking, MutexSpin, UserMutex};
+pub use user_mutex::{update_priority, MutexBlocking, MutexSpin, PiLinks, UserMutex};
+pub use wait_queue::WaitQueue;
+
+use crate::syscall::errno::{EINTR, ERESTARTSYS, ETIMEDOUT};
+use crate::task::Task;
+
+/// Error of a wait given up by `task` before it is woken: `ETIMEDOUT` unless
+/// interrupted by a signal. An interrupted wait with a deadline fails with
+/// `EINTR` rather than being restarted, which would start the timeout again.
+pub fn wait_error(task: &Task, deadline_ms: Option<usize>) -> isize {
+    if !task.is_interrupted() {
+        -ETIMEDOUT
+    } else if deadline_ms.is_some() {
+        -EINTR
+    } else {
+        -ERESTARTSYS
+    }
+}
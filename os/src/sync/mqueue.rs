@@ -0,0 +1,89 @@
+//! POSIX-style message queues, where the messages of higher priorities are
+//! received first, and those of the same priority in the order sent.
+
+use super::{Mutex, WaitQueue};
+use crate::syscall::errno::{EAGAIN, EMSGSIZE};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+pub struct MessageQueue {
+    pub max_msgs: usize,
+    pub msg_size: usize,
+    inner: Mutex<MessageQueueInner>,
+    not_empty: WaitQueue,
+    not_full: WaitQueue,
+}
+
+struct MessageQueueInner {
+    // (priority, sequence number) -> message
+    messages: BTreeMap<(Reverse<u32>, u64), Vec<u8>>,
+    next_seq: u64,
+}
+
+impl MessageQueue {
+    pub fn new(max_msgs: usize, msg_size: usize) -> Self {
+        Self {
+            max_msgs,
+            msg_size,
+            inner: Mutex::new(MessageQueueInner {
+                messages: BTreeMap::new(),
+                next_seq: 0,
+            }),
+            not_empty: WaitQueue::new(),
+            not_full: WaitQueue::new(),
+        }
+    }
+
+    /// Blocks while the queue is full, or fails with `EAGAIN` if `nonblock`.
+    /// Fails with `EMSGSIZE` if the message is longer than `msg_size`, and
+    /// with `ERESTARTSYS` if interrupted by a signal.
+    pub fn send(&self, msg: Vec<u8>, prio: u32, nonblock: bool) -> Result<(), isize> {
+        if msg.len() > self.msg_size {
+            return Err(-EMSGSIZE);
+        }
+        let mut msg = Some(msg);
+        let mut try_send = || {
+            let mut inner = self.inner.lock();
+            if inner.messages.len() >= self.max_msgs {
+                return false;
+            }
+            let seq = inner.next_seq;
+            inner.next_seq += 1;
+            inner
+                .messages
+                .insert((Reverse(prio), seq), msg.take().unwrap());
+            true
+        };
+        if nonblock {
+            if !try_send() {
+                return Err(-EAGAIN);
+            }
+        } else {
+            self.not_full.wait_until_interruptible(try_send)?;
+        }
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Takes the first message with its priority, blocks while the queue is
+    /// empty, or fails with `EAGAIN` if `nonblock`. Fails with `ERESTARTSYS`
+    /// if interrupted by a signal.
+    pub fn receive(&self, nonblock: bool) -> Result<(Vec<u8>, u32), isize> {
+        let mut received = None;
+        let mut try_receive = || {
+            let first = self.inner.lock().messages.pop_first();
+            received = first.map(|((Reverse(prio), _), msg)| (msg, prio));
+            received.is_some()
+        };
+        if nonblock {
+            if !try_receive() {
+                return Err(-EAGAIN);
+            }
+        } else {
+            self.not_empty.wait_until_interruptible(try_receive)?;
+        }
+        self.not_full.notify_one();
+        Ok(received.unwrap())
+    }
+}
@@ -0,0 +1,70 @@
+//! Global namespaces of the named sync objects, shared by all processes.
+//! An object is kept alive by the handles to it, so it outlives its name
+//! once unlinked.
+
+use super::{MessageQueue, Mutex, Semaphore};
+use crate::fs::OpenFlags;
+use crate::syscall::errno::{EEXIST, EINVAL, ENOENT};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Longest name, without the leading `/`.
+pub const NAME_MAX: usize = 255;
+
+pub struct Namespace<T> {
+    objects: Mutex<BTreeMap<String, Arc<T>>>,
+}
+
+impl<T> Namespace<T> {
+    fn new() -> Self {
+        Self {
+            objects: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Opens the object `name`, or creates it by `create` with
+    /// `OpenFlags::CREATE`. Fails with `ENOENT` if there is no such object
+    /// to open, and with `EEXIST` if there is one with `OpenFlags::EXCL`.
+    pub fn open(
+        &self,
+        name: &str,
+        flags: OpenFlags,
+        create: impl FnOnce() -> Result<T, isize>,
+    ) -> Result<Arc<T>, isize> {
+        check_name(name)?;
+        let mut objects = self.objects.lock();
+        if let Some(object) = objects.get(name) {
+            if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) {
+                return Err(-EEXIST);
+            }
+            return Ok(object.clone());
+        }
+        if !flags.contains(OpenFlags::CREATE) {
+            return Err(-ENOENT);
+        }
+        let object = Arc::new(create()?);
+        objects.insert(String::from(name), object.clone());
+        Ok(object)
+    }
+
+    /// Removes the name, fails with `ENOENT` if there is no such object.
+    pub fn unlink(&self, name: &str) -> Result<(), isize> {
+        check_name(name)?;
+        self.objects.lock().remove(name).map(|_| ()).ok_or(-ENOENT)
+    }
+}
+
+/// A name is `/` followed by up to `NAME_MAX` characters other than `/`.
+fn check_name(name: &str) -> Result<(), isize> {
+    match name.strip_prefix('/') {
+        Some(rest) if !rest.is_empty() && rest.len() <= NAME_MAX && !rest.contains('/') => Ok(()),
+        _ => Err(-EINVAL),
+    }
+}
+
+lazy_static! {
+    pub static ref NAMED_SEMAPHORES: Namespace<Semaphore> = Namespace::new();
+    pub static ref MESSAGE_QUEUES: Namespace<MessageQueue> = Namespace::new();
+}
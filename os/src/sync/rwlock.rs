@@ -0,0 +1,87 @@
+use super::Mutex;
+use crate::syscall::errno::{EINVAL, EPERM};
+use crate::task::{CurrentTask, Task};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// A reader-writer lock which prefers writers: a new reader waits if a
+/// writer is waiting, so the writers are not starved by the readers.
+pub struct RwLock {
+    pub inner: Mutex<RwLockInner>,
+}
+
+pub struct RwLockInner {
+    pub readers: usize,
+    // the task which holds the lock for writing
+    pub writer: Option<Arc<Task>>,
+    pub read_queue: VecDeque<Arc<Task>>,
+    pub write_queue: VecDeque<Arc<Task>>,
+}
+
+impl RwLock {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(RwLockInner {
+                readers: 0,
+                writer: None,
+                read_queue: VecDeque::new(),
+                write_queue: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn read(&self) {
+        let mut inner = self.inner.lock();
+        if inner.writer.is_some() || !inner.write_queue.is_empty() {
+            let task = CurrentTask::get();
+            inner.read_queue.push_back(task.clone());
+            drop(inner);
+            // the lock is handed over by `unlock`
+            task.block_and_yield();
+        } else {
+            inner.readers += 1;
+        }
+    }
+
+    pub fn write(&self) {
+        let mut inner = self.inner.lock();
+        let task = CurrentTask::get();
+        if inner.writer.is_some() || inner.readers > 0 {
+            inner.write_queue.push_back(task.clone());
+            drop(inner);
+            // the lock is handed over by `unlock`
+            task.block_and_yield();
+        } else {
+            inner.writer = Some(task.clone());
+        }
+    }
+
+    /// Releases the lock held for writing if there is a writer, or the one
+    /// held for reading otherwise. Fails with `EINVAL` if the lock is not
+    /// held, and with `EPERM` if another task holds it for writing.
+    pub fn unlock(&self) -> Result<(), isize> {
+        let mut inner = self.inner.lock();
+        if let Some(writer) = &inner.writer {
+            if !Arc::ptr_eq(writer, &CurrentTask::get()) {
+                return Err(-EPERM);
+            }
+            inner.writer = None;
+        } else if inner.readers > 0 {
+            inner.readers -= 1;
+            if inner.readers > 0 {
+                return Ok(());
+            }
+        } else {
+            return Err(-EINVAL);
+        }
+        if let Some(task) = inner.write_queue.pop_front() {
+            inner.writer = Some(task.clone());
+            task.wake();
+        } else {
+            while let Some(task) = inner.read_queue.pop_front() {
+                inner.readers += 1;
+                task.wake();
+            }
+        }
+        Ok(())
+    }
+}
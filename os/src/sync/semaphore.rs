@@ -1,5 +1,6 @@
-use super::Mutex;
+use super::{wait_error, Mutex};
 use crate::task::{CurrentTask, Task};
+use crate::timer::{add_timer, remove_timer};
 use alloc::{collections::VecDeque, sync::Arc};
 
 pub struct Semaphore {
@@ -26,19 +27,35 @@ impl Semaphore {
         inner.count += 1;
         if inner.count <= 0 {
             if let Some(task) = inner.wait_queue.pop_front() {
-                task.resume();
+                task.wake();
             }
         }
     }
 
-    pub fn down(&self) {
+    /// Gives up at `deadline_ms` if given with `ETIMEDOUT`, or when a signal
+    /// interrupts the wait, see `wait_error`.
+    pub fn down_timeout(&self, deadline_ms: Option<usize>) -> Result<(), isize> {
         let mut inner = self.inner.lock();
         inner.count -= 1;
         if inner.count < 0 {
             let task = CurrentTask::get();
             inner.wait_queue.push_back(task.clone());
             drop(inner);
-            task.block_and_yield();
+            if let Some(deadline_ms) = deadline_ms {
+                add_timer(deadline_ms, task.clone());
+            }
+            task.block_interruptible();
+            if deadline_ms.is_some() {
+                remove_timer(task.clone());
+            }
+            // still queued if woken by the timer or a signal
+            let mut inner = self.inner.lock();
+            if let Some(idx) = inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+                inner.wait_queue.remove(idx);
+                inner.count += 1;
+                return Err(wait_error(&task, deadline_ms));
+            }
         }
+        Ok(())
     }
 }
@@ -1,85 +1,265 @@
-use super::Mutex;
+use super::{wait_error, Mutex};
+use crate::syscall::errno::{EINVAL, EPERM};
 use crate::task::{CurrentTask, Task};
-use alloc::{collections::VecDeque, sync::Arc};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 pub trait UserMutex: Sync + Send {
-    fn lock(&self);
-    fn unlock(&self);
+    /// Waits through the signals, e.g. to lock the mutex again after a
+    /// condvar wait.
+    fn lock(&self) {
+        self.acquire(None, false).unwrap();
+    }
+
+    /// Gives up at `deadline_ms` if given with `ETIMEDOUT`, or when a signal
+    /// interrupts the wait, see `wait_error`.
+    fn lock_timeout(&self, deadline_ms: Option<usize>) -> Result<(), isize> {
+        self.acquire(deadline_ms, true)
+    }
+
+    /// Locks the mutex, only interrupted by signals if `interruptible`.
+    fn acquire(&self, deadline_ms: Option<usize>, interruptible: bool) -> Result<(), isize>;
+
+    /// Fails with `EINVAL` if the mutex is not locked, and with `EPERM` if it
+    /// is locked by another task.
+    fn unlock(&self) -> Result<(), isize>;
+
+    fn is_locked(&self) -> bool;
 }
 
 pub struct MutexSpin {
-    locked: Mutex<bool>,
+    // the task which holds the lock
+    owner: Mutex<Option<Arc<Task>>>,
 }
 
 impl MutexSpin {
     pub fn new() -> Self {
         Self {
-            locked: Mutex::new(false),
+            owner: Mutex::new(None),
         }
     }
 }
 
 impl UserMutex for MutexSpin {
-    fn lock(&self) {
+    fn acquire(&self, deadline_ms: Option<usize>, interruptible: bool) -> Result<(), isize> {
+        let curr_task = CurrentTask::get();
         loop {
-            let mut locked = self.locked.lock();
-            if *locked {
-                drop(locked);
-                CurrentTask::get().yield_now();
+            let mut owner = self.owner.lock();
+            if owner.is_some() {
+                drop(owner);
+                if deadline_ms.map_or(false, |d| get_time_ms() as usize >= d)
+                    || (interruptible && curr_task.is_interrupted())
+                {
+                    return Err(wait_error(&curr_task, deadline_ms));
+                }
+                curr_task.yield_now();
                 continue;
             } else {
-                *locked = true;
-                return;
+                *owner = Some(curr_task.clone());
+                return Ok(());
             }
         }
     }
 
-    fn unlock(&self) {
-        let mut locked = self.locked.lock();
-        *locked = false;
+    fn unlock(&self) -> Result<(), isize> {
+        let mut owner = self.owner.lock();
+        match &*owner {
+            None => return Err(-EINVAL),
+            Some(owner) if !Arc::ptr_eq(owner, &CurrentTask::get()) => return Err(-EPERM),
+            Some(_) => {}
+        }
+        *owner = None;
+        Ok(())
+    }
+
+    fn is_locked(&self) -> bool {
+        self.owner.lock().is_some()
     }
 }
 
 pub struct MutexBlocking {
-    inner: Mutex<MutexBlockingInner>,
+    inner: Arc<Mutex<MutexBlockingInner>>,
 }
 
 pub struct MutexBlockingInner {
     locked: bool,
+    // the task which holds the lock
+    owner: Option<Arc<Task>>,
+    // whether the owner inherits the priorities of the waiters
+    pi: bool,
     wait_queue: VecDeque<Arc<Task>>,
 }
 
+/// Priority-inheritance mutexes which a task holds or is blocked on.
+#[derive(Default)]
+pub struct PiLinks {
+    held: Vec<Weak<Mutex<MutexBlockingInner>>>,
+    blocked_on: Option<Weak<Mutex<MutexBlockingInner>>>,
+}
+
 impl MutexBlocking {
-    pub fn new() -> Self {
+    /// With `pi`, the owner runs at least at the priority of the waiters,
+    /// also through the chain of PI mutexes which the owner waits for.
+    pub fn new(pi: bool) -> Self {
         Self {
-            inner: Mutex::new(MutexBlockingInner {
+            inner: Arc::new(Mutex::new(MutexBlockingInner {
                 locked: false,
+                owner: None,
+                pi,
                 wait_queue: VecDeque::new(),
-            }),
+            })),
+        }
+    }
+}
+
+/// Raises the priority of `owner` to `prio`, and so on for the owners of the
+/// PI mutexes which it waits for.
+fn inherit_priority(mut owner: Option<Arc<Task>>, prio: usize) {
+    while let Some(task) = owner {
+        if task.priority() >= prio {
+            break;
         }
+        task.set_priority(prio);
+        let blocked_on = task.pi_links.lock().blocked_on.clone();
+        owner = blocked_on
+            .and_then(|m| m.upgrade())
+            .and_then(|m| m.lock().owner.clone());
+    }
+}
+
+/// Recomputes the effective priority of `task` from its base priority and the
+/// waiters of the PI mutexes which it holds.
+pub fn update_priority(task: &Arc<Task>) {
+    let held: Vec<_> = task
+        .pi_links
+        .lock()
+        .held
+        .iter()
+        .filter_map(|m| m.upgrade())
+        .collect();
+    let mut prio = task.base_priority();
+    for m in held {
+        let inner = m.lock();
+        let waiter_prio = inner.wait_queue.iter().map(|t| t.priority()).max();
+        prio = prio.max(waiter_prio.unwrap_or(0));
+    }
+    if prio != task.priority() {
+        task.set_priority(prio);
     }
 }
 
 impl UserMutex for MutexBlocking {
-    fn lock(&self) {
+    fn acquire(&self, deadline_ms: Option<usize>, interruptible: bool) -> Result<(), isize> {
         let mut mutex_inner = self.inner.lock();
+        let curr_task = CurrentTask::get();
         if mutex_inner.locked {
-            let curr_task = CurrentTask::get();
             mutex_inner.wait_queue.push_back(curr_task.clone());
+            let owner = if mutex_inner.pi {
+                curr_task.pi_links.lock().blocked_on = Some(Arc::downgrade(&self.inner));
+                mutex_inner.owner.clone()
+            } else {
+                None
+            };
             drop(mutex_inner);
-            curr_task.block_and_yield();
+            inherit_priority(owner, curr_task.priority());
+            if let Some(deadline_ms) = deadline_ms {
+                add_timer(deadline_ms, curr_task.clone());
+            }
+            // the lock is handed over by `unlock`
+            if interruptible {
+                curr_task.block_interruptible();
+            } else {
+                curr_task.block_and_yield();
+            }
+            if deadline_ms.is_some() {
+                remove_timer(curr_task.clone());
+            }
+            // still queued if woken by the timer or a signal
+            let mut mutex_inner = self.inner.lock();
+            let queue = &mut mutex_inner.wait_queue;
+            if let Some(idx) = queue.iter().position(|t| Arc::ptr_eq(t, &curr_task)) {
+                queue.remove(idx);
+                let owner = mutex_inner.owner.clone();
+                let pi = mutex_inner.pi;
+                drop(mutex_inner);
+                if pi {
+                    curr_task.pi_links.lock().blocked_on = None;
+                    // the owner no longer inherits from this task
+                    if let Some(owner) = owner {
+                        update_priority(&owner);
+                    }
+                }
+                return Err(wait_error(&curr_task, deadline_ms));
+            }
+            Ok(())
         } else {
             mutex_inner.locked = true;
+            mutex_inner.owner = Some(curr_task.clone());
+            if mutex_inner.pi {
+                curr_task
+                    .pi_links
+                    .lock()
+                    .held
+                    .push(Arc::downgrade(&self.inner));
+            }
+            Ok(())
         }
     }
 
-    fn unlock(&self) {
+    fn unlock(&self) -> Result<(), isize> {
         let mut mutex_inner = self.inner.lock();
-        assert!(mutex_inner.locked);
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            waking_task.resume();
+        if !mutex_inner.locked {
+            return Err(-EINVAL);
+        }
+        let curr_task = CurrentTask::get();
+        if !matches!(&mutex_inner.owner, Some(owner) if Arc::ptr_eq(owner, &curr_task)) {
+            return Err(-EPERM);
+        }
+        let pi = mutex_inner.pi;
+        if pi {
+            curr_task
+                .pi_links
+                .lock()
+                .held
+                .retain(|m| m.as_ptr() != Arc::as_ptr(&self.inner));
+        }
+        // the waiter with the highest priority goes first with PI
+        let next = if pi {
+            let mut idx = 0;
+            for (i, t) in mutex_inner.wait_queue.iter().enumerate() {
+                if t.priority() > mutex_inner.wait_queue[idx].priority() {
+                    idx = i;
+                }
+            }
+            mutex_inner.wait_queue.remove(idx)
+        } else {
+            mutex_inner.wait_queue.pop_front()
+        };
+        if let Some(waking_task) = next {
+            mutex_inner.owner = Some(waking_task.clone());
+            drop(mutex_inner);
+            if pi {
+                let mut links = waking_task.pi_links.lock();
+                links.blocked_on = None;
+                links.held.push(Arc::downgrade(&self.inner));
+                drop(links);
+                update_priority(&waking_task);
+            }
+            waking_task.wake();
         } else {
             mutex_inner.locked = false;
+            mutex_inner.owner = None;
+            drop(mutex_inner);
+        }
+        if pi {
+            update_priority(&curr_task);
         }
+        Ok(())
+    }
+
+    fn is_locked(&self) -> bool {
+        self.inner.lock().locked
     }
 }
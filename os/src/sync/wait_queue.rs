@@ -0,0 +1,83 @@
+use super::Mutex;
+use crate::arch;
+use crate::syscall::errno::ERESTARTSYS;
+use crate::task::{CurrentTask, Task};
+use alloc::{collections::VecDeque, sync::Arc};
+
+pub struct WaitQueue {
+    queue: Mutex<VecDeque<Arc<Task>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Blocks the current task until `condition` returns true.
+    ///
+    /// IRQs stay disabled from the check of `condition` until the task is switched
+    /// out, so a notification can not slip in between and get lost.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        let curr_task = CurrentTask::get();
+        loop {
+            let irq_enabled_before = !arch::irqs_disabled();
+            arch::disable_irqs();
+            let ready = condition();
+            if !ready {
+                self.queue.lock().push_back(curr_task.clone());
+                curr_task.block_and_yield();
+            }
+            if irq_enabled_before {
+                arch::enable_irqs();
+            }
+            if ready {
+                break;
+            }
+        }
+    }
+
+    /// Like `wait_until`, but gives up with `ERESTARTSYS` if a signal
+    /// interrupts the wait, unless `condition` is met at the same time.
+    pub fn wait_until_interruptible(
+        &self,
+        mut condition: impl FnMut() -> bool,
+    ) -> Result<(), isize> {
+        let curr_task = CurrentTask::get();
+        let irq_enabled_before = !arch::irqs_disabled();
+        arch::disable_irqs();
+        let ret = loop {
+            if condition() {
+                break Ok(());
+            }
+            if curr_task.is_interrupted() {
+                break Err(-ERESTARTSYS);
+            }
+            self.queue.lock().push_back(curr_task.clone());
+            curr_task.block_interruptible();
+            // still queued if woken by a signal
+            self.queue.lock().retain(|t| !Arc::ptr_eq(t, &curr_task));
+        };
+        if irq_enabled_before {
+            arch::enable_irqs();
+        }
+        ret
+    }
+
+    pub fn notify_one(&self) -> bool {
+        let task = self.queue.lock().pop_front();
+        if let Some(task) = task {
+            // may have been woken by a signal, then it checks the condition
+            // before giving up
+            task.wake();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn notify_all(&self) {
+        while self.notify_one() {}
+    }
+}
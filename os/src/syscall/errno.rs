@@ -0,0 +1,26 @@
+//! Linux error numbers, returned negated by syscalls.
+
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EINTR: isize = 4;
+pub const EIO: isize = 5;
+pub const E2BIG: isize = 7;
+pub const EBADF: isize = 9;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EEXIST: isize = 17;
+pub const EINVAL: isize = 22;
+pub const EMFILE: isize = 24;
+pub const ENOTTY: isize = 25;
+pub const EFBIG: isize = 27;
+pub const EPIPE: isize = 32;
+pub const EDEADLK: isize = 35;
+pub const EMSGSIZE: isize = 90;
+pub const ETIMEDOUT: isize = 110;
+
+/// Returned by a blocking call interrupted by a signal, and never seen by the
+/// user: the syscall is restarted after the signal is handled, unless it is
+/// handled by a handler without `SA_RESTART`, then it fails with `EINTR`.
+pub const ERESTARTSYS: isize = 512;
@@ -1,55 +1,53 @@
+use super::errno::EMFILE;
 use crate::fs::{make_pipe, open_file, OpenFlags};
 use crate::mm::{UserInPtr, UserOutPtr};
-use crate::task::CurrentTask;
+use crate::sync::Resource;
+use crate::task::{CurrentTask, Object, Rights, RLIMIT_NOFILE};
 
 const CHUNK_SIZE: usize = 256;
 
 pub fn sys_write(fd: usize, buf: UserInPtr<u8>, len: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let fd_table = proc.fd_table.lock();
-    if fd >= fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &fd_table[fd] {
-        if !file.writable() {
-            return -1;
-        }
-        let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(fd_table);
-        let mut count = 0;
-        while count < len {
-            let chunk_len = CHUNK_SIZE.min(len - count);
-            let chunk: [u8; CHUNK_SIZE] = unsafe { buf.add(count).read_array(chunk_len) };
-            let _len = file.write(&chunk[..chunk_len]);
-            assert_eq!(_len, chunk_len);
-            count += chunk_len;
+    let file = proc.handle_table.lock().file(fd, Rights::WRITE);
+    match file {
+        Ok(file) => {
+            let mut count = 0;
+            while count < len {
+                let chunk_len = CHUNK_SIZE.min(len - count);
+                let chunk: [u8; CHUNK_SIZE] = unsafe { buf.add(count).read_array(chunk_len) };
+                match file.write(&chunk[..chunk_len]) {
+                    Ok(write_len) => {
+                        count += write_len;
+                        if write_len < chunk_len {
+                            break;
+                        }
+                    }
+                    // the chunks already written are reported instead
+                    Err(err) if count == 0 => return err,
+                    Err(_) => break,
+                }
+            }
+            count as isize
         }
-        count as isize
-    } else {
-        -1
+        Err(err) => err,
     }
 }
 
 pub fn sys_read(fd: usize, mut buf: UserOutPtr<u8>, len: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let fd_table = proc.fd_table.lock();
-    if fd >= fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &fd_table[fd] {
-        if !file.readable() {
-            return -1;
+    let file = proc.handle_table.lock().file(fd, Rights::READ);
+    match file {
+        Ok(file) => {
+            let mut buffer = alloc::vec![0u8; len];
+            match file.read(&mut buffer) {
+                Ok(len) => {
+                    buf.write_buf(&buffer[..len]);
+                    len as isize
+                }
+                Err(err) => err,
+            }
         }
-        let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(fd_table);
-        let mut buffer = alloc::vec![0u8; len];
-        let len = file.read(&mut buffer) as isize;
-        buf.write_buf(&buffer);
-        len
-    } else {
-        -1
+        Err(err) => err,
     }
 }
 
@@ -57,47 +55,80 @@ pub fn sys_open(path: UserInPtr<u8>, flags: u32) -> isize {
     let proc = CurrentTask::get().proc();
     let path = path.as_c_str().unwrap();
     if let Some(inode) = open_file(path, OpenFlags::from_bits(flags).unwrap()) {
-        proc.alloc_fd(Some(inode)) as isize
+        proc.alloc_handle(Object::File(inode))
+            .map_or(-EMFILE, |fd| fd as isize)
     } else {
         -1
     }
 }
 
-pub fn sys_close(fd: usize) -> isize {
+/// Closes a handle of any kind, the object is destroyed once no handle or
+/// blocked task refers to it.
+pub fn sys_close(handle: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let mut fd_table = proc.fd_table.lock();
-    if fd >= fd_table.len() {
-        return -1;
-    }
-    if fd_table[fd].is_none() {
-        return -1;
+    let removed = proc.handle_table.lock().remove(handle);
+    match removed {
+        Ok(removed) => {
+            // the resource is forgotten with the last handle to the object
+            if let Some(res) = Resource::of(&removed.object) {
+                let handle_table = proc.handle_table.lock();
+                if !handle_table.objects().any(|o| Resource::of(o) == Some(res)) {
+                    proc.deadlock.lock().remove_resource(res);
+                }
+            }
+            0
+        }
+        Err(err) => err,
     }
-    fd_table[fd].take();
-    0
 }
 
 pub fn sys_pipe(mut pipe: UserOutPtr<usize>) -> isize {
     let proc = CurrentTask::get().proc();
     let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = proc.alloc_fd(Some(pipe_read));
-    let write_fd = proc.alloc_fd(Some(pipe_write));
+    let read_fd = match proc.alloc_handle(Object::File(pipe_read)) {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    let write_fd = match proc.alloc_handle(Object::File(pipe_write)) {
+        Some(fd) => fd,
+        None => {
+            proc.handle_table.lock().set(read_fd, None);
+            return -EMFILE;
+        }
+    };
     pipe.write_buf(&[read_fd, write_fd]);
     0
 }
 
-pub fn sys_dup(fd: usize) -> isize {
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let mut fd_table = proc.fd_table.lock();
-    if fd >= fd_table.len() || fd_table[fd].is_none() {
-        return -1;
+    let file = proc.handle_table.lock().file(fd, Rights::empty());
+    match file {
+        Ok(file) => file.ioctl(cmd, arg),
+        Err(err) => err,
     }
-    let new_fd = if let Some(fd) = (0..fd_table.len()).find(|fd| fd_table[*fd].is_none()) {
-        fd
-    } else {
-        fd_table.push(None);
-        fd_table.len() - 1
+}
+
+/// Duplicates a handle of any kind, with the same rights.
+pub fn sys_dup(handle: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let max_handles = proc.rlimits.lock().get(RLIMIT_NOFILE);
+    let mut handle_table = proc.handle_table.lock();
+    let clone = match handle_table.get(handle) {
+        Ok(h) => h.clone(),
+        Err(err) => return err,
     };
-    let clone = fd_table[fd].as_ref().unwrap().clone();
-    fd_table[new_fd] = Some(clone);
-    new_fd as isize
+    handle_table
+        .alloc(clone, max_handles)
+        .map_or(-EMFILE, |new_handle| new_handle as isize)
+}
+
+/// Keeps only `rights` of the handle, returns the rights left.
+pub fn sys_handle_restrict(handle: usize, rights: u32) -> isize {
+    let proc = CurrentTask::get().proc();
+    let restricted = proc.handle_table.lock().restrict(handle, rights);
+    match restricted {
+        Ok(rights) => rights.bits() as isize,
+        Err(err) => err,
+    }
 }
@@ -5,31 +5,73 @@ const SYSCALL_EXIT_GROUP: usize = 94;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
-const SYSCALL_FORK: usize = 220;
-const SYSCALL_EXEC: usize = 221;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXECVE: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE2: usize = 59;
 const SYSCALL_DUP3: usize = 24;
+const SYSCALL_IOCTL: usize = 29;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_SIGALTSTACK: usize = 132;
+const SYSCALL_TGKILL: usize = 131;
 const SYSCALL_SIGACTION: usize = 134;
 const SYSCALL_SIGPROCMASK: usize = 135;
-const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SIGTIMEDWAIT: usize = 137;
+const SYSCALL_RT_SIGQUEUEINFO: usize = 138;
+pub const SYSCALL_SIGRETURN: usize = 139;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_GETITIMER: usize = 102;
+const SYSCALL_SETITIMER: usize = 103;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
+const SYSCALL_GETRUSAGE: usize = 165;
+const SYSCALL_SBRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
+const SYSCALL_HANDLE_RESTRICT: usize = 470;
+const SYSCALL_ALARM: usize = 471;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1013;
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1023;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1033;
+const SYSCALL_CONDVAR_BROADCAST: usize = 1034;
+const SYSCALL_RWLOCK_CREATE: usize = 1040;
+const SYSCALL_RWLOCK_READ: usize = 1041;
+const SYSCALL_RWLOCK_WRITE: usize = 1042;
+const SYSCALL_RWLOCK_UNLOCK: usize = 1043;
+const SYSCALL_BARRIER_CREATE: usize = 1050;
+const SYSCALL_BARRIER_WAIT: usize = 1051;
+const SYSCALL_SEM_OPEN: usize = 1060;
+const SYSCALL_SEM_UNLINK: usize = 1061;
+const SYSCALL_MQ_OPEN: usize = 1070;
+const SYSCALL_MQ_UNLINK: usize = 1071;
+const SYSCALL_MQ_SEND: usize = 1072;
+const SYSCALL_MQ_RECEIVE: usize = 1073;
 
+pub mod errno;
 mod fs;
 mod process;
 mod signal;
@@ -43,7 +85,7 @@ use self::thread::*;
 use crate::trap::TrapFrame;
 use signal::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3], tf: &mut TrapFrame) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6], tf: &mut TrapFrame) -> isize {
     // arch::enable_irqs();
     let ret = match syscall_id {
         SYSCALL_READ => sys_read(args[0], args[1].into(), args[2]),
@@ -53,30 +95,77 @@ pub fn syscall(syscall_id: usize, args: [usize; 3], tf: &mut TrapFrame) -> isize
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
-        SYSCALL_FORK => sys_fork(tf),
-        SYSCALL_EXEC => sys_exec(args[0].into(), args[1].into(), tf),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1].into()),
+        SYSCALL_CLONE => sys_clone(args[0], args[1], args[2].into(), args[3], args[4], tf),
+        SYSCALL_SPAWN => sys_spawn(
+            args[0].into(),
+            args[1].into(),
+            args[2].into(),
+            args[3].into(),
+            args[4],
+        ),
+        SYSCALL_EXECVE => sys_execve(args[0].into(), args[1].into(), args[2].into(), tf),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1].into(), args[2] as _),
         SYSCALL_OPEN => sys_open(args[0].into(), args[1] as _),
         SYSCALL_CLOSE => sys_close(args[0]),
         SYSCALL_DUP3 => sys_dup(args[0]),
         SYSCALL_PIPE2 => sys_pipe(args[0].into()),
-        SYSCALL_KILL => sys_kill(args[0], args[1] as _),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_KILL => sys_kill(args[0] as isize, args[1] as _),
+        SYSCALL_SIGALTSTACK => sys_sigaltstack(args[0].into(), args[1].into(), tf),
         SYSCALL_SIGACTION => sys_sigaction(args[0] as _, args[1].into(), args[2].into()),
-        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0] as _),
+        SYSCALL_TGKILL => sys_tgkill(args[0], args[1], args[2] as _),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0], args[1].into(), args[2].into()),
+        SYSCALL_SIGTIMEDWAIT => sys_sigtimedwait(args[0].into(), args[1].into(), args[2].into()),
+        SYSCALL_RT_SIGQUEUEINFO => sys_rt_sigqueueinfo(args[0], args[1] as _, args[2].into()),
         SYSCALL_SIGRETURN => sys_sigretrun(tf),
         SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_GETITIMER => sys_getitimer(args[0], args[1].into()),
+        SYSCALL_SETITIMER => sys_setitimer(args[0], args[1].into(), args[2].into()),
+        SYSCALL_FUTEX => sys_futex(args[0].into(), args[1], args[2], args[3], args[4].into()),
+        SYSCALL_TIMES => sys_times(args[0].into()),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1]),
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
+        SYSCALL_GETSID => sys_getsid(args[0]),
+        SYSCALL_SETSID => sys_setsid(),
+        SYSCALL_GETRLIMIT => sys_getrlimit(args[0], args[1].into()),
+        SYSCALL_SETRLIMIT => sys_setrlimit(args[0], args[1].into()),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1].into()),
+        SYSCALL_SBRK => sys_sbrk(args[0] as isize),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
         SYSCALL_GETTID => sys_gettid(),
+        SYSCALL_SETPRIORITY => sys_set_priority(args[0]),
+        SYSCALL_GETPRIORITY => sys_get_priority(),
         SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
-        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
+        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0]),
         SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
         SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
+        SYSCALL_MUTEX_LOCK_TIMEOUT => sys_mutex_lock_timeout(args[0], args[1]),
         SYSCALL_SEMAPHORE_CREATE => sys_semaphore_create(args[0]),
         SYSCALL_SEMAPHORE_UP => sys_semaphore_up(args[0]),
         SYSCALL_SEMAPHORE_DOWN => sys_semaphore_down(args[0]),
+        SYSCALL_SEMAPHORE_DOWN_TIMEOUT => sys_semaphore_down_timeout(args[0], args[1]),
         SYSCALL_CONDVAR_CREATE => sys_condvar_create(args[0]),
         SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
+        SYSCALL_CONDVAR_WAIT_TIMEOUT => sys_condvar_wait_timeout(args[0], args[1], args[2]),
+        SYSCALL_CONDVAR_BROADCAST => sys_condvar_broadcast(args[0]),
+        SYSCALL_RWLOCK_CREATE => sys_rwlock_create(),
+        SYSCALL_RWLOCK_READ => sys_rwlock_read(args[0]),
+        SYSCALL_RWLOCK_WRITE => sys_rwlock_write(args[0]),
+        SYSCALL_RWLOCK_UNLOCK => sys_rwlock_unlock(args[0]),
+        SYSCALL_BARRIER_CREATE => sys_barrier_create(args[0]),
+        SYSCALL_BARRIER_WAIT => sys_barrier_wait(args[0]),
+        SYSCALL_SEM_OPEN => sys_sem_open(args[0].into(), args[1] as u32, args[2]),
+        SYSCALL_SEM_UNLINK => sys_sem_unlink(args[0].into()),
+        SYSCALL_MQ_OPEN => sys_mq_open(args[0].into(), args[1] as u32, args[2], args[3]),
+        SYSCALL_MQ_UNLINK => sys_mq_unlink(args[0].into()),
+        SYSCALL_MQ_SEND => sys_mq_send(args[0], args[1].into(), args[2], args[3] as u32),
+        SYSCALL_MQ_RECEIVE => sys_mq_receive(args[0], args[1].into(), args[2], args[3].into()),
+        SYSCALL_ENABLE_DEADLOCK_DETECT => sys_enable_deadlock_detect(args[0]),
+        SYSCALL_HANDLE_RESTRICT => sys_handle_restrict(args[0], args[1] as u32),
+        SYSCALL_ALARM => sys_alarm(args[0]),
         _ => {
             println!("Unsupported syscall_id: {}", syscall_id);
             crate::task::CurrentTask::get().exit(-1);
@@ -1,11 +1,104 @@
-use crate::mm::{UserInPtr, UserOutPtr};
-use crate::task::{spawn_proc, spawn_task, CurrentTask};
-use crate::timer::get_time_ms;
+use super::errno::{E2BIG, EAGAIN, EBADF, EEXIST, EFAULT, EINVAL, ENOENT, ENOMEM, EPERM, ESRCH};
+use crate::config::{MAX_ARG_SIZE, USER_MMAP_TOP};
+use crate::fs::{open_file, OpenFlags};
+use crate::mm::{align_up, MemFlags, UserInPtr, UserOutPtr, VirtAddr, PAGE_SIZE};
+use crate::task::{
+    pgid2procs, pid2proc, spawn_proc, spawn_task, CloneFlags, CurrentTask, Handle, HandleTable,
+    Object, ProcTimes, Rlimit, WaitOptions, ITIMER_PROF, ITIMER_REAL, RLIMIT_AS, RLIMIT_DATA,
+    RLIMIT_NOFILE, RLIM_NLIMITS,
+};
+use crate::timer::{
+    counter_to_secs, counter_to_ticks, counter_to_us, get_counter, get_time_ms, us_to_counter,
+};
 use crate::trap::TrapFrame;
 use alloc::{string::String, vec::Vec};
 
 const MAX_STR_LEN: usize = 256;
 
+/// File actions of `sys_spawn`.
+const SPAWN_CLOSE: usize = 0;
+const SPAWN_DUP2: usize = 1;
+const SPAWN_OPEN: usize = 2;
+
+/// A file action of `sys_spawn`, sets `fd` to nothing (`SPAWN_CLOSE`), to the
+/// file of fd `arg` (`SPAWN_DUP2`), or to the file at the path `arg` opened
+/// with `flags` (`SPAWN_OPEN`).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpawnFileAction {
+    op: usize,
+    fd: usize,
+    arg: usize,
+    flags: usize,
+}
+
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
+/// Same layout as `struct tms`, in clock ticks.
+#[repr(C)]
+pub struct Tms {
+    tms_utime: i64,
+    tms_stime: i64,
+    tms_cutime: i64,
+    tms_cstime: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeVal {
+    sec: i64,
+    usec: i64,
+}
+
+/// Same layout as `struct itimerval`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ITimerVal {
+    interval: TimeVal,
+    value: TimeVal,
+}
+
+/// Same layout as `struct rusage`, only the CPU times are filled.
+#[repr(C)]
+pub struct Rusage {
+    ru_utime: TimeVal,
+    ru_stime: TimeVal,
+    ru_others: [i64; 14],
+}
+
+impl TimeVal {
+    fn from_counter(counter: u64) -> Self {
+        let us = counter_to_us(counter) as i64;
+        Self {
+            sec: us / 1_000_000,
+            usec: us % 1_000_000,
+        }
+    }
+
+    /// Fails with `EINVAL` if the time is negative or `usec` is out of range.
+    fn to_counter(self) -> Result<u64, isize> {
+        if self.sec < 0 || !(0..1_000_000).contains(&self.usec) {
+            return Err(-EINVAL);
+        }
+        Ok(us_to_counter(
+            (self.sec as u64)
+                .saturating_mul(1_000_000)
+                .saturating_add(self.usec as u64),
+        ))
+    }
+}
+
+impl ITimerVal {
+    fn from_counter((value, interval): (u64, u64)) -> Self {
+        Self {
+            interval: TimeVal::from_counter(interval),
+            value: TimeVal::from_counter(value),
+        }
+    }
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     CurrentTask::get().exit(exit_code);
 }
@@ -28,41 +121,500 @@ pub fn sys_getpid() -> isize {
     CurrentTask::get().proc().pid().as_usize() as isize
 }
 
-pub fn sys_fork(tf: &TrapFrame) -> isize {
-    let new_proc = CurrentTask::get().proc().new_fork(tf);
+/// Creates a task which starts from the return of `clone`, with the stack
+/// pointer `newsp` if it is not 0. With `CLONE_THREAD` the task is a thread
+/// of the caller's process and its tid is returned, otherwise it is in a new
+/// child process and the pid is returned. The new task gets 0.
+pub fn sys_clone(
+    flags: usize,
+    newsp: usize,
+    mut parent_tid: UserOutPtr<i32>,
+    tls: usize,
+    child_tid: usize,
+    tf: &TrapFrame,
+) -> isize {
+    // the low byte is the exit signal
+    let flags = match CloneFlags::from_bits(flags as u32 & !0xff) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    if flags.contains(CloneFlags::CLONE_THREAD) && !flags.contains(CloneFlags::CLONE_SIGHAND)
+        || flags.contains(CloneFlags::CLONE_SIGHAND) && !flags.contains(CloneFlags::CLONE_VM)
+    {
+        return -EINVAL;
+    }
+    let proc = CurrentTask::get().proc();
+    let mut child_tf = tf.new_fork();
+    if newsp != 0 {
+        child_tf.usp = newsp as _;
+    }
+    let tls = if flags.contains(CloneFlags::CLONE_SETTLS) {
+        tls
+    } else {
+        crate::arch::user_thread_pointer()
+    };
+    if proc.nproc_exceeded() {
+        return -EAGAIN;
+    }
+    let (new_task, id) = if flags.contains(CloneFlags::CLONE_THREAD) {
+        let new_task = proc.new_thread(child_tf, tls);
+        let tid = new_task.tid().as_usize();
+        (new_task, tid)
+    } else {
+        let new_proc = proc.new_fork(flags, child_tf, tls);
+        spawn_proc(new_proc.clone());
+        (new_proc.task(), new_proc.pid().as_usize())
+    };
+    if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+        new_task.set_clear_child_tid(child_tid);
+    }
+    if flags.contains(CloneFlags::CLONE_PARENT_SETTID) && !parent_tid.is_null() {
+        parent_tid.write(id as i32);
+    }
+    spawn_task(new_task);
+    if flags.contains(CloneFlags::CLONE_VFORK) && !flags.contains(CloneFlags::CLONE_THREAD) {
+        proc.wait_vfork_done(&pid2proc(id).unwrap());
+    }
+    id as isize
+}
+
+/// Applies the file actions of `sys_spawn` to the handle table of the child.
+fn apply_file_action(
+    handle_table: &mut HandleTable,
+    action: &SpawnFileAction,
+    max_fds: usize,
+) -> isize {
+    let handle = match action.op {
+        SPAWN_CLOSE => None,
+        SPAWN_DUP2 => match handle_table.get(action.arg) {
+            Ok(h) => Some(h.clone()),
+            Err(err) => return err,
+        },
+        SPAWN_OPEN => {
+            let path = match read_path(action.arg.into()) {
+                Ok(path) => path,
+                Err(err) => return err,
+            };
+            match open_file(&path, OpenFlags::from_bits_truncate(action.flags as u32)) {
+                Some(inode) => Some(Handle::new(Object::File(inode))),
+                None => return -ENOENT,
+            }
+        }
+        _ => return -EINVAL,
+    };
+    let fd = action.fd;
+    if fd >= max_fds {
+        return -EBADF;
+    }
+    handle_table.set(fd, handle);
+    0
+}
+
+/// Creates a child process running the program `path` with `argv` and `envp`, whose handle
+/// table has the inherited handles of the caller with the `n_actions` file
+/// actions applied in order. Returns the pid of the child.
+pub fn sys_spawn(
+    path: UserInPtr<u8>,
+    argv: UserInPtr<*const u8>,
+    envp: UserInPtr<*const u8>,
+    actions: UserInPtr<SpawnFileAction>,
+    n_actions: usize,
+) -> isize {
+    let path = match read_path(path) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let (args, envs) = match read_args_envs(argv, envp) {
+        Ok(args_envs) => args_envs,
+        Err(err) => return err,
+    };
+    let proc = CurrentTask::get().proc();
+    if proc.nproc_exceeded() {
+        return -EAGAIN;
+    }
+    let elf_data = match open_file(&path, OpenFlags::RDONLY) {
+        Some(inode) => inode.read_all(),
+        None => return -ENOENT,
+    };
+    if !proc.image_fits(elf_data.as_slice()) {
+        return -ENOMEM;
+    }
+    let max_fds = proc.rlimits.lock().get(RLIMIT_NOFILE);
+    let mut handle_table = proc.handle_table.lock().inherited();
+    for i in 0..n_actions {
+        let action = unsafe { actions.add(i) };
+        if !action.check() {
+            return -EFAULT;
+        }
+        let ret = apply_file_action(&mut handle_table, &action.read(), max_fds);
+        if ret < 0 {
+            return ret;
+        }
+    }
+    let new_proc = proc.new_spawn(elf_data.as_slice(), &args, &envs, handle_table);
     let pid = new_proc.pid().as_usize() as isize;
     spawn_proc(new_proc.clone());
     spawn_task(new_proc.task());
     pid
 }
 
-fn read_argvs(args: UserInPtr<*const u8>) -> Vec<String> {
+/// Reads the path at `path`, fails with `EFAULT` if it is not in the user
+/// space, and with `EINVAL` if it is not UTF-8.
+fn read_path(path: UserInPtr<u8>) -> Result<String, isize> {
+    if !path.check() {
+        return Err(-EFAULT);
+    }
+    let (path_buf, len) = path.read_str::<MAX_STR_LEN>();
+    match core::str::from_utf8(&path_buf[..len]) {
+        Ok(path) => Ok(String::from(path)),
+        Err(_) => Err(-EINVAL),
+    }
+}
+
+/// Reads the null-terminated arrays of strings `argv` and `envp`. Fails with
+/// `E2BIG` if they would take more than `MAX_ARG_SIZE` on the user stack.
+fn read_args_envs(
+    argv: UserInPtr<*const u8>,
+    envp: UserInPtr<*const u8>,
+) -> Result<(Vec<String>, Vec<String>), isize> {
+    let mut size = 0;
+    let args = read_argvs(argv, &mut size)?;
+    let envs = read_argvs(envp, &mut size)?;
+    Ok((args, envs))
+}
+
+/// Reads a null-terminated array of strings, which is empty if `args` is null.
+/// The room that the strings and their pointers take on the user stack is
+/// added to `size`.
+fn read_argvs(args: UserInPtr<*const u8>, size: &mut usize) -> Result<Vec<String>, isize> {
     let mut args_vec = Vec::<String>::new();
+    *size += core::mem::size_of::<usize>();
+    if args.is_null() {
+        return Ok(args_vec);
+    }
     let mut argc = 0;
     loop {
-        let arg = unsafe { args.add(argc).read() } as usize;
-        if arg as usize == 0 {
+        let arg = unsafe { args.add(argc) };
+        if !arg.check() {
+            return Err(-EFAULT);
+        }
+        let arg = arg.read() as usize;
+        if arg == 0 {
             break;
         }
         let arg: UserInPtr<u8> = UserInPtr::from(arg);
-        let s = arg.read_c_str().expect("Invalid args");
+        if !arg.check() {
+            return Err(-EFAULT);
+        }
+        let s = arg.read_c_str().map_err(|_| -EINVAL)?;
+        *size += s.len() + 1 + core::mem::size_of::<usize>();
+        if *size > MAX_ARG_SIZE {
+            return Err(-E2BIG);
+        }
         args_vec.push(s);
         argc += 1;
     }
-    args_vec
+    Ok(args_vec)
 }
 
-pub fn sys_exec(path: UserInPtr<u8>, args: UserInPtr<*const u8>, tf: &mut TrapFrame) -> isize {
-    let (path_buf, len) = path.read_str::<MAX_STR_LEN>();
-    let path = core::str::from_utf8(&path_buf[..len]).unwrap();
-    CurrentTask::get().proc().exec(path, read_argvs(args), tf)
+/// Replaces the program of the process with `path`, passing `argv` and `envp`.
+pub fn sys_execve(
+    path: UserInPtr<u8>,
+    argv: UserInPtr<*const u8>,
+    envp: UserInPtr<*const u8>,
+    tf: &mut TrapFrame,
+) -> isize {
+    let path = match read_path(path) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let (args, envs) = match read_args_envs(argv, envp) {
+        Ok(args_envs) => args_envs,
+        Err(err) => return err,
+    };
+    CurrentTask::get().proc().exec(&path, args, envs, tf)
 }
 
 /// If there is no child process has the same pid as the given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, mut exit_code_ptr: UserOutPtr<i32>) -> isize {
-    let mut exit_code = 0;
-    let ret = CurrentTask::get().proc().waitpid(pid, &mut exit_code);
-    exit_code_ptr.write(exit_code);
+/// Else if there is a child process but it is still running, block until it exits,
+/// or return 0 at once if `WNOHANG` is given.
+pub fn sys_waitpid(pid: isize, mut exit_code_ptr: UserOutPtr<i32>, options: u32) -> isize {
+    let options = match WaitOptions::from_bits(options) {
+        Some(options) => options,
+        None => return -1,
+    };
+    let mut exit_status = 0;
+    let ret = CurrentTask::get()
+        .proc()
+        .waitpid(pid, &mut exit_status, options);
+    if ret > 0 && !exit_code_ptr.is_null() {
+        exit_code_ptr.write(exit_status);
+    }
     ret
 }
+
+/// Moves the process `pid` (the caller if 0) into the process group `pgid`
+/// (a new group led by `pid` if 0). Fails with `ESRCH` unless `pid` is the
+/// caller or its child, and with `EPERM` unless the group is in the same
+/// session.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let curr_proc = CurrentTask::get().proc();
+    let proc = if pid == 0 || pid == curr_proc.pid().as_usize() {
+        curr_proc.clone()
+    } else {
+        let children = curr_proc.children.lock();
+        match children.iter().find(|c| c.pid().as_usize() == pid) {
+            Some(child) => child.clone(),
+            None => return -ESRCH,
+        }
+    };
+    let pid = proc.pid().as_usize();
+    let pgid = if pgid == 0 { pid } else { pgid };
+    // a session leader can not leave its group
+    if proc.sid() != curr_proc.sid() || proc.sid() == pid {
+        return -EPERM;
+    }
+    if pgid != pid && !pgid2procs(pgid).iter().any(|p| p.sid() == curr_proc.sid()) {
+        return -EPERM;
+    }
+    proc.set_pgid(pgid);
+    0
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    if pid == 0 {
+        CurrentTask::get().proc().pgid() as isize
+    } else {
+        pid2proc(pid).map_or(-ESRCH, |proc| proc.pgid() as isize)
+    }
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    if pid == 0 {
+        CurrentTask::get().proc().sid() as isize
+    } else {
+        pid2proc(pid).map_or(-ESRCH, |proc| proc.sid() as isize)
+    }
+}
+
+/// Creates a new session and a new process group led by the caller, fails
+/// with `EPERM` if the caller is already a process group leader.
+pub fn sys_setsid() -> isize {
+    let proc = CurrentTask::get().proc();
+    let pid = proc.pid().as_usize();
+    if proc.pgid() == pid {
+        return -EPERM;
+    }
+    proc.set_sid(pid);
+    proc.set_pgid(pid);
+    pid as isize
+}
+
+pub fn sys_getrlimit(resource: usize, mut rlim: UserOutPtr<Rlimit>) -> isize {
+    if resource >= RLIM_NLIMITS {
+        return -EINVAL;
+    }
+    if !rlim.check() {
+        return -EFAULT;
+    }
+    let proc = CurrentTask::get().proc();
+    rlim.write(proc.rlimits.lock().table[resource]);
+    0
+}
+
+/// The soft limit can not exceed the hard limit, and the hard limit can only
+/// be lowered.
+pub fn sys_setrlimit(resource: usize, rlim: UserInPtr<Rlimit>) -> isize {
+    if resource >= RLIM_NLIMITS {
+        return -EINVAL;
+    }
+    if !rlim.check() {
+        return -EFAULT;
+    }
+    let new_limit = rlim.read();
+    if new_limit.cur > new_limit.max {
+        return -EINVAL;
+    }
+    let proc = CurrentTask::get().proc();
+    let mut rlimits = proc.rlimits.lock();
+    if new_limit.max > rlimits.table[resource].max {
+        return -EPERM;
+    }
+    rlimits.table[resource] = new_limit;
+    0
+}
+
+/// Moves the program break by `increment` bytes, returns the old one. Fails
+/// with `ENOMEM` if the heap would exceed `RLIMIT_DATA`, or the address space
+/// `RLIMIT_AS`.
+pub fn sys_sbrk(increment: isize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let rlimits = proc.rlimits.lock().clone();
+    let vm = proc.vm();
+    let mut vm = vm.lock();
+    let old_brk = vm.brk();
+    let brk = if increment >= 0 {
+        old_brk.checked_add(increment as usize)
+    } else {
+        old_brk.checked_sub(increment.unsigned_abs())
+    };
+    let brk = match brk {
+        Some(brk) if brk >= vm.heap_bottom() && brk <= USER_MMAP_TOP => brk,
+        _ => return -ENOMEM,
+    };
+    let grown = align_up(brk, PAGE_SIZE).saturating_sub(align_up(old_brk, PAGE_SIZE));
+    if brk - vm.heap_bottom() > rlimits.get(RLIMIT_DATA)
+        || vm.size() + grown > rlimits.get(RLIMIT_AS)
+        || !vm.set_brk(brk)
+    {
+        return -ENOMEM;
+    }
+    old_brk as isize
+}
+
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+
+/// Maps `len` bytes of zeroed memory at `start`, readable, writable or
+/// executable as `prot` says, returns `start`. Fails with `EINVAL` if `start`
+/// is not page aligned or the range is out of the user space, with `EEXIST`
+/// if it overlaps a mapped area, and with `ENOMEM` if the address space would
+/// exceed `RLIMIT_AS`.
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    if start == 0
+        || start % PAGE_SIZE != 0
+        || start >= USER_MMAP_TOP
+        || len == 0
+        || len > USER_MMAP_TOP - start
+        || prot == 0
+        || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0
+    {
+        return -EINVAL;
+    }
+    let size = align_up(len, PAGE_SIZE);
+    let proc = CurrentTask::get().proc();
+    let max_size = proc.rlimits.lock().get(RLIMIT_AS);
+    let vm = proc.vm();
+    let mut vm = vm.lock();
+    if vm.size() + size > max_size {
+        return -ENOMEM;
+    }
+    // the bits of `prot` are those of `MemFlags`
+    let flags = MemFlags::from_bits_truncate(prot) | MemFlags::USER;
+    if !vm.mmap(VirtAddr::new(start), size, flags) {
+        return -EEXIST;
+    }
+    start as isize
+}
+
+/// Unmaps the area mapped by `sys_mmap` at `start`, fails with `EINVAL` if
+/// there is no such area of `len` bytes.
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    if start % PAGE_SIZE != 0 || start >= USER_MMAP_TOP || len > USER_MMAP_TOP {
+        return -EINVAL;
+    }
+    let vm = CurrentTask::get().proc().vm();
+    let mut vm = vm.lock();
+    if !vm.munmap(VirtAddr::new(start), align_up(len, PAGE_SIZE)) {
+        return -EINVAL;
+    }
+    0
+}
+
+/// Writes the CPU times of the caller and its waited-for children, returns the
+/// clock ticks since boot.
+pub fn sys_times(mut tms: UserOutPtr<Tms>) -> isize {
+    let times = CurrentTask::get().proc().cpu_times();
+    if !tms.is_null() {
+        if !tms.check() {
+            return -EFAULT;
+        }
+        tms.write(Tms {
+            tms_utime: counter_to_ticks(times.utime) as _,
+            tms_stime: counter_to_ticks(times.stime) as _,
+            tms_cutime: counter_to_ticks(times.cutime) as _,
+            tms_cstime: counter_to_ticks(times.cstime) as _,
+        });
+    }
+    counter_to_ticks(get_counter()) as isize
+}
+
+pub fn sys_getrusage(who: isize, mut usage: UserOutPtr<Rusage>) -> isize {
+    let task = CurrentTask::get();
+    let (utime, stime) = match who {
+        RUSAGE_SELF => {
+            let ProcTimes { utime, stime, .. } = task.proc().cpu_times();
+            (utime, stime)
+        }
+        RUSAGE_CHILDREN => {
+            let ProcTimes { cutime, cstime, .. } = task.proc().cpu_times();
+            (cutime, cstime)
+        }
+        RUSAGE_THREAD => {
+            let times = *task.times.lock();
+            (times.utime, times.stime)
+        }
+        _ => return -EINVAL,
+    };
+    if !usage.check() {
+        return -EFAULT;
+    }
+    usage.write(Rusage {
+        ru_utime: TimeVal::from_counter(utime),
+        ru_stime: TimeVal::from_counter(stime),
+        ru_others: [0; 14],
+    });
+    0
+}
+
+/// Gets interval timer `which`, see `ITIMER_REAL` etc.
+pub fn sys_getitimer(which: usize, mut curr_value: UserOutPtr<ITimerVal>) -> isize {
+    if which > ITIMER_PROF {
+        return -EINVAL;
+    }
+    if !curr_value.check() {
+        return -EFAULT;
+    }
+    let curr = CurrentTask::get().proc().get_itimer(which);
+    curr_value.write(ITimerVal::from_counter(curr));
+    0
+}
+
+/// Sets interval timer `which`, which is disarmed if `new_value.value` is 0,
+/// and gets the old one if `old_value` is not null.
+pub fn sys_setitimer(
+    which: usize,
+    new_value: UserInPtr<ITimerVal>,
+    mut old_value: UserOutPtr<ITimerVal>,
+) -> isize {
+    if which > ITIMER_PROF {
+        return -EINVAL;
+    }
+    if !new_value.check() || (!old_value.is_null() && !old_value.check()) {
+        return -EFAULT;
+    }
+    let new = new_value.read();
+    let (value, interval) = match (new.value.to_counter(), new.interval.to_counter()) {
+        (Ok(value), Ok(interval)) => (value, interval),
+        _ => return -EINVAL,
+    };
+    let old = CurrentTask::get().proc().set_itimer(which, value, interval);
+    if !old_value.is_null() {
+        old_value.write(ITimerVal::from_counter(old));
+    }
+    0
+}
+
+/// Sends `SIGALRM` after `secs` seconds, or cancels the alarm if 0. Returns
+/// the seconds left of the old alarm, rounded up.
+pub fn sys_alarm(secs: usize) -> isize {
+    let value = us_to_counter((secs as u64).saturating_mul(1_000_000));
+    let (left, _) = CurrentTask::get().proc().set_itimer(ITIMER_REAL, value, 0);
+    let left_secs = counter_to_secs(left);
+    if us_to_counter(left_secs * 1_000_000) < left {
+        left_secs as isize + 1
+    } else {
+        left_secs as isize
+    }
+}
@@ -1,73 +1,269 @@
-use crate::mm::{UserInOutPtr, UserInPtr};
-use crate::task::{pid2proc, CurrentTask, SignalAction, SignalFlags, TaskState, MAX_SIG};
+use super::errno::{EAGAIN, EFAULT, EINVAL, ENOMEM, EPERM, ESRCH};
+use super::sync::TimeSpec;
+use crate::mm::{UserInOutPtr, UserInPtr, UserOutPtr, VirtAddr};
+use crate::task::{
+    kill_pgrp, pgid2procs, pid2proc, CurrentTask, SigActionFlags, SigInfo, SignalAction,
+    SignalFlags, SignalFrame, SignalStack, MAX_SIG, MINSIGSTKSZ, SIGRTMIN, SIG_BLOCK, SIG_SETMASK,
+    SIG_UNBLOCK, SI_TKILL, SI_USER, SS_DISABLE, SS_ONSTACK,
+};
+use crate::timer::get_time_ms;
 use crate::trap::TrapFrame;
 
-pub fn sys_kill(pid: usize, signum: i32) -> isize {
-    if let Some(proc) = pid2proc(pid) {
-        if let Some(flag) = SignalFlags::from_bits(1 << signum) {
-            // insert the signal if legal
-            // signal mask??
-            let tasks = proc.tasks.lock();
-            let mut count = 0;
-            for task in tasks.values().cloned() {
-                if task.state() != TaskState::Zombie {
-                    let mut inner = task.signal.lock();
-                    if !inner.signals.contains(flag) {
-                        inner.signals.insert(flag);
-                        count += 1;
-                    }
-                }
-            }
-            if count > 0 {
-                return 0;
-            }
-        }
+/// Sends a signal to the process `pid` if `pid` > 0, to the process group of
+/// the caller if `pid` == 0, or to the process group `-pid` if `pid` < -1.
+/// Signal 0 is not sent, but the target is still checked.
+pub fn sys_kill(pid: isize, signum: i32) -> isize {
+    if signum < 0 || signum as usize > MAX_SIG {
+        return -1;
+    }
+    let proc = CurrentTask::get().proc();
+    if signum == 0 {
+        let found = match pid {
+            -1 => false,
+            0 => true,
+            pid if pid < 0 => !pgid2procs(-pid as usize).is_empty(),
+            pid => pid2proc(pid as usize).is_some(),
+        };
+        return if found { 0 } else { -1 };
+    }
+    let info = SigInfo {
+        signo: signum,
+        code: SI_USER,
+        pid: proc.pid().as_usize() as i32,
+        ..SigInfo::default()
+    };
+    let sent = match pid {
+        -1 => false,
+        0 => kill_pgrp(proc.pgid(), info),
+        pid if pid < 0 => kill_pgrp(-pid as usize, info),
+        pid => pid2proc(pid as usize).map_or(false, |proc| proc.send_signal_info(info)),
+    };
+    if sent {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Sends a signal to the task `tid` of the process `tgid` only, rather than to
+/// any task of the process which does not block it. Fails with `ESRCH` if
+/// there is no such task. Signal 0 is not sent, but the target is still
+/// checked.
+pub fn sys_tgkill(tgid: usize, tid: usize, signum: i32) -> isize {
+    if signum < 0 || signum as usize > MAX_SIG {
+        return -EINVAL;
+    }
+    let task = match pid2proc(tgid).and_then(|proc| proc.tasks.lock().get(&tid).cloned()) {
+        Some(task) => task,
+        None => return -ESRCH,
+    };
+    if signum == 0 {
+        return 0;
+    }
+    let info = SigInfo {
+        signo: signum,
+        code: SI_TKILL,
+        pid: CurrentTask::get().proc().pid().as_usize() as i32,
+        ..SigInfo::default()
+    };
+    if !task.send_signal_info(info) && signum as usize >= SIGRTMIN {
+        return -EAGAIN;
+    }
+    0
+}
+
+/// Sends signal `signum` described by `info` to the process `pid`, see
+/// `sigqueue`. The real-time signals are queued with their information.
+/// Fails with `EPERM` if `info` does not have a negative code, which is kept
+/// for the signals sent by the user, and with `EAGAIN` if the signal cannot be
+/// queued. Signal 0 is not sent, but the target is still checked.
+pub fn sys_rt_sigqueueinfo(pid: usize, signum: i32, info: UserInPtr<SigInfo>) -> isize {
+    if signum < 0 || signum as usize > MAX_SIG {
+        return -EINVAL;
+    }
+    if !info.check() {
+        return -EFAULT;
+    }
+    let mut info = info.read();
+    if info.code >= 0 {
+        return -EPERM;
+    }
+    let target = match pid2proc(pid) {
+        Some(target) => target,
+        None => return -ESRCH,
+    };
+    if signum == 0 {
+        return 0;
+    }
+    info.signo = signum;
+    info.pid = CurrentTask::get().proc().pid().as_usize() as i32;
+    if !target.send_signal_info(info) && signum as usize >= SIGRTMIN {
+        return -EAGAIN;
     }
-    -1
+    0
 }
 
-pub fn sys_sigprocmask(mask: u32) -> isize {
+/// Changes the blocked signals as `how` says with `set` if it is not null,
+/// and gets the old ones into `old_set` if it is not null. `SIGKILL` and
+/// `SIGSTOP` cannot be blocked.
+pub fn sys_sigprocmask(how: usize, set: UserInPtr<u64>, mut old_set: UserOutPtr<u64>) -> isize {
     let task = CurrentTask::get();
     let mut inner = task.signal.lock();
     let old_mask = inner.signal_mask;
-    if let Some(flag) = SignalFlags::from_bits(mask) {
-        inner.signal_mask = flag;
-        old_mask.bits() as isize
+    if !set.is_null() {
+        if !set.check() {
+            return -EFAULT;
+        }
+        let set = SignalFlags::from_bits_truncate(set.read()) - SignalFlags::UNCATCHABLE;
+        inner.signal_mask = match how {
+            SIG_BLOCK => old_mask | set,
+            SIG_UNBLOCK => old_mask - set,
+            SIG_SETMASK => set,
+            _ => return -EINVAL,
+        };
+    }
+    if !old_set.is_null() {
+        if !old_set.check() {
+            return -EFAULT;
+        }
+        old_set.write(old_mask.bits());
+    }
+    0
+}
+
+/// Waits for a signal in `set` and takes it without calling its handler,
+/// returns its number. Its information goes to `info` if not null. Gives up
+/// after `timeout` if not null, with `EAGAIN`, and fails with `EINTR` if a
+/// signal not in `set` is handled meanwhile.
+pub fn sys_sigtimedwait(
+    set: UserInPtr<u64>,
+    mut info: UserOutPtr<SigInfo>,
+    timeout: UserInPtr<TimeSpec>,
+) -> isize {
+    if !set.check() {
+        return -EFAULT;
+    }
+    let set = SignalFlags::from_bits_truncate(set.read()) - SignalFlags::UNCATCHABLE;
+    let deadline_ms = if timeout.is_null() {
+        None
+    } else if !timeout.check() {
+        return -EFAULT;
     } else {
-        -1
+        let timeout = timeout.read();
+        if !timeout.is_valid() {
+            return -EINVAL;
+        }
+        Some(get_time_ms() as usize + timeout.as_ms())
+    };
+    let taken = match CurrentTask::get().wait_signal(set, deadline_ms) {
+        Ok(taken) => taken,
+        Err(err) => return err,
+    };
+    if !info.is_null() {
+        if !info.check() {
+            return -EFAULT;
+        }
+        info.write(taken);
     }
+    taken.signo as isize
 }
 
+/// Pops the frame of the running handler and resumes the context which it
+/// interrupted. Returns the restored `x0`, so that it is not overwritten by
+/// the return value.
 pub fn sys_sigretrun(tf: &mut TrapFrame) -> isize {
     let task = CurrentTask::get();
     let mut inner = task.signal.lock();
-    if let Some(backup) = inner.trapframe_backup.take() {
-        inner.handling_sig = None;
-        inner.signal_mask = inner.mask_backup.take().unwrap();
-        *tf = backup;
-        return 0;
+    if inner.frame == 0 {
+        return -1;
+    }
+    let ptr = UserInPtr::<SignalFrame>::from(inner.frame);
+    let size = core::mem::size_of::<SignalFrame>();
+    if !ptr.check()
+        || !task
+            .proc()
+            .vm()
+            .lock()
+            .is_mapped(VirtAddr::new(inner.frame), size)
+    {
+        return -EFAULT;
+    }
+    let frame = ptr.read();
+    inner.frame = frame.prev;
+    inner.signal_mask = frame.uc.mask - SignalFlags::UNCATCHABLE;
+    frame.uc.fp.restore();
+    tf.restore_user(&frame.uc.tf);
+    tf.r[0] as isize
+}
+
+/// Sets the stack for the handlers with `SA_ONSTACK` if `ss` is not null, and
+/// gets the old one if `old_ss` is not null. Fails with `EPERM` if the task is
+/// running on the stack, and with `ENOMEM` if the stack is too small.
+pub fn sys_sigaltstack(
+    ss: UserInPtr<SignalStack>,
+    mut old_ss: UserOutPtr<SignalStack>,
+    tf: &TrapFrame,
+) -> isize {
+    let task = CurrentTask::get();
+    let mut inner = task.signal.lock();
+    let on_stack = inner.altstack.contains(tf.usp as usize);
+    let new = if ss.is_null() {
+        None
+    } else if !ss.check() {
+        return -EFAULT;
+    } else if on_stack {
+        return -EPERM;
+    } else {
+        let new = ss.read();
+        match new.flags {
+            SS_DISABLE => Some(SignalStack::disabled()),
+            0 if new.size < MINSIGSTKSZ => return -ENOMEM,
+            0 => Some(new),
+            _ => return -EINVAL,
+        }
+    };
+    if !old_ss.is_null() {
+        if !old_ss.check() {
+            return -EFAULT;
+        }
+        let mut old = inner.altstack;
+        if on_stack {
+            old.flags |= SS_ONSTACK;
+        }
+        old_ss.write(old);
+    }
+    if let Some(new) = new {
+        inner.altstack = new;
     }
-    -1
+    0
 }
 
+/// Sets the action of signal `signum`, whose handler can also be `SIG_DFL` or
+/// `SIG_IGN`, and gets the old one. Either pointer can be null, to only get
+/// or only set the action. The action of `SIGKILL` and `SIGSTOP` cannot be
+/// changed.
 pub fn sys_sigaction(
     signum: i32,
     action: UserInPtr<SignalAction>,
     mut old_action: UserInOutPtr<SignalAction>,
 ) -> isize {
-    if signum as usize > MAX_SIG {
-        return -1;
+    if signum < 1 || signum as usize > MAX_SIG {
+        return -EINVAL;
     }
-    if let Some(signal) = SignalFlags::from_bits(1 << signum) {
-        if action.is_null()
-            || old_action.is_null()
-            || signal == SignalFlags::SIGKILL
-            || signal == SignalFlags::SIGSTOP
-        {
-            return -1;
-        }
-        let proc = CurrentTask::get().proc();
-        let mut actions = proc.signal_actions.lock();
+    let signal = match SignalFlags::from_bits(1 << signum) {
+        Some(signal) => signal,
+        None => return -EINVAL,
+    };
+    if !action.is_null() && (signal == SignalFlags::SIGKILL || signal == SignalFlags::SIGSTOP) {
+        return -EINVAL;
+    }
+    if !(action.is_null() || action.check()) || !(old_action.is_null() || old_action.check()) {
+        return -EFAULT;
+    }
+    let proc = CurrentTask::get().proc();
+    let signal_actions = proc.signal_actions();
+    let mut actions = signal_actions.lock();
+    if !old_action.is_null() {
         let old_kernel_action = actions.table[signum as usize];
         if old_kernel_action.mask != SignalFlags::TRAP_QUIT {
             old_action.write(old_kernel_action);
@@ -76,8 +272,17 @@ pub fn sys_sigaction(
             action.handler = old_kernel_action.handler;
             old_action.write(action);
         }
-        actions.table[signum as usize] = action.read();
-        return 0;
     }
-    -1
+    if !action.is_null() {
+        let mut action = action.read();
+        action.flags = SigActionFlags::from_bits_truncate(action.flags.bits());
+        action.mask -= SignalFlags::UNCATCHABLE;
+        actions.table[signum as usize] = action;
+        // a pending signal is discarded once ignored
+        if actions.ignores(signum as usize) {
+            drop(actions);
+            proc.discard_signal(signal);
+        }
+    }
+    0
 }
@@ -1,129 +1,574 @@
-use crate::sync::{Condvar, MutexBlocking, MutexSpin, Semaphore, UserMutex};
-use crate::task::CurrentTask;
-use crate::timer::{add_timer, get_time_ms};
+use super::errno::{EAGAIN, EDEADLK, EFAULT, EINTR, EINVAL, EMFILE, EMSGSIZE};
+use crate::fs::OpenFlags;
+use crate::mm::{PhysAddr, UserInPtr, UserOutPtr, VirtAddr};
+use crate::sync::{futex_requeue, futex_wait, futex_wake};
+use crate::sync::{
+    Barrier, Condvar, MessageQueue, MutexBlocking, MutexSpin, Resource, RwLock, Semaphore,
+    UserMutex, MESSAGE_QUEUES, NAMED_SEMAPHORES, NAME_MAX,
+};
+use crate::task::{CurrentTask, Object, Rights};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::string::String;
 use alloc::sync::Arc;
 
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_REQUEUE: usize = 3;
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// Same layout as `struct timespec`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeSpec {
+    sec: i64,
+    nsec: i64,
+}
+
+impl TimeSpec {
+    pub(super) fn is_valid(&self) -> bool {
+        self.sec >= 0 && (0..1_000_000_000).contains(&self.nsec)
+    }
+
+    /// In milliseconds, rounded up.
+    pub(super) fn as_ms(&self) -> usize {
+        self.sec as usize * 1000 + (self.nsec as usize + 999_999) / 1_000_000
+    }
+}
+
+/// Fails with `EINTR` if interrupted by a signal, and is never restarted.
 pub fn sys_sleep(ms: usize) -> isize {
     let expire_ms = get_time_ms() as usize + ms;
     let task = CurrentTask::get();
     add_timer(expire_ms, task.clone());
-    task.block_and_yield();
+    task.block_interruptible();
+    if (get_time_ms() as usize) < expire_ms {
+        remove_timer(task.clone());
+        return -EINTR;
+    }
     0
 }
 
-pub fn sys_mutex_create(blocking: bool) -> isize {
+/// The physical address of the futex word `uaddr`, which is the key of its
+/// wait queue.
+fn futex_key(uaddr: &UserInPtr<u32>) -> Result<PhysAddr, isize> {
+    if uaddr.as_ptr() as usize % 4 != 0 {
+        return Err(-EINVAL);
+    }
+    if !uaddr.check() {
+        return Err(-EFAULT);
+    }
     let proc = CurrentTask::get().proc();
-    let mutex: Option<Arc<dyn UserMutex>> = if !blocking {
-        Some(Arc::new(MutexSpin::new()))
-    } else {
-        Some(Arc::new(MutexBlocking::new()))
-    };
-    let mut mutex_inner = proc.mutex_list.lock();
-    if let Some(id) = mutex_inner
-        .iter()
-        .enumerate()
-        .find(|(_, item)| item.is_none())
-        .map(|(id, _)| id)
-    {
-        mutex_inner[id] = mutex;
-        id as isize
+    let vm = proc.vm();
+    let paddr = vm.lock().translate(VirtAddr::new(uaddr.as_ptr() as usize));
+    paddr.ok_or(-EFAULT)
+}
+
+/// `val2` is the timeout for `FUTEX_WAIT`, and the maximum number of tasks to
+/// requeue for `FUTEX_REQUEUE`.
+pub fn sys_futex(
+    uaddr: UserInPtr<u32>,
+    op: usize,
+    val: usize,
+    val2: usize,
+    uaddr2: UserInPtr<u32>,
+) -> isize {
+    let key = match futex_key(&uaddr) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let deadline_ms = if val2 != 0 {
+                let timeout: UserInPtr<TimeSpec> = val2.into();
+                if !timeout.check() {
+                    return -EFAULT;
+                }
+                let timeout = timeout.read();
+                if !timeout.is_valid() {
+                    return -EINVAL;
+                }
+                Some(get_time_ms() as usize + timeout.as_ms())
+            } else {
+                None
+            };
+            // IRQs are disabled, so no wakeup is lost after the check
+            if uaddr.read() != val as u32 {
+                return -EAGAIN;
+            }
+            match futex_wait(key, deadline_ms) {
+                Ok(()) => 0,
+                Err(err) => err,
+            }
+        }
+        FUTEX_WAKE => futex_wake(key, val) as isize,
+        FUTEX_REQUEUE => match futex_key(&uaddr2) {
+            Ok(key2) => futex_requeue(key, val, key2, val2) as isize,
+            Err(err) => err,
+        },
+        _ => -EINVAL,
+    }
+}
+
+/// `flags` of `sys_mutex_create`, a spin mutex is created without both.
+const MUTEX_BLOCKING: usize = 1;
+const MUTEX_PI: usize = 2;
+
+/// Sync objects are created as handles, which fails with `EMFILE` beyond
+/// `RLIMIT_NOFILE`, and destroyed by `sys_close`. The other calls fail with
+/// `EBADF` for a bad handle and with `EINVAL` for one of another kind.
+pub fn sys_mutex_create(flags: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let mutex: Arc<dyn UserMutex> = if flags & MUTEX_PI != 0 {
+        Arc::new(MutexBlocking::new(true))
+    } else if flags & MUTEX_BLOCKING != 0 {
+        Arc::new(MutexBlocking::new(false))
     } else {
-        mutex_inner.push(mutex);
-        mutex_inner.len() as isize - 1
+        Arc::new(MutexSpin::new())
+    };
+    let res = Resource::mutex(&mutex);
+    match proc.alloc_handle(Object::Mutex(mutex)) {
+        Some(id) => {
+            proc.deadlock.lock().add_resource(res, 1);
+            id as isize
+        }
+        None => -EMFILE,
     }
 }
 
 pub fn sys_mutex_lock(mutex_id: usize) -> isize {
-    let proc = CurrentTask::get().proc();
-    let mutex_inner = proc.mutex_list.lock();
-    let mutex = Arc::clone(mutex_inner[mutex_id].as_ref().unwrap());
-    drop(mutex_inner);
+    mutex_lock(mutex_id, None)
+}
+
+/// Fails with `ETIMEDOUT` if the mutex is not got in `timeout_ms`, and with
+/// `EINTR` if interrupted by a signal.
+pub fn sys_mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> isize {
+    mutex_lock(mutex_id, Some(get_time_ms() as usize + timeout_ms))
+}
+
+fn mutex_lock(mutex_id: usize, deadline_ms: Option<usize>) -> isize {
+    let task = CurrentTask::get();
+    let tid = task.tid().as_usize();
+    let proc = task.proc();
+    let mutex = match proc.handle_table.lock().mutex(mutex_id) {
+        Ok(mutex) => mutex,
+        Err(err) => return err,
+    };
+    let res = Resource::mutex(&mutex);
+    if !proc.deadlock.lock().request(tid, res) {
+        return -EDEADLK;
+    }
     drop(proc);
-    mutex.lock();
-    0
+    let ret = mutex.lock_timeout(deadline_ms);
+    let proc = task.proc();
+    let mut deadlock = proc.deadlock.lock();
+    match ret {
+        Ok(()) => {
+            deadlock.acquire(tid, res);
+            0
+        }
+        Err(err) => {
+            deadlock.cancel(tid, res);
+            err
+        }
+    }
 }
 
 pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
-    let proc = CurrentTask::get().proc();
-    let mutex_inner = proc.mutex_list.lock();
-    let mutex = Arc::clone(mutex_inner[mutex_id].as_ref().unwrap());
-    drop(mutex_inner);
-    drop(proc);
-    mutex.unlock();
+    let task = CurrentTask::get();
+    let proc = task.proc();
+    let mutex = match proc.handle_table.lock().mutex(mutex_id) {
+        Ok(mutex) => mutex,
+        Err(err) => return err,
+    };
+    if let Err(err) = mutex.unlock() {
+        return err;
+    }
+    let tid = task.tid().as_usize();
+    proc.deadlock.lock().release(tid, Resource::mutex(&mutex));
     0
 }
 
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let mut sem_inner = proc.semaphore_list.lock();
-    let id = if let Some(id) = sem_inner
-        .iter()
-        .enumerate()
-        .find(|(_, item)| item.is_none())
-        .map(|(id, _)| id)
-    {
-        sem_inner[id] = Some(Arc::new(Semaphore::new(res_count)));
-        id
-    } else {
-        sem_inner.push(Some(Arc::new(Semaphore::new(res_count))));
-        sem_inner.len() - 1
-    };
-    id as isize
+    let sem = Arc::new(Semaphore::new(res_count));
+    let res = Resource::semaphore(&sem);
+    match proc.alloc_handle(Object::Semaphore(sem)) {
+        Some(id) => {
+            proc.deadlock.lock().add_resource(res, res_count);
+            id as isize
+        }
+        None => -EMFILE,
+    }
 }
 
 pub fn sys_semaphore_up(sem_id: usize) -> isize {
-    let proc = CurrentTask::get().proc();
-    let sem_inner = proc.semaphore_list.lock();
-    let sem = Arc::clone(sem_inner[sem_id].as_ref().unwrap());
-    drop(sem_inner);
+    let task = CurrentTask::get();
+    let proc = task.proc();
+    let sem = match proc.handle_table.lock().semaphore(sem_id) {
+        Ok(sem) => sem,
+        Err(err) => return err,
+    };
+    let tid = task.tid().as_usize();
+    proc.deadlock.lock().release(tid, Resource::semaphore(&sem));
     sem.up();
     0
 }
 
 pub fn sys_semaphore_down(sem_id: usize) -> isize {
-    let proc = CurrentTask::get().proc();
-    let sem_inner = proc.semaphore_list.lock();
-    let sem = Arc::clone(sem_inner[sem_id].as_ref().unwrap());
-    drop(sem_inner);
-    sem.down();
-    0
+    semaphore_down(sem_id, None)
+}
+
+/// Fails with `ETIMEDOUT` if the semaphore is not got in `timeout_ms`, and
+/// with `EINTR` if interrupted by a signal.
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    semaphore_down(sem_id, Some(get_time_ms() as usize + timeout_ms))
+}
+
+fn semaphore_down(sem_id: usize, deadline_ms: Option<usize>) -> isize {
+    let task = CurrentTask::get();
+    let tid = task.tid().as_usize();
+    let proc = task.proc();
+    let sem = match proc.handle_table.lock().semaphore(sem_id) {
+        Ok(sem) => sem,
+        Err(err) => return err,
+    };
+    let res = Resource::semaphore(&sem);
+    if !proc.deadlock.lock().request(tid, res) {
+        return -EDEADLK;
+    }
+    drop(proc);
+    let got = sem.down_timeout(deadline_ms);
+    let proc = task.proc();
+    let mut deadlock = proc.deadlock.lock();
+    match got {
+        Ok(()) => {
+            deadlock.acquire(tid, res);
+            0
+        }
+        Err(err) => {
+            deadlock.cancel(tid, res);
+            err
+        }
+    }
 }
 
 pub fn sys_condvar_create(_arg: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let mut condv_inner = proc.condvar_list.lock();
-    let id = if let Some(id) = condv_inner
-        .iter()
-        .enumerate()
-        .find(|(_, item)| item.is_none())
-        .map(|(id, _)| id)
-    {
-        condv_inner[id] = Some(Arc::new(Condvar::new()));
-        id
-    } else {
-        condv_inner.push(Some(Arc::new(Condvar::new())));
-        condv_inner.len() - 1
-    };
-    id as isize
+    proc.alloc_handle(Object::Condvar(Arc::new(Condvar::new())))
+        .map_or(-EMFILE, |id| id as isize)
 }
 
 pub fn sys_condvar_signal(condvar_id: usize) -> isize {
     let proc = CurrentTask::get().proc();
-    let condv_inner = proc.condvar_list.lock();
-    let condvar = Arc::clone(condv_inner[condvar_id].as_ref().unwrap());
-    drop(condv_inner);
+    let condvar = match proc.handle_table.lock().condvar(condvar_id) {
+        Ok(condvar) => condvar,
+        Err(err) => return err,
+    };
     condvar.signal();
     0
 }
 
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let condvar = match proc.handle_table.lock().condvar(condvar_id) {
+        Ok(condvar) => condvar,
+        Err(err) => return err,
+    };
+    condvar.broadcast();
+    0
+}
+
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    condvar_wait(condvar_id, mutex_id, None)
+}
+
+/// Fails with `ETIMEDOUT` if not signaled in `timeout_ms`, and with `EINTR`
+/// if interrupted by a signal. The mutex is locked again either way.
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    condvar_wait(
+        condvar_id,
+        mutex_id,
+        Some(get_time_ms() as usize + timeout_ms),
+    )
+}
+
+fn condvar_wait(condvar_id: usize, mutex_id: usize, deadline_ms: Option<usize>) -> isize {
+    let proc = CurrentTask::get().proc();
+    let handle_table = proc.handle_table.lock();
+    let (condvar, mutex) = match (
+        handle_table.condvar(condvar_id),
+        handle_table.mutex(mutex_id),
+    ) {
+        (Ok(condvar), Ok(mutex)) => (condvar, mutex),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    drop(handle_table);
+    // the mutex is given back while waiting
+    if let Err(err) = mutex.unlock() {
+        return err;
+    }
+    let task = CurrentTask::get();
+    let tid = task.tid().as_usize();
+    let res = Resource::mutex(&mutex);
+    proc.deadlock.lock().release(tid, res);
+    drop(proc);
+    let signaled = condvar.wait_timeout(mutex, deadline_ms);
+    let proc = task.proc();
+    proc.deadlock.lock().acquire(tid, res);
+    match signaled {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+pub fn sys_rwlock_create() -> isize {
+    let proc = CurrentTask::get().proc();
+    proc.alloc_handle(Object::RwLock(Arc::new(RwLock::new())))
+        .map_or(-EMFILE, |id| id as isize)
+}
+
+pub fn sys_rwlock_read(rwlock_id: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let rwlock = match proc.handle_table.lock().rwlock(rwlock_id) {
+        Ok(rwlock) => rwlock,
+        Err(err) => return err,
+    };
+    drop(proc);
+    rwlock.read();
+    0
+}
+
+pub fn sys_rwlock_write(rwlock_id: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let rwlock = match proc.handle_table.lock().rwlock(rwlock_id) {
+        Ok(rwlock) => rwlock,
+        Err(err) => return err,
+    };
+    drop(proc);
+    rwlock.write();
+    0
+}
+
+/// Fails with `EINVAL` if the lock is not held, and with `EPERM` if another
+/// task holds it for writing.
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let rwlock = match proc.handle_table.lock().rwlock(rwlock_id) {
+        Ok(rwlock) => rwlock,
+        Err(err) => return err,
+    };
+    match rwlock.unlock() {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// Fails with `EINVAL` if `count` is zero.
+pub fn sys_barrier_create(count: usize) -> isize {
+    if count == 0 {
+        return -EINVAL;
+    }
+    let proc = CurrentTask::get().proc();
+    proc.alloc_handle(Object::Barrier(Arc::new(Barrier::new(count))))
+        .map_or(-EMFILE, |id| id as isize)
+}
+
+/// Returns 1 for the last task arrived at the barrier, and 0 for the others.
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    let proc = CurrentTask::get().proc();
+    let barrier = match proc.handle_table.lock().barrier(barrier_id) {
+        Ok(barrier) => barrier,
+        Err(err) => return err,
+    };
+    drop(proc);
+    barrier.wait() as isize
+}
+
+/// With the leading `/` and the trailing nul.
+const NAME_BUF_LEN: usize = NAME_MAX + 2;
+
+/// Limits and defaults of the message queues.
+const MQ_MAXMSG_MAX: usize = 64;
+const MQ_MSGSIZE_MAX: usize = 8192;
+const MQ_DEFAULT_MAXMSG: usize = 10;
+const MQ_DEFAULT_MSGSIZE: usize = 8192;
+const MQ_PRIO_MAX: u32 = 32768;
+
+fn read_name(name: &UserInPtr<u8>) -> Result<String, isize> {
+    if !name.check() {
+        return Err(-EFAULT);
+    }
+    let (buf, len) = name.read_str::<NAME_BUF_LEN>();
+    core::str::from_utf8(&buf[..len])
+        .map(String::from)
+        .map_err(|_| -EINVAL)
+}
+
+/// Opens the semaphore `name` shared by all processes, which is created with
+/// `value` by `OpenFlags::CREATE`. Returns a semaphore handle.
+pub fn sys_sem_open(name: UserInPtr<u8>, flags: u32, value: usize) -> isize {
+    let name = match read_name(&name) {
+        Ok(name) => name,
+        Err(err) => return err,
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let sem = match NAMED_SEMAPHORES.open(&name, flags, || Ok(Semaphore::new(value))) {
+        Ok(sem) => sem,
+        Err(err) => return err,
+    };
+    let count = sem.inner.lock().count.max(0) as usize;
+    let res = Resource::semaphore(&sem);
+    let proc = CurrentTask::get().proc();
+    match proc.alloc_handle(Object::Semaphore(sem)) {
+        Some(id) => {
+            proc.deadlock.lock().add_resource(res, count);
+            id as isize
+        }
+        None => -EMFILE,
+    }
+}
+
+/// Removes the name of a semaphore, which lives on while opened.
+pub fn sys_sem_unlink(name: UserInPtr<u8>) -> isize {
+    match read_name(&name).and_then(|name| NAMED_SEMAPHORES.unlink(&name)) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// Opens the message queue `name` shared by all processes, which is created
+/// by `OpenFlags::CREATE` to hold `max_msgs` messages of up to `msg_size`
+/// bytes, or the defaults if zero. The handle can receive, send or both by
+/// the access mode, and does not block with `OpenFlags::NONBLOCK`.
+pub fn sys_mq_open(name: UserInPtr<u8>, flags: u32, max_msgs: usize, msg_size: usize) -> isize {
+    let name = match read_name(&name) {
+        Ok(name) => name,
+        Err(err) => return err,
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let rights = if flags.contains(OpenFlags::RDWR) {
+        Rights::READ | Rights::WRITE
+    } else if flags.contains(OpenFlags::WRONLY) {
+        Rights::WRITE
+    } else {
+        Rights::READ
+    };
+    let create = || {
+        let max_msgs = if max_msgs == 0 {
+            MQ_DEFAULT_MAXMSG
+        } else {
+            max_msgs
+        };
+        let msg_size = if msg_size == 0 {
+            MQ_DEFAULT_MSGSIZE
+        } else {
+            msg_size
+        };
+        if max_msgs > MQ_MAXMSG_MAX || msg_size > MQ_MSGSIZE_MAX {
+            return Err(-EINVAL);
+        }
+        Ok(MessageQueue::new(max_msgs, msg_size))
+    };
+    let mq = match MESSAGE_QUEUES.open(&name, flags, create) {
+        Ok(mq) => mq,
+        Err(err) => return err,
+    };
+    let proc = CurrentTask::get().proc();
+    let nonblock = flags.contains(OpenFlags::NONBLOCK);
+    match proc.alloc_handle(Object::MessageQueue { mq, nonblock }) {
+        Some(id) => {
+            let rights = rights | Rights::INHERIT;
+            proc.handle_table
+                .lock()
+                .restrict(id, rights.bits())
+                .unwrap();
+            id as isize
+        }
+        None => -EMFILE,
+    }
+}
+
+/// Removes the name of a message queue, which lives on while opened.
+pub fn sys_mq_unlink(name: UserInPtr<u8>) -> isize {
+    match read_name(&name).and_then(|name| MESSAGE_QUEUES.unlink(&name)) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// Sends `len` bytes at `buf` with priority `prio`, which is less than
+/// `MQ_PRIO_MAX`. Fails with `EMSGSIZE` if `len` is more than the message
+/// size of the queue.
+pub fn sys_mq_send(mqd: usize, buf: UserInPtr<u8>, len: usize, prio: u32) -> isize {
+    if prio >= MQ_PRIO_MAX {
+        return -EINVAL;
+    }
+    let proc = CurrentTask::get().proc();
+    let (mq, nonblock) = match proc.handle_table.lock().message_queue(mqd, Rights::WRITE) {
+        Ok(mq) => mq,
+        Err(err) => return err,
+    };
+    drop(proc);
+    if len > mq.msg_size {
+        return -EMSGSIZE;
+    }
+    let msg = match buf.as_slice(len) {
+        Ok(msg) => msg.to_vec(),
+        Err(_) => return -EFAULT,
+    };
+    match mq.send(msg, prio, nonblock) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// Receives the first message into `buf` of `len` bytes, which is at least
+/// the message size of the queue, and its priority into `prio` if not null.
+/// Returns the length of the message.
+pub fn sys_mq_receive(
+    mqd: usize,
+    mut buf: UserOutPtr<u8>,
+    len: usize,
+    mut prio: UserOutPtr<u32>,
+) -> isize {
+    let proc = CurrentTask::get().proc();
+    let (mq, nonblock) = match proc.handle_table.lock().message_queue(mqd, Rights::READ) {
+        Ok(mq) => mq,
+        Err(err) => return err,
+    };
+    drop(proc);
+    if len < mq.msg_size {
+        return -EMSGSIZE;
+    }
+    if !buf.check_len(len) || !(prio.is_null() || prio.check()) {
+        return -EFAULT;
+    }
+    match mq.receive(nonblock) {
+        Ok((msg, msg_prio)) => {
+            buf.write_buf(&msg);
+            if !prio.is_null() {
+                prio.write(msg_prio);
+            }
+            msg.len() as isize
+        }
+        Err(err) => err,
+    }
+}
+
+/// Makes `sys_mutex_lock` and `sys_semaphore_down` of the current process
+/// fail with `EDEADLK` instead of blocking, if granting the request would
+/// make the state unsafe by the banker's algorithm.
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    if enabled > 1 {
+        return -EINVAL;
+    }
     let proc = CurrentTask::get().proc();
-    let condv_inner = proc.condvar_list.lock();
-    let mutex_inner = proc.mutex_list.lock();
-    let condvar = Arc::clone(condv_inner[condvar_id].as_ref().unwrap());
-    let mutex = Arc::clone(mutex_inner[mutex_id].as_ref().unwrap());
-    drop(mutex_inner);
-    drop(condv_inner);
-    condvar.wait(mutex);
+    proc.deadlock.lock().set_enabled(enabled == 1);
     0
 }
@@ -1,7 +1,21 @@
-use crate::task::{spawn_task, CurrentTask};
+use super::errno::{EAGAIN, EINVAL, ENOMEM, EPERM};
+use crate::config::USER_STACK_SIZE;
+use crate::sync::update_priority;
+use crate::task::{spawn_task, CurrentTask, MAX_PRIORITY, RLIMIT_AS, RLIMIT_STACK};
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     let proc = CurrentTask::get().proc();
+    if proc.nproc_exceeded() {
+        return -EAGAIN;
+    }
+    // the new thread maps a user stack
+    let rlimits = proc.rlimits.lock().clone();
+    let vm_size = proc.vm().lock().size();
+    if USER_STACK_SIZE > rlimits.get(RLIMIT_STACK)
+        || vm_size + USER_STACK_SIZE > rlimits.get(RLIMIT_AS)
+    {
+        return -ENOMEM;
+    }
     // create a new thread
     let new_task = proc.new_user_task(entry, arg);
     let tid = new_task.tid();
@@ -13,9 +27,30 @@ pub fn sys_gettid() -> isize {
     CurrentTask::get().tid().as_usize() as _
 }
 
+/// Sets the base priority of the current task, in `0..=MAX_PRIORITY`. Fails
+/// with `EPERM` if it is higher than the current one, which can only be
+/// lowered.
+pub fn sys_set_priority(prio: usize) -> isize {
+    if prio > MAX_PRIORITY {
+        return -EINVAL;
+    }
+    let task = CurrentTask::get();
+    if prio > task.base_priority() {
+        return -EPERM;
+    }
+    task.set_base_priority(prio);
+    update_priority(&task);
+    0
+}
+
+/// The effective priority of the current task, raised by the waiters of the
+/// PI mutexes which it holds.
+pub fn sys_get_priority() -> isize {
+    CurrentTask::get().priority() as _
+}
+
 /// thread does not exist, return -1
-/// thread has not exited yet, return -2
-/// otherwise, return thread's exit code
+/// otherwise, block until the thread exits and return its exit code
 pub fn sys_waittid(tid: usize) -> i32 {
     let task = CurrentTask::get();
     let proc = task.proc();
@@ -0,0 +1,229 @@
+//! Per-process handle table, holding the files and the sync objects in one
+//! number space. A file descriptor is a handle to a file.
+
+use crate::fs::{File, Stdin, Stdout};
+use crate::sync::{Barrier, Condvar, MessageQueue, RwLock, Semaphore, UserMutex};
+use crate::syscall::errno::{EBADF, EINVAL};
+use alloc::{sync::Arc, vec::Vec};
+use bitflags::bitflags;
+
+bitflags! {
+    /// What can be done through a handle.
+    pub struct Rights: u32 {
+        /// Read from the file, or receive from the message queue.
+        const READ = 1;
+        /// Write to the file, or send to the message queue.
+        const WRITE = 1 << 1;
+        /// Copied to the child by `fork` and `spawn`.
+        const INHERIT = 1 << 2;
+    }
+}
+
+#[derive(Clone)]
+pub enum Object {
+    File(Arc<dyn File + Send + Sync>),
+    Mutex(Arc<dyn UserMutex>),
+    Semaphore(Arc<Semaphore>),
+    Condvar(Arc<Condvar>),
+    RwLock(Arc<RwLock>),
+    Barrier(Arc<Barrier>),
+    MessageQueue {
+        mq: Arc<MessageQueue>,
+        nonblock: bool,
+    },
+}
+
+#[derive(Clone)]
+pub struct Handle {
+    pub object: Object,
+    pub rights: Rights,
+}
+
+impl Handle {
+    /// A handle with all the rights which apply to `object`. The sync objects
+    /// are private to the process, without `Rights::INHERIT`, as their
+    /// holders and waiters are tasks of the process.
+    pub fn new(object: Object) -> Self {
+        let rights = match &object {
+            Object::File(file) => {
+                let mut rights = Rights::INHERIT;
+                rights.set(Rights::READ, file.readable());
+                rights.set(Rights::WRITE, file.writable());
+                rights
+            }
+            Object::MessageQueue { .. } => Rights::READ | Rights::WRITE | Rights::INHERIT,
+            Object::Mutex(_)
+            | Object::Semaphore(_)
+            | Object::Condvar(_)
+            | Object::RwLock(_)
+            | Object::Barrier(_) => Rights::empty(),
+        };
+        Self { object, rights }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct HandleTable {
+    handles: Vec<Option<Handle>>,
+}
+
+impl HandleTable {
+    /// A table with stdin, stdout and stderr as handles 0, 1 and 2.
+    pub fn with_stdio() -> Self {
+        let stdin: Arc<dyn File + Send + Sync> = Arc::new(Stdin);
+        let stdout: Arc<dyn File + Send + Sync> = Arc::new(Stdout);
+        Self {
+            handles: alloc::vec![
+                Some(Handle::new(Object::File(stdin))),
+                Some(Handle::new(Object::File(stdout.clone()))),
+                Some(Handle::new(Object::File(stdout))),
+            ],
+        }
+    }
+
+    /// The handles which have `Rights::INHERIT`, at the same numbers.
+    pub fn inherited(&self) -> Self {
+        let handles = self
+            .handles
+            .iter()
+            .map(|h| h.clone().filter(|h| h.rights.contains(Rights::INHERIT)))
+            .collect();
+        Self { handles }
+    }
+
+    /// The objects of all the handles, once per handle.
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.handles.iter().flatten().map(|h| &h.object)
+    }
+
+    /// Fails with `EBADF` if there is no such handle.
+    pub fn get(&self, handle: usize) -> Result<&Handle, isize> {
+        self.handles
+            .get(handle)
+            .and_then(|h| h.as_ref())
+            .ok_or(-EBADF)
+    }
+
+    /// Puts `handle` at the lowest free number, returns `None` if the number
+    /// would reach `max`.
+    pub fn alloc(&mut self, handle: Handle, max: usize) -> Option<usize> {
+        let num = match self.handles.iter().position(|h| h.is_none()) {
+            Some(num) => num,
+            None => self.handles.len(),
+        };
+        if num >= max {
+            return None;
+        }
+        self.set(num, Some(handle));
+        Some(num)
+    }
+
+    /// Replaces the handle at number `num`, which can be beyond the end.
+    pub fn set(&mut self, num: usize, handle: Option<Handle>) {
+        if num >= self.handles.len() {
+            self.handles.resize(num + 1, None);
+        }
+        self.handles[num] = handle;
+    }
+
+    /// Fails with `EBADF` if there is no such handle.
+    pub fn remove(&mut self, handle: usize) -> Result<Handle, isize> {
+        self.handles
+            .get_mut(handle)
+            .and_then(|h| h.take())
+            .ok_or(-EBADF)
+    }
+
+    /// Keeps only `rights` of the handle, returns the rights left. Fails with
+    /// `EINVAL` if `rights` has unknown bits.
+    pub fn restrict(&mut self, handle: usize, rights: u32) -> Result<Rights, isize> {
+        let rights = Rights::from_bits(rights).ok_or(-EINVAL)?;
+        let h = self
+            .handles
+            .get_mut(handle)
+            .and_then(|h| h.as_mut())
+            .ok_or(-EBADF)?;
+        h.rights &= rights;
+        Ok(h.rights)
+    }
+
+    /// Fails with `EBADF` if there is no such handle or it lacks `rights`,
+    /// and with `EINVAL` if it is not a file.
+    pub fn file(
+        &self,
+        handle: usize,
+        rights: Rights,
+    ) -> Result<Arc<dyn File + Send + Sync>, isize> {
+        let h = self.get(handle)?;
+        if !h.rights.contains(rights) {
+            return Err(-EBADF);
+        }
+        match &h.object {
+            Object::File(file) => Ok(file.clone()),
+            _ => Err(-EINVAL),
+        }
+    }
+
+    /// Fails with `EBADF` if there is no such handle, and with `EINVAL` if it
+    /// is not a mutex.
+    pub fn mutex(&self, handle: usize) -> Result<Arc<dyn UserMutex>, isize> {
+        match &self.get(handle)?.object {
+            Object::Mutex(mutex) => Ok(mutex.clone()),
+            _ => Err(-EINVAL),
+        }
+    }
+
+    /// Fails with `EBADF` if there is no such handle, and with `EINVAL` if it
+    /// is not a semaphore.
+    pub fn semaphore(&self, handle: usize) -> Result<Arc<Semaphore>, isize> {
+        match &self.get(handle)?.object {
+            Object::Semaphore(sem) => Ok(sem.clone()),
+            _ => Err(-EINVAL),
+        }
+    }
+
+    /// Fails with `EBADF` if there is no such handle, and with `EINVAL` if it
+    /// is not a condvar.
+    pub fn condvar(&self, handle: usize) -> Result<Arc<Condvar>, isize> {
+        match &self.get(handle)?.object {
+            Object::Condvar(condvar) => Ok(condvar.clone()),
+            _ => Err(-EINVAL),
+        }
+    }
+
+    /// Fails with `EBADF` if there is no such handle, and with `EINVAL` if it
+    /// is not a reader-writer lock.
+    pub fn rwlock(&self, handle: usize) -> Result<Arc<RwLock>, isize> {
+        match &self.get(handle)?.object {
+            Object::RwLock(rwlock) => Ok(rwlock.clone()),
+            _ => Err(-EINVAL),
+        }
+    }
+
+    /// Fails with `EBADF` if there is no such handle, and with `EINVAL` if it
+    /// is not a barrier.
+    pub fn barrier(&self, handle: usize) -> Result<Arc<Barrier>, isize> {
+        match &self.get(handle)?.object {
+            Object::Barrier(barrier) => Ok(barrier.clone()),
+            _ => Err(-EINVAL),
+        }
+    }
+
+    /// The message queue and whether it is in non-blocking mode. Fails with
+    /// `EBADF` if there is no such handle or it lacks `rights`, and with
+    /// `EINVAL` if it is not a message queue.
+    pub fn message_queue(
+        &self,
+        handle: usize,
+        rights: Rights,
+    ) -> Result<(Arc<MessageQueue>, bool), isize> {
+        let h = self.get(handle)?;
+        if !h.rights.contains(rights) {
+            return Err(-EBADF);
+        }
+        match &h.object {
+            Object::MessageQueue { mq, nonblock } => Ok((mq.clone(), *nonblock)),
+            _ => Err(-EINVAL),
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::cell::UnsafeCell;
 
 use super::percpu::PerCpu;
-use super::schedule::{Scheduler, SimpleScheduler};
+use super::schedule::{PriorityScheduler, Scheduler};
+use super::signal::SigInfo;
 use super::structs::{CurrentTask, Process, Task, TaskState, ROOT_PROC};
 use crate::sync::{LazyInit, SpinNoIrqLock};
 
@@ -21,6 +23,20 @@ impl<S: Scheduler> TaskManager<S> {
         self.scheduler.add_ready_task(&t);
     }
 
+    /// Removes `t` from the scheduler if it is ready, returns whether it was.
+    pub fn remove_ready(&mut self, t: &Arc<Task>) -> bool {
+        if t.state() == TaskState::Ready {
+            self.scheduler.remove_ready_task(t);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn timer_tick(&mut self) {
+        self.scheduler.timer_tick();
+    }
+
     fn switch_to(&self, curr_task: &Arc<Task>, next_task: Arc<Task>) {
         next_task.set_state(TaskState::Running);
         if Arc::ptr_eq(curr_task, &next_task) {
@@ -32,6 +48,12 @@ impl<S: Scheduler> TaskManager<S> {
 
         assert!(Arc::strong_count(curr_task) > 1);
         assert!(Arc::strong_count(&next_task) > 1);
+        curr_task.times.lock().account_system();
+        next_task.times.lock().switch_in();
+        unsafe {
+            curr_task.save_fp();
+            next_task.restore_fp();
+        }
         PerCpu::current().set_current_task(next_task);
 
         unsafe { (&mut *curr_ctx_ptr).switch_to(&*next_ctx_ptr) };
@@ -130,13 +152,34 @@ pub fn pid2proc(id: usize) -> Option<Arc<Process>> {
     PROC_MAP.lock().get(&id).cloned()
 }
 
-pub(super) static TASK_MANAGER: LazyInit<SpinNoIrqLock<TaskManager<SimpleScheduler>>> =
+pub fn pgid2procs(pgid: usize) -> Vec<Arc<Process>> {
+    PROC_MAP
+        .lock()
+        .values()
+        .filter(|p| p.pgid() == pgid)
+        .cloned()
+        .collect()
+}
+
+/// Sends the signal described by `info` to all processes in the process group
+/// `pgid`, returns false if there is no such group.
+pub fn kill_pgrp(pgid: usize, info: SigInfo) -> bool {
+    let procs = pgid2procs(pgid);
+    for proc in procs.iter() {
+        proc.send_signal_info(info);
+    }
+    !procs.is_empty()
+}
+
+pub(super) static TASK_MANAGER: LazyInit<SpinNoIrqLock<TaskManager<PriorityScheduler>>> =
     LazyInit::new();
 
 pub(super) static PROC_MAP: LazyInit<SpinNoIrqLock<BTreeMap<usize, Arc<Process>>>> =
     LazyInit::new();
 
 pub(super) fn init() {
-    TASK_MANAGER.init_by(SpinNoIrqLock::new(TaskManager::new(SimpleScheduler::new())));
+    TASK_MANAGER.init_by(SpinNoIrqLock::new(TaskManager::new(
+        PriorityScheduler::new(),
+    )));
     PROC_MAP.init_by(SpinNoIrqLock::new(BTreeMap::new()));
 }
@@ -1,18 +1,25 @@
+mod handle;
 mod manager;
 mod percpu;
+mod rlimit;
 mod schedule;
 mod signal;
 mod structs;
 mod switch;
+mod times;
 
 use alloc::sync::Arc;
 
-pub use manager::pid2proc;
+pub use handle::{Handle, HandleTable, Object, Rights};
+pub use manager::{kill_pgrp, pgid2procs, pid2proc};
+pub use rlimit::*;
+pub use schedule::{DEFAULT_PRIORITY, MAX_PRIORITY};
 pub use signal::*;
-pub use structs::{CurrentTask, ProcId, Task, TaskState};
+pub use structs::{CloneFlags, CurrentTask, ProcId, Process, Task, TaskState, WaitOptions};
+pub use times::{ProcTimes, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL};
 
 use self::manager::{PROC_MAP, TASK_MANAGER};
-use self::structs::{Process, ROOT_PROC};
+use self::structs::ROOT_PROC;
 
 pub fn init() {
     percpu::init_percpu();
@@ -22,8 +29,8 @@ pub fn init() {
         |_| loop {
             let curr_task = CurrentTask::get();
             let curr_proc = curr_task.proc();
-            let mut exit_code = 0;
-            while curr_proc.waitpid(-1, &mut exit_code) > 0 {}
+            let mut exit_status = 0;
+            while curr_proc.waitpid(-1, &mut exit_status, WaitOptions::WNOHANG) > 0 {}
             if curr_proc.children.lock().len() == 0 {
                 crate::arch::wait_for_ints();
             } else {
@@ -42,13 +49,20 @@ pub fn init() {
         0
     };
 
-    let mut m = TASK_MANAGER.lock();
+    // the root task yields in a loop while there are children, so it must
+    // not keep the tasks of lower priorities from running
     let root_task = ROOT_PROC.task();
+    root_task.set_base_priority(0);
+    root_task.set_priority(0);
+
+    let mut m = TASK_MANAGER.lock();
     assert!(root_task.is_root());
     m.spawn(root_task);
     m.spawn(Process::new_kernel(test_kernel_task, 0xdead).task());
     m.spawn(Process::new_kernel(test_kernel_task, 0xbeef).task());
-    m.spawn(Process::new_user("user_shell").task());
+    let shell = Process::new_user("user_shell");
+    spawn_proc(shell.clone());
+    m.spawn(shell.task());
 }
 
 pub fn spawn_proc(proc: Arc<Process>) {
@@ -59,6 +73,11 @@ pub fn spawn_task(task: Arc<Task>) {
     TASK_MANAGER.lock().spawn(task);
 }
 
+/// Counts a timer tick for the scheduler.
+pub fn timer_tick() {
+    TASK_MANAGER.lock().timer_tick();
+}
+
 pub fn run() -> ! {
     crate::arch::enable_irqs();
     CurrentTask::get().yield_now(); // current task is idle at this time
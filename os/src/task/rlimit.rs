@@ -0,0 +1,53 @@
+//! Per-process resource limits.
+
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_FSIZE: usize = 1;
+pub const RLIMIT_DATA: usize = 2;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIMIT_SIGPENDING: usize = 11;
+pub const RLIM_NLIMITS: usize = 16;
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// Soft and hard limits of a resource, the same layout as `struct rlimit`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rlimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+impl Rlimit {
+    const fn new(cur: usize, max: usize) -> Self {
+        Self { cur, max }
+    }
+
+    const fn infinity() -> Self {
+        Self::new(RLIM_INFINITY, RLIM_INFINITY)
+    }
+}
+
+#[derive(Clone)]
+pub struct Rlimits {
+    pub table: [Rlimit; RLIM_NLIMITS],
+}
+
+impl Default for Rlimits {
+    fn default() -> Self {
+        let mut table = [Rlimit::infinity(); RLIM_NLIMITS];
+        table[RLIMIT_STACK] = Rlimit::new(0x80_0000, RLIM_INFINITY);
+        table[RLIMIT_NPROC] = Rlimit::new(256, 256);
+        table[RLIMIT_NOFILE] = Rlimit::new(1024, 4096);
+        table[RLIMIT_SIGPENDING] = Rlimit::new(1024, 1024);
+        Self { table }
+    }
+}
+
+impl Rlimits {
+    pub fn get(&self, resource: usize) -> usize {
+        self.table[resource].cur
+    }
+}
@@ -1,60 +1,102 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 use super::structs::{Task, TaskState};
 
+/// The highest priority of tasks.
+pub const MAX_PRIORITY: usize = 31;
+/// The priority of new tasks.
+pub const DEFAULT_PRIORITY: usize = 16;
+/// Timer ticks after which a ready task goes before those of higher
+/// priorities, so that a task yielding in a loop until a lower one makes
+/// progress does not starve it.
+const STARVATION_TICKS: usize = 10;
+
 pub trait Scheduler {
     fn add_ready_task(&mut self, t: &Arc<Task>);
     fn pick_next_task(&mut self) -> Option<Arc<Task>>;
+    /// Removes a ready task, before its priority changes.
+    fn remove_ready_task(&mut self, t: &Arc<Task>);
     fn block_task(&mut self, t: &Arc<Task>);
     fn timer_tick(&mut self);
 }
 
 struct SchedulerState {
     task: Arc<Task>,
+    // the tick when the task became ready
+    ready_tick: usize,
 }
 
 impl SchedulerState {
-    fn new(task: Arc<Task>) -> Self {
-        Self { task }
+    fn new(task: Arc<Task>, ready_tick: usize) -> Self {
+        Self { task, ready_tick }
     }
 }
 
-pub struct SimpleScheduler {
-    ready_queue: VecDeque<SchedulerState>,
+/// Ready tasks in one queue per priority. The highest priority is picked
+/// first, and tasks of the same priority take turns, unless a task has been
+/// ready for `STARVATION_TICKS`, then the one ready for the longest goes first.
+pub struct PriorityScheduler {
+    ready_queues: Vec<VecDeque<SchedulerState>>,
+    ticks: usize,
 }
 
-impl SimpleScheduler {
+impl PriorityScheduler {
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
+            ready_queues: (0..=MAX_PRIORITY).map(|_| VecDeque::new()).collect(),
+            ticks: 0,
+        }
+    }
+
+    /// Drops the stale entries at the front of `queue`, so that it starts
+    /// with a ready task if any.
+    fn skip_stale(queue: &mut VecDeque<SchedulerState>) {
+        while let Some(front) = queue.front() {
+            match front.task.state() {
+                TaskState::Ready => break,
+                TaskState::Running => panic!("Invalid TaskState"),
+                TaskState::Zombie | TaskState::Blocking => {
+                    queue.pop_front();
+                }
+            }
         }
     }
 }
 
-impl Scheduler for SimpleScheduler {
+impl Scheduler for PriorityScheduler {
     fn add_ready_task(&mut self, t: &Arc<Task>) {
-        self.ready_queue.push_back(SchedulerState::new(t.clone()));
+        self.ready_queues[t.priority()].push_back(SchedulerState::new(t.clone(), self.ticks));
     }
 
     fn pick_next_task(&mut self) -> Option<Arc<Task>> {
-        loop {
-            if let Some(task) = self.ready_queue.pop_front() {
-                match task.task.state() {
-                    TaskState::Ready => break Some(task.task),
-                    TaskState::Running => panic!("Invalid TaskState"),
-                    TaskState::Zombie => continue,
-                    TaskState::Blocking => continue,
-                }
-            } else {
-                break None;
-            }
-        }
+        self.ready_queues.iter_mut().for_each(Self::skip_stale);
+        let starved = self
+            .ready_queues
+            .iter()
+            .enumerate()
+            .filter_map(|(prio, queue)| queue.front().map(|s| (s.ready_tick, prio)))
+            .filter(|&(ready_tick, _)| self.ticks - ready_tick >= STARVATION_TICKS)
+            .min();
+        let prio = match starved {
+            Some((_, prio)) => prio,
+            None => (0..=MAX_PRIORITY)
+                .rev()
+                .find(|&prio| !self.ready_queues[prio].is_empty())?,
+        };
+        self.ready_queues[prio].pop_front().map(|s| s.task)
+    }
+
+    fn remove_ready_task(&mut self, t: &Arc<Task>) {
+        self.ready_queues[t.priority()].retain(|s| !Arc::ptr_eq(&s.task, t));
     }
 
     fn block_task(&mut self, t: &Arc<Task>) {
         t.set_state(TaskState::Blocking);
     }
 
-    fn timer_tick(&mut self) {}
+    fn timer_tick(&mut self) {
+        self.ticks += 1;
+    }
 }
@@ -1,9 +1,15 @@
+use crate::trap::{FpState, TrapFrame};
+use alloc::collections::VecDeque;
 use bitflags::*;
 
-pub const MAX_SIG: usize = 31;
+pub const MAX_SIG: usize = 63;
+/// The first real-time signal. The real-time signals up to `MAX_SIG` have no
+/// names, and are queued rather than merged when sent again before they are
+/// delivered.
+pub const SIGRTMIN: usize = 32;
 
 bitflags! {
-    pub struct SignalFlags: u32 {
+    pub struct SignalFlags: u64 {
         const SIGDEF = 1; // Default signal handling
         const SIGHUP = 1 << 1;
         const SIGINT = 1 << 2;
@@ -36,40 +42,96 @@ bitflags! {
         const SIGIO = 1 << 29;
         const SIGPWR = 1 << 30;
         const SIGSYS = 1 << 31;
+        // Real-time signals, from `SIGRTMIN` to `MAX_SIG`
+        const SIGRT = 0xffff_ffff << SIGRTMIN;
 
-        const KERNEL_SIGNAL = Self::SIGKILL.bits | Self::SIGSTOP.bits | Self::SIGCONT.bits | Self::SIGDEF.bits;
+        // Signals whose action cannot be changed, and which cannot be ignored
+        const UNCATCHABLE = Self::SIGKILL.bits | Self::SIGSTOP.bits;
         const TRAP_QUIT = Self::SIGQUIT.bits | Self::SIGTRAP.bits;
-
+        // Stop signals from the terminal, which can be caught
+        const TTY_STOP = Self::SIGTSTP.bits | Self::SIGTTIN.bits | Self::SIGTTOU.bits;
+        const STOP = Self::SIGSTOP.bits | Self::TTY_STOP.bits;
+        // Signals terminating the process with a core dump by default
+        const CORE = Self::SIGQUIT.bits | Self::SIGILL.bits | Self::SIGTRAP.bits
+            | Self::SIGABRT.bits | Self::SIGBUS.bits | Self::SIGFPE.bits | Self::SIGSEGV.bits
+            | Self::SIGXCPU.bits | Self::SIGXFSZ.bits | Self::SIGSYS.bits;
+        // Signals ignored by default
+        const IGNORE = Self::SIGCHLD.bits | Self::SIGURG.bits | Self::SIGWINCH.bits;
     }
 }
 
+/// `how` of `sigprocmask` to block the signals in the set.
+pub const SIG_BLOCK: usize = 0;
+/// `how` of `sigprocmask` to unblock the signals in the set.
+pub const SIG_UNBLOCK: usize = 1;
+/// `how` of `sigprocmask` to block exactly the signals in the set.
+pub const SIG_SETMASK: usize = 2;
+
+/// `SignalAction::handler` for the default action of the signal.
+pub const SIG_DFL: usize = 0;
+/// `SignalAction::handler` to ignore the signal.
+pub const SIG_IGN: usize = 1;
+
+/// Action of a signal without a handler, as in POSIX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// Terminates the process.
+    Terminate,
+    /// Terminates the process, whose wait status tells it dumped core.
+    Core,
+    /// Stops the process until `SIGCONT`.
+    Stop,
+    /// Continues the process if stopped, which is done when the signal is sent.
+    Continue,
+    Ignore,
+}
+
 impl SignalFlags {
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
-        } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
-        } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
-        } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
-        } else if self.contains(Self::SIGKILL) {
-            Some((-9, "Killed, SIGKILL=9"))
-        } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
+    /// The default action of a single signal.
+    pub fn default_action(&self) -> DefaultAction {
+        if Self::CORE.contains(*self) {
+            DefaultAction::Core
+        } else if Self::STOP.contains(*self) {
+            DefaultAction::Stop
+        } else if *self == Self::SIGCONT {
+            DefaultAction::Continue
+        } else if Self::IGNORE.contains(*self) {
+            DefaultAction::Ignore
         } else {
-            //println!("[K] signalflags check_error  {:?}", self);
-            None
+            DefaultAction::Terminate
         }
     }
 }
 
+bitflags! {
+    /// Flags of a signal action, with the values of Linux.
+    pub struct SigActionFlags: u32 {
+        /// No `SIGCHLD` when a child stops or continues.
+        const SA_NOCLDSTOP = 1;
+        /// The handler is given the `SigInfo` and the `UContext` in `x1` and `x2`.
+        const SA_SIGINFO = 4;
+        /// The handler returns to `SignalAction::restorer`, which calls `sigreturn`.
+        const SA_RESTORER = 0x0400_0000;
+        /// The handler runs on the stack set by `sigaltstack`.
+        const SA_ONSTACK = 0x0800_0000;
+        /// The syscalls interrupted by the handler are restarted.
+        const SA_RESTART = 0x1000_0000;
+        /// The signal is not blocked while its handler runs.
+        const SA_NODEFER = 0x4000_0000;
+        /// The action is reset to the default when the handler is called.
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
 /// Action for a signal
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SignalAction {
     pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself.
     pub mask: SignalFlags,
+    pub flags: SigActionFlags,
+    pub restorer: usize,
 }
 
 impl Default for SignalAction {
@@ -77,6 +139,8 @@ impl Default for SignalAction {
         Self {
             handler: 0,
             mask: SignalFlags::from_bits(40).unwrap(),
+            flags: SigActionFlags::empty(),
+            restorer: 0,
         }
     }
 }
@@ -93,3 +157,250 @@ impl Default for SignalActions {
         }
     }
 }
+
+impl SignalActions {
+    /// Whether signal `sig` is ignored when delivered, by `SIG_IGN` or by
+    /// its default action. The other signals interrupt the blocking syscalls.
+    pub fn ignores(&self, sig: usize) -> bool {
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
+        if SignalFlags::UNCATCHABLE.contains(signal) {
+            return false;
+        }
+        match self.table[sig].handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(
+                signal.default_action(),
+                DefaultAction::Continue | DefaultAction::Ignore
+            ),
+            _ => false,
+        }
+    }
+
+    /// The actions after `exec`, where the handlers are reset to `SIG_DFL`,
+    /// while the ignored signals stay ignored.
+    pub fn after_exec(&self) -> Self {
+        let mut actions = Self::default();
+        for (action, old) in actions.table.iter_mut().zip(self.table.iter()) {
+            if old.handler == SIG_IGN {
+                *action = *old;
+            }
+        }
+        actions
+    }
+}
+
+/// `SigInfo::code` of a signal sent by `kill`.
+pub const SI_USER: i32 = 0;
+/// `SigInfo::code` of a signal sent by `tgkill`.
+pub const SI_TKILL: i32 = -6;
+/// `SigInfo::code` of a signal sent by the kernel.
+pub const SI_KERNEL: i32 = 0x80;
+/// `SigInfo::code` of `SIGCHLD` when the child exits.
+pub const CLD_EXITED: i32 = 1;
+/// `SigInfo::code` of `SIGCHLD` when the child is terminated by a signal.
+pub const CLD_KILLED: i32 = 2;
+/// `SigInfo::code` of `SIGCHLD` when the child is terminated by a signal and
+/// dumps core.
+pub const CLD_DUMPED: i32 = 3;
+/// `SigInfo::code` of `SIGCHLD` when the child is stopped.
+pub const CLD_STOPPED: i32 = 5;
+/// `SigInfo::code` of `SIGCHLD` when the child is continued.
+pub const CLD_CONTINUED: i32 = 6;
+
+/// `SigInfo::code` of `SIGILL` for an undefined instruction.
+pub const ILL_ILLOPC: i32 = 1;
+/// `SigInfo::code` of `SIGFPE` for a division by zero.
+pub const FPE_FLTDIV: i32 = 3;
+/// `SigInfo::code` of `SIGFPE` for an overflow.
+pub const FPE_FLTOVF: i32 = 4;
+/// `SigInfo::code` of `SIGFPE` for an underflow.
+pub const FPE_FLTUND: i32 = 5;
+/// `SigInfo::code` of `SIGFPE` for an inexact result.
+pub const FPE_FLTRES: i32 = 6;
+/// `SigInfo::code` of `SIGFPE` for an invalid operation.
+pub const FPE_FLTINV: i32 = 7;
+/// `SigInfo::code` of `SIGFPE` for another floating-point exception.
+pub const FPE_FLTUNK: i32 = 14;
+/// `SigInfo::code` of `SIGSEGV` for an address which is not mapped.
+pub const SEGV_MAPERR: i32 = 1;
+/// `SigInfo::code` of `SIGSEGV` for an access which is not permitted.
+pub const SEGV_ACCERR: i32 = 2;
+/// `SigInfo::code` of `SIGBUS` for a misaligned address.
+pub const BUS_ADRALN: i32 = 1;
+
+/// Information about a signal, kept from when it is sent until it is
+/// delivered.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    /// Process which sent the signal, or the child of `SIGCHLD`.
+    pub pid: i32,
+    /// Exit code of the child of `SIGCHLD`, or the signal which terminated,
+    /// stopped or continued it.
+    pub status: i32,
+    /// Faulting address of `SIGSEGV`, `SIGBUS`, `SIGILL` and `SIGFPE`.
+    pub addr: usize,
+    /// Value sent with the signal by `sigqueue`.
+    pub value: usize,
+}
+
+impl SigInfo {
+    /// Information about `signal` sent by the kernel.
+    pub fn kernel(signal: SignalFlags) -> Self {
+        Self {
+            signo: signal.bits().trailing_zeros() as i32,
+            code: SI_KERNEL,
+            ..Self::default()
+        }
+    }
+
+    /// Information about the fault `signal` with `code` at address `addr`,
+    /// see `SEGV_MAPERR`.
+    pub fn fault(signal: SignalFlags, code: i32, addr: usize) -> Self {
+        Self {
+            signo: signal.bits().trailing_zeros() as i32,
+            code,
+            addr,
+            ..Self::default()
+        }
+    }
+
+    /// Information about `SIGCHLD` from the child `pid`, see `CLD_EXITED`.
+    pub fn child(pid: usize, code: i32, status: i32) -> Self {
+        Self {
+            signo: SignalFlags::SIGCHLD.bits().trailing_zeros() as i32,
+            code,
+            pid: pid as i32,
+            status,
+            ..Self::default()
+        }
+    }
+}
+
+/// Signals pending for a task, or for a whole process.
+pub struct PendingSignals {
+    pub signals: SignalFlags,
+    // information about the pending signals
+    infos: [SigInfo; MAX_SIG + 1],
+    // information about the pending real-time signals, in the order sent
+    queue: VecDeque<SigInfo>,
+}
+
+impl PendingSignals {
+    pub fn new() -> Self {
+        Self {
+            signals: SignalFlags::empty(),
+            infos: [SigInfo::default(); MAX_SIG + 1],
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Makes the signal described by `info` pending. Returns false if it is
+    /// already pending and not real-time, or if `max` real-time signals are
+    /// already queued.
+    pub fn enqueue(&mut self, info: SigInfo, max: usize) -> bool {
+        let sig = info.signo as usize;
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
+        if SignalFlags::SIGRT.contains(signal) {
+            if self.queue.len() >= max {
+                return false;
+            }
+            self.queue.push_back(info);
+        } else if self.signals.contains(signal) {
+            return false;
+        } else {
+            self.infos[sig] = info;
+        }
+        self.signals.insert(signal);
+        true
+    }
+
+    /// Takes the pending signal `sig`, returns its information. A real-time
+    /// signal stays pending while more of it are queued.
+    pub fn dequeue(&mut self, sig: usize) -> SigInfo {
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
+        self.signals.remove(signal);
+        if !SignalFlags::SIGRT.contains(signal) {
+            return self.infos[sig];
+        }
+        let info = match self
+            .queue
+            .iter()
+            .position(|info| info.signo as usize == sig)
+        {
+            Some(pos) => self.queue.remove(pos).unwrap(),
+            None => SigInfo::kernel(signal),
+        };
+        if self.queue.iter().any(|info| info.signo as usize == sig) {
+            self.signals.insert(signal);
+        }
+        info
+    }
+
+    /// Discards the pending `signals`, with all queued instances of them.
+    pub fn discard(&mut self, signals: SignalFlags) {
+        self.signals.remove(signals);
+        self.queue
+            .retain(|info| !signals.contains(SignalFlags::from_bits(1 << info.signo).unwrap()));
+    }
+}
+
+/// `SignalStack::flags` when the task runs on the stack.
+pub const SS_ONSTACK: u32 = 1;
+/// `SignalStack::flags` when there is no stack.
+pub const SS_DISABLE: u32 = 2;
+/// Minimum size of a stack set by `sigaltstack`.
+pub const MINSIGSTKSZ: usize = 5120;
+
+/// Alternate stack for the handlers with `SA_ONSTACK`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalStack {
+    pub sp: usize,
+    pub flags: u32,
+    pub size: usize,
+}
+
+impl SignalStack {
+    pub const fn disabled() -> Self {
+        Self {
+            sp: 0,
+            flags: SS_DISABLE,
+            size: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.flags & SS_DISABLE == 0
+    }
+
+    /// Whether the user stack pointer `sp` is on this stack.
+    pub fn contains(&self, sp: usize) -> bool {
+        self.is_enabled() && sp > self.sp && sp <= self.sp + self.size
+    }
+}
+
+/// Context of the task interrupted by a handler. It is restored by
+/// `sigreturn`, including the changes made by the handler.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UContext {
+    pub stack: SignalStack,
+    pub mask: SignalFlags,
+    pub tf: TrapFrame,
+    pub fp: FpState,
+}
+
+/// Pushed onto the user stack when a handler is called, and popped by
+/// `sigreturn`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalFrame {
+    pub info: SigInfo,
+    pub uc: UContext,
+    /// Address of the frame of the interrupted handler, or 0.
+    pub prev: usize,
+}
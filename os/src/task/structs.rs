@@ -1,19 +1,35 @@
-use crate::config::{USER_STACK_SIZE, USER_STACK_TOP};
+use crate::config::USER_STACK_SIZE;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
-use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
-use core::sync::atomic::{AtomicI32, AtomicU8, AtomicUsize, Ordering};
+use alloc::{boxed::Box, vec::Vec};
+use bitflags::bitflags;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 
+use super::handle::{Handle, HandleTable, Object};
 use super::manager::{TaskLockedCell, PROC_MAP, TASK_MANAGER};
 use super::percpu::PerCpu;
-use super::signal::{SignalActions, SignalFlags, MAX_SIG};
+use super::rlimit::{
+    Rlimits, RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_SIGPENDING, RLIMIT_STACK,
+    RLIM_INFINITY,
+};
+use super::schedule::DEFAULT_PRIORITY;
+use super::signal::{
+    DefaultAction, PendingSignals, SigActionFlags, SigInfo, SignalAction, SignalActions,
+    SignalFlags, SignalFrame, SignalStack, UContext, CLD_CONTINUED, CLD_DUMPED, CLD_EXITED,
+    CLD_KILLED, CLD_STOPPED, MAX_SIG, SIG_DFL, SIG_IGN,
+};
 use super::switch::TaskContext;
+use super::times::{CpuTimes, ITimer, ProcTimes, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL};
 use crate::config::KERNEL_STACK_SIZE;
 use crate::fs::{open_file, OpenFlags};
-use crate::fs::{File, Stdin, Stdout};
-use crate::mm::{MapArea, MemFlags, MemorySet, PhysAddr, VirtAddr, PAGE_SIZE};
-use crate::sync::{Condvar, LazyInit, Mutex, Semaphore, UserMutex};
-use crate::trap::TrapFrame;
+use crate::mm::{MemorySet, PhysAddr, UserOutPtr, VirtAddr};
+use crate::sync::{futex_wake, DeadlockDetector, LazyInit, Mutex, PiLinks, WaitQueue};
+use crate::syscall::errno::{EAGAIN, EINTR, ENOENT, ENOMEM};
+use crate::timer::{
+    add_real_timer, add_timer, counter_to_secs, get_counter, get_time_ms, remove_timer,
+};
+use crate::trap::{FpState, TrapFrame};
 
 pub static ROOT_PROC: LazyInit<Arc<Process>> = LazyInit::new();
 
@@ -45,6 +61,57 @@ pub enum ProcState {
     Zombie = 3,
 }
 
+bitflags! {
+    /// Options of `waitpid`.
+    pub struct WaitOptions: u32 {
+        /// Return immediately if no child has exited.
+        const WNOHANG = 1;
+        /// Also return if a child has been stopped by a signal.
+        const WUNTRACED = 2;
+    }
+}
+
+bitflags! {
+    /// Flags of `clone`. The low byte is the exit signal, which is ignored.
+    pub struct CloneFlags: u32 {
+        /// Share the address space.
+        const CLONE_VM = 0x100;
+        /// Share the handle table.
+        const CLONE_FILES = 0x400;
+        /// Share the signal actions, requires `CLONE_VM`.
+        const CLONE_SIGHAND = 0x800;
+        /// Suspend the caller until the child calls `exec` or exits.
+        const CLONE_VFORK = 0x4000;
+        /// Create a task in the same process, requires `CLONE_SIGHAND`.
+        const CLONE_THREAD = 0x1_0000;
+        /// Set the thread pointer (`TPIDR_EL0`) of the new task.
+        const CLONE_SETTLS = 0x8_0000;
+        /// Write the new id to `parent_tid` in the parent.
+        const CLONE_PARENT_SETTID = 0x10_0000;
+        /// Clear `child_tid` in the child when it exits.
+        const CLONE_CHILD_CLEARTID = 0x20_0000;
+    }
+}
+
+/// Linux-style wait status of a child which exited with `exit_code`.
+pub const fn exited_status(exit_code: i32) -> i32 {
+    (exit_code & 0xff) << 8
+}
+
+/// Linux-style wait status of a child which was terminated by signal `signum`.
+pub const fn signaled_status(signum: usize) -> i32 {
+    (signum & 0x7f) as i32
+}
+
+/// Set in the wait status of a child which was terminated by a signal whose
+/// default action dumps core.
+pub const WCOREFLAG: i32 = 0x80;
+
+/// Linux-style wait status of a child which was stopped by signal `signum`.
+pub const fn stopped_status(signum: usize) -> i32 {
+    (((signum & 0xff) << 8) | 0x7f) as i32
+}
+
 pub struct Task {
     tid: TaskId,
     _is_kernel: bool,
@@ -54,39 +121,80 @@ pub struct Task {
     exit_code: AtomicI32,
     kstack: Stack<KERNEL_STACK_SIZE>,
     ctx: TaskLockedCell<TaskContext>,
+    // FP/SIMD registers of a user task while it is switched out
+    fp: TaskLockedCell<FpState>,
     pub signal: Mutex<SignalInner>,
+    pub times: Mutex<CpuTimes>,
+    // the priority set by the task, and the one raised by priority inheritance
+    base_priority: AtomicUsize,
+    priority: AtomicUsize,
+    pub pi_links: Mutex<PiLinks>,
+    // user address cleared when the task exits, set by `CLONE_CHILD_CLEARTID`
+    clear_child_tid: AtomicUsize,
+    // if the task is blocked by `block_interruptible`
+    interruptible: AtomicBool,
+    // the user stack mapped by `thread_create`, as its slot in the address
+    // space, which may be shared with other processes by `CLONE_VM`
+    ustack: Mutex<Option<(Weak<Mutex<MemorySet>>, usize)>>,
 }
 
+/// Handle table, shared by the processes created with `CLONE_FILES`.
+pub type SharedHandleTable = Arc<Mutex<HandleTable>>;
+
 pub struct Process {
     id: ProcId,
     is_kernel: bool,
     state: AtomicU8,
-    exit_code: AtomicI32,
-    pub vm: Mutex<Option<MemorySet>>,
+    // wait status reported to the parent, see `exited_status` and `signaled_status`
+    exit_status: AtomicI32,
+    // process group and session, for job control
+    pgid: AtomicUsize,
+    sid: AtomicUsize,
+    // the signal which stopped this process and has not been reported by `waitpid`, or 0
+    stop_signal: AtomicUsize,
+    // if all tasks are stopped by a signal until `SIGCONT`
+    stopped: AtomicBool,
+    // CPU times of removed tasks and of waited-for children
+    times: Mutex<ProcTimes>,
+    // the CPU seconds when `SIGXCPU` was sent last time
+    xcpu_secs: AtomicU64,
+    // set by `setitimer`, indexed by `ITIMER_REAL` etc.
+    itimers: Mutex<[ITimer; 3]>,
+    // shared by the processes created with `CLONE_VM`
+    vm: Mutex<Option<Arc<Mutex<MemorySet>>>>,
+    // woken when the process gives up the address space by `exec` or exit
+    vfork_queue: WaitQueue,
 
     pub tasks: Mutex<BTreeMap<usize, Arc<Task>>>,
-    tid_allocator: IdAllocator,
+    tid_allocator: RecycleAllocator,
 
     pub parent: Mutex<Weak<Process>>,
     pub children: Mutex<Vec<Arc<Process>>>,
-    pub fd_table: Mutex<Vec<Option<Arc<dyn File + Send + Sync>>>>,
-    pub signal_actions: Mutex<SignalActions>,
-    pub mutex_list: Mutex<Vec<Option<Arc<dyn UserMutex>>>>,
-    pub semaphore_list: Mutex<Vec<Option<Arc<Semaphore>>>>,
-    pub condvar_list: Mutex<Vec<Option<Arc<Condvar>>>>,
+    // woken when a child process or a task of this process becomes a zombie
+    pub wait_queue: WaitQueue,
+    pub handle_table: SharedHandleTable,
+    // shared by the processes created with `CLONE_SIGHAND`, until `exec`
+    signal_actions: Mutex<Arc<Mutex<SignalActions>>>,
+    // signals sent to the process, taken by any task which does not block them
+    pending_signals: Mutex<PendingSignals>,
+    pub rlimits: Mutex<Rlimits>,
+    pub deadlock: Mutex<DeadlockDetector>,
 }
 
 pub struct SignalInner {
-    pub signals: SignalFlags,
+    // signals sent to this task rather than to the process
+    pub pending: PendingSignals,
     pub signal_mask: SignalFlags,
-    // the signal which is being handling
-    pub handling_sig: Option<usize>,
-    // if the task is killed
-    pub killed: bool,
-    // if the task is frozen by a signal
+    // signals waited for by `sigtimedwait`, which wake the task even if blocked
+    pub sigwait: SignalFlags,
+    // the signal which kills the task
+    pub killed: Option<usize>,
+    // if the task is blocked until its stopped process is continued
     pub frozen: bool,
-    pub mask_backup: Option<SignalFlags>,
-    pub trapframe_backup: Option<TrapFrame>,
+    // user address of the `SignalFrame` of the running handler, or 0
+    pub frame: usize,
+    // set by `sigaltstack`
+    pub altstack: SignalStack,
 }
 
 struct IdAllocator {
@@ -105,6 +213,43 @@ impl IdAllocator {
     }
 }
 
+/// Allocates the smallest free id, so that the freed ids, and the user stacks
+/// indexed by them, are reused.
+struct RecycleAllocator {
+    inner: Mutex<RecycleInner>,
+}
+
+struct RecycleInner {
+    next: usize,
+    recycled: BTreeSet<usize>,
+}
+
+impl RecycleAllocator {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(RecycleInner {
+                next: 0,
+                recycled: BTreeSet::new(),
+            }),
+        }
+    }
+
+    pub fn alloc(&self) -> usize {
+        let mut inner = self.inner.lock();
+        if let Some(id) = inner.recycled.pop_first() {
+            id
+        } else {
+            inner.next += 1;
+            inner.next - 1
+        }
+    }
+
+    pub fn dealloc(&self, id: usize) {
+        let mut inner = self.inner.lock();
+        assert!(id < inner.next && inner.recycled.insert(id));
+    }
+}
+
 impl ProcId {
     const IDLE_TASK_ID: Self = Self(0);
 
@@ -165,24 +310,26 @@ impl Process {
             id,
             state: AtomicU8::new(ProcState::Normal as u8),
             is_kernel,
-            exit_code: AtomicI32::new(0),
+            exit_status: AtomicI32::new(0),
+            pgid: AtomicUsize::new(id.as_usize()),
+            sid: AtomicUsize::new(id.as_usize()),
+            stop_signal: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            times: Mutex::new(ProcTimes::default()),
+            xcpu_secs: AtomicU64::new(0),
+            itimers: Mutex::new([ITimer::default(); 3]),
             vm: Mutex::new(None),
+            vfork_queue: WaitQueue::new(),
             tasks: Mutex::new(BTreeMap::new()),
-            tid_allocator: IdAllocator::zero(),
+            tid_allocator: RecycleAllocator::new(),
             parent: Mutex::new(Weak::default()),
             children: Mutex::new(Vec::new()),
-            fd_table: Mutex::new(alloc::vec![
-                // 0 -> stdin
-                Some(Arc::new(Stdin)),
-                // 1 -> stdout
-                Some(Arc::new(Stdout)),
-                // 2 -> stderr
-                Some(Arc::new(Stdout)),
-            ]),
-            signal_actions: Mutex::new(SignalActions::default()),
-            mutex_list: Mutex::new(Vec::new()),
-            semaphore_list: Mutex::new(Vec::new()),
-            condvar_list: Mutex::new(Vec::new()),
+            wait_queue: WaitQueue::new(),
+            handle_table: Arc::new(Mutex::new(HandleTable::with_stdio())),
+            signal_actions: Mutex::new(Arc::new(Mutex::new(SignalActions::default()))),
+            pending_signals: Mutex::new(PendingSignals::new()),
+            rlimits: Mutex::new(Rlimits::default()),
+            deadlock: Mutex::new(DeadlockDetector::default()),
         }
     }
 
@@ -206,95 +353,262 @@ impl Process {
     }
 
     pub fn new_user(path: &str) -> Arc<Self> {
-        let t = Arc::new(Self::new_common(ProcId::alloc(), false));
+        let mut t = Self::new_common(ProcId::alloc(), false);
 
         let elf_data = open_file(path, OpenFlags::RDONLY).expect("No such user program");
-        let mut vm = MemorySet::new();
-        let (entry, ustack_top) = vm.load_user(elf_data.read_all().as_slice());
-
-        let mut task = Task::new_common(t.alloc_tid(), false, &t);
-
-        task.entry = EntryState::User(Box::new(TrapFrame::new_user(entry, ustack_top)));
-        task.ctx
-            .get_mut()
-            .init(task_entry as _, task.kstack.top(), vm.page_table_root());
+        let (vm, tf) = load_image(elf_data.read_all().as_slice(), &[], &[]);
+        t.vm = Mutex::new(Some(Arc::new(Mutex::new(vm))));
 
-        *t.vm.lock() = Some(vm);
-        t.add_task(Arc::new(task));
+        let t = Arc::new(t);
+        t.add_user_task(t.alloc_tid(), tf, 0, FpState::default());
 
         ROOT_PROC.add_child(&t);
         t
     }
 
-    pub fn user_stack(tid: usize) -> (usize, usize) {
-        let top = USER_STACK_TOP - tid * (USER_STACK_SIZE + PAGE_SIZE);
-        let bottom = top - USER_STACK_SIZE;
-        (bottom, top)
+    /// Creates a thread with a user stack mapped by the kernel, which is
+    /// unmapped when the thread is waited for or the process exits.
+    pub fn new_user_task(self: &Arc<Self>, entry: usize, arg: usize) -> Arc<Task> {
+        let vm = self.vm();
+        let (slot, ustack_top) = vm.lock().map_thread_stack();
+        let tf = TrapFrame::new_user_arg(entry, ustack_top, arg as _, 0);
+        let task = self.add_user_task(self.alloc_tid(), tf, 0, FpState::default());
+        *task.ustack.lock() = Some((Arc::downgrade(&vm), slot));
+        task
     }
 
-    pub fn new_user_task(self: &Arc<Self>, entry: usize, arg: usize) -> Arc<Task> {
-        let tid = self.tid_allocator.alloc();
-        // user stack
-        let mut vm = self.vm.lock();
-        let (ustack_bottom, ustack_top) = Self::user_stack(tid);
-        vm.as_mut().unwrap().insert(MapArea::new_framed(
-            VirtAddr::new(ustack_bottom),
-            USER_STACK_SIZE,
-            MemFlags::READ | MemFlags::WRITE | MemFlags::USER,
-        ));
-        drop(vm);
-        let task = Task::new_user(tid.into(), self, entry, ustack_top, arg);
-        self.tasks.lock().insert(tid, task.clone());
+    /// Creates a task in this process by `clone` with `CLONE_THREAD`, which
+    /// inherits the FP/SIMD registers of the caller.
+    pub fn new_thread(self: &Arc<Self>, tf: TrapFrame, tls: usize) -> Arc<Task> {
+        self.add_user_task(self.alloc_tid(), tf, tls, FpState::current())
+    }
+
+    fn add_user_task(
+        self: &Arc<Self>,
+        tid: TaskId,
+        tf: TrapFrame,
+        tls: usize,
+        fp: FpState,
+    ) -> Arc<Task> {
+        let mut task = Task::new_user(tid, self, tf);
+        task.ctx.get_mut().tpidr_el0 = tls as _;
+        *task.fp.get_mut() = fp;
+        // a user task cannot get above its own priority by creating tasks
+        let creator = CurrentTask::get();
+        if !creator.proc().is_kernel() {
+            let prio = creator.base_priority();
+            task.base_priority = AtomicUsize::new(prio);
+            task.priority = AtomicUsize::new(prio);
+        }
+        let task = Arc::new(task);
+        self.add_task(task.clone());
         task
     }
 
-    pub fn new_fork(self: &Arc<Self>, tf: &TrapFrame) -> Arc<Self> {
+    /// Creates a child process by `clone` without `CLONE_THREAD`, whose task
+    /// starts from `tf`. The address space, the fd table and the signal
+    /// actions are shared with `CLONE_VM`, `CLONE_FILES` and `CLONE_SIGHAND`,
+    /// or copied otherwise.
+    pub fn new_fork(self: &Arc<Self>, flags: CloneFlags, tf: TrapFrame, tls: usize) -> Arc<Self> {
         assert!(!self.is_kernel());
-        let t = Arc::new(Self::new_common(ProcId::alloc(), false));
-        let vm = self.vm.lock().clone().unwrap();
+        let mut t = Self::new_common(ProcId::alloc(), false);
+        let vm = self.vm();
+        t.vm = Mutex::new(Some(if flags.contains(CloneFlags::CLONE_VM) {
+            vm
+        } else {
+            Arc::new(Mutex::new(vm.lock().clone()))
+        }));
+        t.handle_table = if flags.contains(CloneFlags::CLONE_FILES) {
+            self.handle_table.clone()
+        } else {
+            Arc::new(Mutex::new(self.handle_table.lock().inherited()))
+        };
+        t.deadlock = Mutex::new(DeadlockDetector::with_handles(&t.handle_table.lock()));
+        let signal_actions = self.signal_actions();
+        t.signal_actions = Mutex::new(if flags.contains(CloneFlags::CLONE_SIGHAND) {
+            signal_actions
+        } else {
+            Arc::new(Mutex::new(signal_actions.lock().clone()))
+        });
+        t.set_pgid(self.pgid());
+        t.set_sid(self.sid());
+        *t.rlimits.lock() = self.rlimits.lock().clone();
+
+        let t = Arc::new(t);
+        t.add_user_task(t.alloc_tid(), tf, tls, FpState::current());
+        self.add_child(&t);
+        t
+    }
 
-        let mut task = Task::new_common(t.alloc_tid(), false, &t);
+    /// Creates a child process running the ELF with `args` and `envs`, and
+    /// with the handle table `handle_table`.
+    pub fn new_spawn(
+        self: &Arc<Self>,
+        elf_data: &[u8],
+        args: &[String],
+        envs: &[String],
+        handle_table: HandleTable,
+    ) -> Arc<Self> {
+        assert!(!self.is_kernel());
+        let mut t = Self::new_common(ProcId::alloc(), false);
+        let (vm, tf) = load_image(elf_data, args, envs);
+        t.vm = Mutex::new(Some(Arc::new(Mutex::new(vm))));
+        t.deadlock = Mutex::new(DeadlockDetector::with_handles(&handle_table));
+        t.handle_table = Arc::new(Mutex::new(handle_table));
+        t.set_pgid(self.pgid());
+        t.set_sid(self.sid());
+        *t.rlimits.lock() = self.rlimits.lock().clone();
+
+        let t = Arc::new(t);
+        t.add_user_task(t.alloc_tid(), tf, 0, FpState::default());
+        self.add_child(&t);
+        t
+    }
 
-        task.entry = EntryState::User(Box::new(tf.new_fork()));
-        task.ctx
-            .get_mut()
-            .init(task_entry as _, task.kstack.top(), vm.page_table_root());
+    /// Whether the image of the ELF is within `RLIMIT_STACK` and `RLIMIT_AS`.
+    pub fn image_fits(&self, elf_data: &[u8]) -> bool {
+        let rlimits = self.rlimits.lock();
+        USER_STACK_SIZE <= rlimits.get(RLIMIT_STACK)
+            && MemorySet::user_size(elf_data) <= rlimits.get(RLIMIT_AS)
+    }
 
-        t.tasks.lock().insert(task.tid().as_usize(), Arc::new(task));
+    /// Blocks until `child`, created by `vfork`, gives back the address space
+    /// by `exec` or exit.
+    pub fn wait_vfork_done(&self, child: &Process) {
+        child.vfork_queue.wait_until(|| !child.shares_vm(self));
+    }
 
-        *t.vm.lock() = Some(vm);
-        // copy fd table
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in self.fd_table.lock().iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
+    fn shares_vm(&self, other: &Process) -> bool {
+        match (self.vm.lock().as_ref(), other.vm.lock().as_ref()) {
+            (Some(vm), Some(other_vm)) => Arc::ptr_eq(vm, other_vm),
+            _ => false,
+        }
+    }
+
+    /// Allocates the lowest free handle for `object`, returns `None` if it
+    /// would exceed `RLIMIT_NOFILE`.
+    pub fn alloc_handle(&self, object: Object) -> Option<usize> {
+        let max_handles = self.rlimits.lock().get(RLIMIT_NOFILE);
+        self.handle_table
+            .lock()
+            .alloc(Handle::new(object), max_handles)
+    }
+
+    /// CPU times of all tasks, including the exited ones, and of the
+    /// waited-for children.
+    pub fn cpu_times(&self) -> ProcTimes {
+        let mut times = *self.times.lock();
+        for task in self.tasks.lock().values() {
+            let task_times = *task.times.lock();
+            times.utime += task_times.utime;
+            times.stime += task_times.stime;
+        }
+        times
+    }
+
+    /// Checks `RLIMIT_CPU` on every timer tick. Sends `SIGXCPU` every second
+    /// after the soft limit is reached, and `SIGKILL` at the hard limit.
+    pub fn check_cpu_limit(&self) {
+        let limit = self.rlimits.lock().table[RLIMIT_CPU];
+        if limit.cur == RLIM_INFINITY {
+            return;
+        }
+        let times = self.cpu_times();
+        let secs = counter_to_secs(times.utime + times.stime);
+        if secs >= limit.max as u64 {
+            self.send_signal(SignalFlags::SIGKILL);
+        } else if secs >= limit.cur as u64 && secs > self.xcpu_secs.swap(secs, Ordering::SeqCst) {
+            self.send_signal(SignalFlags::SIGXCPU);
+        }
+    }
+
+    /// The time counted by interval timer `which`, in system counter cycles.
+    fn itimer_clock(&self, which: usize) -> u64 {
+        match which {
+            ITIMER_REAL => get_counter(),
+            ITIMER_VIRTUAL => self.cpu_times().utime,
+            _ => {
+                let times = self.cpu_times();
+                times.utime + times.stime
             }
         }
-        *t.fd_table.lock() = new_fd_table;
-        self.add_child(&t);
-        t
     }
 
-    pub fn alloc_fd(&self, file: Option<Arc<dyn File + Send + Sync>>) -> usize {
-        let mut fd_table = self.fd_table.lock();
-        let fd = if let Some(fd) = (0..fd_table.len()).find(|fd| fd_table[*fd].is_none()) {
-            fd
+    /// The time left until interval timer `which` expires and its interval,
+    /// in system counter cycles, 0 if disarmed.
+    pub fn get_itimer(&self, which: usize) -> (u64, u64) {
+        let timer = self.itimers.lock()[which];
+        if timer.deadline == 0 {
+            return (0, timer.interval);
+        }
+        // not fired yet, so not less than a cycle
+        let left = timer.deadline.saturating_sub(self.itimer_clock(which));
+        (left.max(1), timer.interval)
+    }
+
+    /// Arms interval timer `which` to expire after `value` and then every
+    /// `interval`, or disarms it if `value` is 0. Returns the old setting as
+    /// `get_itimer` does.
+    pub fn set_itimer(self: &Arc<Self>, which: usize, value: u64, interval: u64) -> (u64, u64) {
+        let old = self.get_itimer(which);
+        let deadline = if value == 0 {
+            0
         } else {
-            fd_table.push(None);
-            fd_table.len() - 1
+            self.itimer_clock(which).saturating_add(value)
         };
-        if let Some(file) = file {
-            fd_table[fd] = Some(file);
+        self.itimers.lock()[which] = ITimer { deadline, interval };
+        // the stale timer in `TIMERS` is ignored when it fires
+        if which == ITIMER_REAL && deadline != 0 {
+            add_real_timer(deadline, self);
+        }
+        old
+    }
+
+    /// Sends `SIGALRM` when `ITIMER_REAL` expires at `deadline`, and arms it
+    /// again if periodic.
+    pub fn fire_real_timer(self: &Arc<Self>, deadline: u64) {
+        let mut itimers = self.itimers.lock();
+        let timer = &mut itimers[ITIMER_REAL];
+        if timer.deadline != deadline || self.state() == ProcState::Zombie {
+            return;
+        }
+        if timer.reload(get_counter()) {
+            add_real_timer(timer.deadline, self);
+        }
+        drop(itimers);
+        self.send_signal(SignalFlags::SIGALRM);
+    }
+
+    /// Checks `ITIMER_VIRTUAL` and `ITIMER_PROF` on every timer tick, and
+    /// sends `SIGVTALRM` and `SIGPROF` when they expire.
+    pub fn check_cpu_timers(&self) {
+        for (which, signal) in [
+            (ITIMER_VIRTUAL, SignalFlags::SIGVTALRM),
+            (ITIMER_PROF, SignalFlags::SIGPROF),
+        ] {
+            if self.itimers.lock()[which].deadline == 0 {
+                continue;
+            }
+            let now = self.itimer_clock(which);
+            let mut itimers = self.itimers.lock();
+            let timer = &mut itimers[which];
+            if timer.deadline != 0 && timer.deadline <= now {
+                timer.reload(now);
+                drop(itimers);
+                self.send_signal(signal);
+            }
         }
-        fd
     }
 
     pub fn alloc_tid(&self) -> TaskId {
         self.tid_allocator.alloc().into()
     }
 
+    /// Frees the tid of a removed task.
+    fn dealloc_tid(&self, tid: usize) {
+        self.tid_allocator.dealloc(tid);
+    }
+
     pub fn add_task(self: &Arc<Self>, task: Arc<Task>) {
         assert!(Arc::ptr_eq(self, &task.process.upgrade().unwrap()));
         let mut tasks = self.tasks.lock();
@@ -317,6 +631,30 @@ impl Process {
         count
     }
 
+    /// Number of live tasks of this process and of its descendants.
+    fn tree_task_count(&self) -> usize {
+        let children = self.children.lock().clone();
+        self.task_count()
+            + children
+                .iter()
+                .map(|child| child.tree_task_count())
+                .sum::<usize>()
+    }
+
+    /// Whether one more task would exceed `RLIMIT_NPROC` of this process or
+    /// of an ancestor. There are no users whose tasks a limit counts as on
+    /// Linux, so it counts those of the tree of the process which sets it.
+    pub fn nproc_exceeded(self: &Arc<Self>) -> bool {
+        let mut proc = Some(self.clone());
+        while let Some(p) = proc {
+            if p.tree_task_count() >= p.rlimits.lock().get(RLIMIT_NPROC) {
+                return true;
+            }
+            proc = p.parent.lock().upgrade();
+        }
+        false
+    }
+
     pub fn task(&self) -> Arc<Task> {
         assert!(self.task_count() == 1);
         self.tasks.lock().get(&0).unwrap().clone()
@@ -353,16 +691,42 @@ impl Process {
         self.is_kernel
     }
 
-    pub fn exit_code(&self) -> i32 {
-        self.exit_code.load(Ordering::SeqCst)
+    pub fn pgid(&self) -> usize {
+        self.pgid.load(Ordering::SeqCst)
     }
 
-    pub fn set_exit_code(&self, exit_code: i32) {
-        self.exit_code.store(exit_code, Ordering::SeqCst)
+    pub fn set_pgid(&self, pgid: usize) {
+        self.pgid.store(pgid, Ordering::SeqCst)
+    }
+
+    pub fn sid(&self) -> usize {
+        self.sid.load(Ordering::SeqCst)
+    }
+
+    pub fn set_sid(&self, sid: usize) {
+        self.sid.store(sid, Ordering::SeqCst)
+    }
+
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status.load(Ordering::SeqCst)
+    }
+
+    pub fn set_exit_status(&self, exit_status: i32) {
+        self.exit_status.store(exit_status, Ordering::SeqCst)
+    }
+
+    /// The actions of the signals, see `CLONE_SIGHAND`.
+    pub fn signal_actions(&self) -> Arc<Mutex<SignalActions>> {
+        self.signal_actions.lock().clone()
+    }
+
+    /// The address space, panics if the process has exited.
+    pub fn vm(&self) -> Arc<Mutex<MemorySet>> {
+        self.vm.lock().clone().unwrap()
     }
 
     pub fn page_table_root(&self) -> PhysAddr {
-        self.vm.lock().as_ref().unwrap().page_table_root()
+        self.vm().lock().page_table_root()
     }
 
     #[allow(unused)]
@@ -387,15 +751,23 @@ impl Task {
 
             kstack: Stack::default(),
             ctx: TaskLockedCell::new(TaskContext::default()),
+            fp: TaskLockedCell::new(FpState::default()),
             signal: Mutex::new(SignalInner {
-                signals: SignalFlags::empty(),
+                pending: PendingSignals::new(),
                 signal_mask: SignalFlags::empty(),
-                handling_sig: None,
-                killed: false,
+                sigwait: SignalFlags::empty(),
+                killed: None,
                 frozen: false,
-                mask_backup: None,
-                trapframe_backup: None,
+                frame: 0,
+                altstack: SignalStack::disabled(),
             }),
+            times: Mutex::new(CpuTimes::default()),
+            base_priority: AtomicUsize::new(DEFAULT_PRIORITY),
+            priority: AtomicUsize::new(DEFAULT_PRIORITY),
+            pi_links: Mutex::new(PiLinks::default()),
+            clear_child_tid: AtomicUsize::new(0),
+            interruptible: AtomicBool::new(false),
+            ustack: Mutex::new(None),
         }
     }
 
@@ -416,63 +788,199 @@ impl Task {
         Arc::new(t)
     }
 
-    pub fn new_user(
-        tid: TaskId,
-        proc: &Arc<Process>,
-        entry: usize,
-        ustack_top: usize,
-        arg: usize,
-    ) -> Arc<Self> {
-        assert!(tid.as_usize() <= 16);
-        let mut t = Self::new_common(tid, true, proc);
-        // TODO: recycle user stack
-        t.entry = EntryState::User(Box::new(TrapFrame::new_user_arg(
-            entry, ustack_top, arg as _, 0,
-        )));
+    fn new_user(tid: TaskId, proc: &Arc<Process>, tf: TrapFrame) -> Self {
+        let mut t = Self::new_common(tid, false, proc);
+        t.entry = EntryState::User(Box::new(tf));
         t.ctx
             .get_mut()
             .init(task_entry as _, t.kstack.top(), proc.page_table_root());
-        Arc::new(t)
+        t
+    }
+
+    /// The effective priority, which is at least the base priority.
+    pub fn priority(&self) -> usize {
+        self.priority.load(Ordering::SeqCst)
+    }
+
+    pub fn base_priority(&self) -> usize {
+        self.base_priority.load(Ordering::SeqCst)
+    }
+
+    /// Sets the base priority, the effective one is updated by
+    /// `update_priority`.
+    pub fn set_base_priority(&self, prio: usize) {
+        self.base_priority.store(prio, Ordering::SeqCst)
+    }
+
+    /// Sets the effective priority, and requeues the task if it is ready.
+    pub fn set_priority(self: &Arc<Self>, prio: usize) {
+        let mut m = TASK_MANAGER.lock();
+        let ready = m.remove_ready(self);
+        self.priority.store(prio, Ordering::SeqCst);
+        if ready {
+            m.spawn(self.clone());
+        }
+    }
+
+    /// Clears `child_tid` when the task exits, see `CLONE_CHILD_CLEARTID`.
+    pub fn set_clear_child_tid(&self, child_tid: usize) {
+        self.clear_child_tid.store(child_tid, Ordering::SeqCst)
+    }
+
+    /// Switches the running task to a new address space.
+    fn switch_vm(&self, page_table_root: PhysAddr) {
+        let _guard = TASK_MANAGER.lock();
+        unsafe {
+            (*self.ctx.as_ptr()).ttbr0_el1 = page_table_root.as_usize() as u64;
+            crate::arch::activate_paging(page_table_root.as_usize(), false);
+        }
+    }
+
+    /// Saves the FP/SIMD registers of a user task which is switched out.
+    ///
+    /// # Safety
+    ///
+    /// The task must be the current one, see `TaskLockedCell::as_ptr`.
+    pub(super) unsafe fn save_fp(&self) {
+        if !self._is_kernel {
+            (*self.fp.as_ptr()).save();
+        }
+    }
+
+    /// Restores the FP/SIMD registers of a user task which is switched in.
+    ///
+    /// # Safety
+    ///
+    /// See `TaskLockedCell::as_ptr`.
+    pub(super) unsafe fn restore_fp(&self) {
+        if !self._is_kernel {
+            (*self.fp.as_ptr()).restore();
+        }
     }
 
-    pub fn set_singal(&self, signal: SignalFlags) {
+    /// The pending signals of the task and of its process which it does not
+    /// block.
+    fn unblocked_signals(&self) -> SignalFlags {
+        let inner = self.signal.lock();
+        let (pending, mask) = (inner.pending.signals, inner.signal_mask);
+        drop(inner);
+        (pending | self.proc().pending_signals.lock().signals) & !mask
+    }
+
+    /// Takes the pending signal `sig`, sent to the task or else to its
+    /// process, returns its information.
+    fn take_signal(&self, sig: usize) -> SigInfo {
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
         let mut inner = self.signal.lock();
-        inner.signals |= signal;
+        if inner.pending.signals.contains(signal) {
+            return inner.pending.dequeue(sig);
+        }
+        drop(inner);
+        self.proc().pending_signals.lock().dequeue(sig)
     }
 
-    pub fn singal_metadata(&self) -> (SignalFlags, Option<SignalFlags>) {
+    /// Whether the task can take `signal` sent to its process, that is it
+    /// does not block the signal or waits for it by `sigtimedwait`.
+    fn wants_signal(&self, signal: SignalFlags) -> bool {
+        if self.state() == TaskState::Zombie {
+            return false;
+        }
         let inner = self.signal.lock();
-        let masked_signal = inner.signals & inner.signal_mask;
-        if let Some(sig) = inner.handling_sig {
-            let action = self.proc().signal_actions.lock().table[sig];
-            let action_mask = action.mask;
-            (masked_signal, Some(action_mask))
-        } else {
-            (masked_signal, None)
+        !inner.signal_mask.contains(signal) || inner.sigwait.contains(signal)
+    }
+
+    /// Wakes the task for `signal` if it is blocked by `block_interruptible`
+    /// and the signal interrupts it, or if it is blocked while its process is
+    /// stopped and the signal is `SIGKILL`. `interrupts` tells whether the
+    /// signal is not ignored. Returns whether the task is woken.
+    fn wake_by_signal(self: &Arc<Self>, signal: SignalFlags, interrupts: bool) -> bool {
+        let inner = self.signal.lock();
+        let wake = self.state() == TaskState::Blocking
+            && ((inner.frozen && signal == SignalFlags::SIGKILL)
+                || (self.interruptible.load(Ordering::SeqCst)
+                    && (inner.sigwait.contains(signal)
+                        || (interrupts && !inner.signal_mask.contains(signal)))));
+        drop(inner);
+        if wake {
+            self.resume();
         }
+        wake
     }
 
-    fn kernel_signal_handler(&self, signal: SignalFlags) {
+    /// Sends the signal described by `info` to this task only, see
+    /// `Process::send_signal_info`.
+    pub fn send_signal_info(self: &Arc<Self>, info: SigInfo) -> bool {
+        if self.state() == TaskState::Zombie {
+            return false;
+        }
+        let proc = self.proc();
+        let (signal, interrupts) = proc.prepare_signal(&info);
+        let max_queued = proc.rlimits.lock().get(RLIMIT_SIGPENDING);
+        let sent = self.signal.lock().pending.enqueue(info, max_queued);
+        self.wake_by_signal(signal, interrupts);
+        sent
+    }
+
+    /// Sends the synchronous fault described by `info` to this task. If the
+    /// signal is blocked or ignored, it is unblocked and its action is reset
+    /// to the default, so that the process is killed rather than running the
+    /// faulting instruction again.
+    pub fn force_signal(self: &Arc<Self>, info: SigInfo) {
+        let sig = info.signo as usize;
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
+        let signal_actions = self.proc().signal_actions();
+        let mut actions = signal_actions.lock();
         let mut inner = self.signal.lock();
-        match signal {
-            SignalFlags::SIGSTOP => {
-                inner.frozen = true;
-                inner.signals ^= SignalFlags::SIGSTOP;
-            }
-            SignalFlags::SIGCONT => {
-                if inner.signals.contains(SignalFlags::SIGCONT) {
-                    inner.signals ^= SignalFlags::SIGCONT;
-                    inner.frozen = false;
-                }
+        if inner.signal_mask.contains(signal) || actions.table[sig].handler == SIG_IGN {
+            actions.table[sig] = SignalAction::default();
+            inner.signal_mask.remove(signal);
+        }
+        drop(inner);
+        drop(actions);
+        self.send_signal_info(info);
+    }
+
+    /// Whether there is a pending signal which interrupts the blocking
+    /// syscalls, that is one which is not ignored.
+    pub fn is_interrupted(&self) -> bool {
+        let pending = self.unblocked_signals();
+        let signal_actions = self.proc().signal_actions();
+        let actions = signal_actions.lock();
+        (0..=MAX_SIG).any(|sig| pending.bits() & (1 << sig) != 0 && !actions.ignores(sig))
+    }
+
+    /// Unmaps the user stack mapped for the task by `thread_create`, unless
+    /// its address space is gone, e.g. replaced by `exec`.
+    fn unmap_user_stack(&self) {
+        if let Some((vm, slot)) = self.ustack.lock().take() {
+            if let Some(vm) = vm.upgrade() {
+                vm.lock().unmap_thread_stack(slot);
+                crate::arch::flush_tlb_all();
             }
-            // SIGKILL | SIGDEF
-            _ => {
-                info!(
-                    "[K] call_kernel_signal_handler:: current task sigflag {:?}",
-                    inner.signals
-                );
-                inner.killed = true;
+        }
+    }
+
+    /// Whether `signal` is blocked by the task or ignored by its process, so
+    /// that sending it has no effect for now.
+    pub fn blocks_or_ignores(&self, signal: SignalFlags) -> bool {
+        let sig = signal.bits().trailing_zeros() as usize;
+        self.signal.lock().signal_mask.contains(signal)
+            || self.proc().signal_actions().lock().ignores(sig)
+    }
+
+    /// Takes the default action of signal `sig`, see `DefaultAction`.
+    fn default_signal_handler(&self, sig: usize) {
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
+        self.take_signal(sig);
+        let mut inner = self.signal.lock();
+        match signal.default_action() {
+            DefaultAction::Terminate | DefaultAction::Core => inner.killed = Some(sig),
+            DefaultAction::Stop => {
+                drop(inner);
+                self.proc().job_stop(sig);
             }
+            // continued when the signal is sent
+            DefaultAction::Continue | DefaultAction::Ignore => {}
         }
     }
 
@@ -482,28 +990,97 @@ impl Task {
         TASK_MANAGER.lock().spawn(self.clone());
     }
 
-    fn user_signal_handler(&self, sig: usize, tf: &mut TrapFrame) {
-        let action = self.proc().signal_actions.lock().table[sig];
+    /// Resumes the task taken from a wait queue, unless its timer has already
+    /// resumed it. Either way, it finds itself out of the queue and knows it
+    /// was woken before timing out.
+    pub fn wake(self: &Arc<Self>) {
+        if self.state() == TaskState::Blocking {
+            self.resume();
+        }
+    }
+
+    /// Sets up the handler of `sig`. If the interrupted syscall is to be made
+    /// again as `restart` says, it fails with `EINTR` instead unless the
+    /// action has `SA_RESTART`.
+    fn user_signal_handler(&self, sig: usize, tf: &mut TrapFrame, restart: &mut bool) {
+        let signal_actions = self.proc().signal_actions();
+        let mut actions = signal_actions.lock();
+        let action = actions.table[sig];
+        let info = self.take_signal(sig);
         let mut inner = self.signal.lock();
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
         let handler = action.handler;
-        if handler != 0 {
-            // backup mask
-            inner.mask_backup = Some(inner.signal_mask);
-            // change current mask
-            inner.signal_mask = action.mask;
-            // handle flag
-            inner.handling_sig = Some(sig);
-            inner.signals ^= SignalFlags::from_bits(1 << sig).unwrap();
-            // backup and modify trapframe
-            inner.trapframe_backup = Some(*tf);
-            // set entry point
-            tf.elr = handler as _;
-            // set arg0
-            tf.r[0] = sig as _;
+        if action.flags.contains(SigActionFlags::SA_RESETHAND) {
+            actions.table[sig] = SignalAction::default();
+        }
+        drop(actions);
+        if *restart {
+            *restart = false;
+            if !action.flags.contains(SigActionFlags::SA_RESTART) {
+                tf.elr += 4;
+                tf.r[0] = -EINTR as _;
+            }
+        }
+        let sp = tf.usp as usize;
+        let altstack = inner.altstack;
+        let frame_sp = if action.flags.contains(SigActionFlags::SA_ONSTACK)
+            && altstack.is_enabled()
+            && !altstack.contains(sp)
+        {
+            altstack.sp + altstack.size
         } else {
-            // default action
-            info!("[K] task/call_user_signal_handler: default action: ignore it or kill process");
+            sp
+        };
+        let frame = SignalFrame {
+            info,
+            uc: UContext {
+                stack: altstack,
+                mask: inner.signal_mask,
+                tf: *tf,
+                fp: FpState::current(),
+            },
+            prev: inner.frame,
+        };
+        let frame_addr = match self.push_signal_frame(frame_sp, &frame) {
+            Some(addr) => addr,
+            None => {
+                // no room for the frame on the user stack
+                inner.killed = Some(SignalFlags::SIGSEGV.bits().trailing_zeros() as usize);
+                return;
+            }
+        };
+        inner.frame = frame_addr;
+        inner.signal_mask |= action.mask;
+        if !action.flags.contains(SigActionFlags::SA_NODEFER) {
+            inner.signal_mask |= signal;
+        }
+        // the handler runs below the frame, and returns to the restorer
+        tf.usp = frame_addr as _;
+        tf.elr = handler as _;
+        tf.r[0] = sig as _;
+        if action.flags.contains(SigActionFlags::SA_SIGINFO) {
+            let uc_offset = &frame.uc as *const _ as usize - &frame as *const _ as usize;
+            tf.r[1] = frame_addr as _;
+            tf.r[2] = (frame_addr + uc_offset) as _;
+        }
+        tf.r[30] = if action.flags.contains(SigActionFlags::SA_RESTORER) {
+            action.restorer as _
+        } else {
+            0
+        };
+    }
+
+    /// Writes `frame` below the user stack pointer `sp`, returns its address,
+    /// or `None` if the stack is not mapped there.
+    fn push_signal_frame(&self, sp: usize, frame: &SignalFrame) -> Option<usize> {
+        let size = core::mem::size_of::<SignalFrame>();
+        let addr = sp.checked_sub(size)? & !0xf;
+        let mut ptr = UserOutPtr::<SignalFrame>::from(addr);
+        if !ptr.check() || !self.proc().vm().lock().is_mapped(VirtAddr::new(addr), size) {
+            return None;
         }
+        ptr.write(*frame);
+        Some(addr)
     }
 
     pub fn proc(&self) -> Arc<Process> {
@@ -559,29 +1136,61 @@ fn task_entry() -> ! {
             task.exit(ret as _);
         }
         EntryState::User(tf) => {
+            task.times.lock().account_system();
             unsafe { tf.exec(task.kstack.top()) };
         }
     }
 }
 
-fn push_stack<T>(stack: usize, val: T) -> usize {
-    let stack = unsafe { (stack as *mut T).sub(1) };
-    unsafe { *stack = val };
-    stack as usize
-}
-
-fn push_c_str(stack: usize, val: &str) -> usize {
-    let len = val.len() + 1;
-    let stack = unsafe { core::slice::from_raw_parts_mut((stack - len) as *mut u8, len) };
-    for (idx, c) in val.as_bytes().iter().enumerate() {
-        stack[idx] = *c;
-    }
-    stack[len - 1] = 0;
-    stack.as_ptr() as usize
+/// Loads the ELF into a new address space, returns it with the trap frame to
+/// enter the program.
+///
+/// The strings of `args` and `envs` are put on the top of the user stack,
+/// and below them the null-terminated pointer arrays `argv` and then `envp`,
+/// which are passed with `argc` in `x0`..`x2`. The syscalls keep them within
+/// `MAX_ARG_SIZE`, so that the program has the rest of the stack.
+fn load_image(elf_data: &[u8], args: &[String], envs: &[String]) -> (MemorySet, TrapFrame) {
+    let mut vm = MemorySet::new();
+    let (entry, mut ustack_top) = vm.load_user(elf_data);
+    let mut push_strs = |strs: &[String]| -> Vec<usize> {
+        let mut ptrs: Vec<usize> = strs
+            .iter()
+            .rev()
+            .map(|s| {
+                ustack_top -= s.len() + 1;
+                vm.write_data(VirtAddr::new(ustack_top), s.as_bytes());
+                vm.write_data(VirtAddr::new(ustack_top + s.len()), &[0]);
+                ustack_top
+            })
+            .collect();
+        ptrs.reverse();
+        ptrs.push(0);
+        ptrs
+    };
+    let envp = push_strs(envs);
+    let argv = push_strs(args);
+    let ptrs: Vec<u8> = argv
+        .iter()
+        .chain(envp.iter())
+        .flat_map(|ptr| ptr.to_ne_bytes())
+        .collect();
+    ustack_top = (ustack_top - ptrs.len()) & !0xF; // 16 bytes aligned
+    vm.write_data(VirtAddr::new(ustack_top), &ptrs);
+    let argv_base = ustack_top;
+    let envp_base = argv_base + argv.len() * core::mem::size_of::<usize>();
+    let mut tf = TrapFrame::new_user_arg(entry, ustack_top, args.len() as _, argv_base as _);
+    tf.r[2] = envp_base as _;
+    (vm, tf)
 }
 
 impl Process {
     pub fn stop(&self, exit_code: i32) {
+        self.stop_with_status(exited_status(exit_code));
+    }
+
+    /// Kills all tasks of the process, which reports `exit_status` to the
+    /// parent. The children are given to the root process.
+    fn stop_with_status(&self, exit_status: i32) {
         assert!(!self.is_idle());
         assert!(!self.is_root());
 
@@ -593,63 +1202,190 @@ impl Process {
         children.clear();
         drop(children);
 
+        let tasks: Vec<_> = self.tasks.lock().values().cloned().collect();
+        for task in tasks {
+            task.send_signal_info(SigInfo::kernel(SignalFlags::SIGKILL));
+        }
+        self.set_state(ProcState::Stop);
+        self.set_exit_status(exit_status);
+    }
+
+    /// Whether all tasks are stopped by a signal until `SIGCONT`.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Takes `signal` out of the pending signals of the process and of all
+    /// tasks.
+    pub fn discard_signal(&self, signal: SignalFlags) {
+        self.pending_signals.lock().discard(signal);
+        for task in self.tasks.lock().values() {
+            task.signal.lock().pending.discard(signal);
+        }
+    }
+
+    /// Sends `signal` to the process, returns false if it is already pending,
+    /// or cannot be queued if real-time.
+    pub fn send_signal(&self, signal: SignalFlags) -> bool {
+        self.send_signal_info(SigInfo::kernel(signal))
+    }
+
+    /// Takes the effects of the signal described by `info` which come when
+    /// it is sent. `SIGCONT` continues the process at once, even if blocked
+    /// or ignored, and discards the pending stop signals, while a stop signal
+    /// discards a pending `SIGCONT`. Returns the signal, and whether it
+    /// interrupts the blocking syscalls.
+    fn prepare_signal(&self, info: &SigInfo) -> (SignalFlags, bool) {
+        let signal = SignalFlags::from_bits(1 << info.signo).unwrap();
+        let interrupts = !self.signal_actions().lock().ignores(info.signo as usize);
+        if signal == SignalFlags::SIGCONT {
+            if self.stopped.swap(false, Ordering::SeqCst) {
+                self.notify_parent(CLD_CONTINUED, info.signo);
+            }
+            self.stop_signal.store(0, Ordering::SeqCst);
+            self.discard_signal(SignalFlags::STOP);
+            for task in self.tasks.lock().values() {
+                let frozen = task.signal.lock().frozen;
+                if frozen && task.state() == TaskState::Blocking {
+                    task.resume();
+                }
+            }
+        } else if SignalFlags::STOP.contains(signal) {
+            self.discard_signal(SignalFlags::SIGCONT);
+        }
+        (signal, interrupts)
+    }
+
+    /// Sends the signal described by `info` to the process, where it is taken
+    /// by one task which does not block it. It is kept for its handler unless
+    /// the signal is already pending, while a real-time signal is queued
+    /// instead, up to `RLIMIT_SIGPENDING`. See `prepare_signal` for the
+    /// effects of `SIGCONT` and the stop signals.
+    pub fn send_signal_info(&self, info: SigInfo) -> bool {
+        let (signal, interrupts) = self.prepare_signal(&info);
+        let max_queued = self.rlimits.lock().get(RLIMIT_SIGPENDING);
+        let sent = self.pending_signals.lock().enqueue(info, max_queued);
+        // a running or ready task takes it on its way back to the user,
+        // otherwise a blocked one is woken
         let tasks = self.tasks.lock();
-        for task in tasks.values() {
-            if task.state() != TaskState::Zombie {
-                let mut inner = task.signal.lock();
-                if !inner.signals.contains(SignalFlags::SIGKILL) {
-                    inner.signals.insert(SignalFlags::SIGKILL);
+        let takers = tasks.values().filter(|task| task.wants_signal(signal));
+        if takers
+            .clone()
+            .all(|task| task.state() == TaskState::Blocking)
+        {
+            for task in takers {
+                if task.wake_by_signal(signal, interrupts) {
+                    break;
                 }
             }
         }
-        drop(tasks);
-        self.set_state(ProcState::Stop);
-        self.set_exit_code(exit_code);
+        sent
+    }
+
+    /// Stops all tasks of the process on signal `signum`, and tells the
+    /// parent. The stop signals pending in the other tasks are discarded, so
+    /// that the stop is reported once.
+    fn job_stop(&self, signum: usize) {
+        self.discard_signal(SignalFlags::STOP);
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.stop_signal.store(signum, Ordering::SeqCst);
+        self.notify_parent(CLD_STOPPED, signum as i32);
     }
 
     pub fn exit(&self) {
         self.set_state(ProcState::Zombie);
+        // the address space may live on in other processes by `CLONE_VM`
+        for task in self.tasks.lock().values() {
+            task.unmap_user_stack();
+        }
         // drop memory set
         *self.vm.lock() = None;
+        self.vfork_queue.notify_all();
+        let exit_status = self.exit_status();
+        let signum = exit_status & 0x7f;
+        if signum == 0 {
+            self.notify_parent(CLD_EXITED, (exit_status >> 8) & 0xff);
+        } else if exit_status & WCOREFLAG != 0 {
+            self.notify_parent(CLD_DUMPED, signum);
+        } else {
+            self.notify_parent(CLD_KILLED, signum);
+        }
+    }
+
+    /// Wakes the parent waiting in `waitpid`, and sends it `SIGCHLD` with
+    /// `code` and `status`, see `SigInfo::child`. There is no signal for a
+    /// stop or a continue if the parent's action has `SA_NOCLDSTOP`.
+    fn notify_parent(&self, code: i32, status: i32) {
+        let parent = match self.parent.lock().upgrade() {
+            Some(parent) => parent,
+            None => return,
+        };
+        parent.wait_queue.notify_all();
+        let sig = SignalFlags::SIGCHLD.bits().trailing_zeros() as usize;
+        let nocldstop = parent.signal_actions().lock().table[sig]
+            .flags
+            .contains(SigActionFlags::SA_NOCLDSTOP);
+        if nocldstop && (code == CLD_STOPPED || code == CLD_CONTINUED) {
+            return;
+        }
+        parent.send_signal_info(SigInfo::child(self.pid().as_usize(), code, status));
     }
 
-    pub fn task_exit(&self, _tid: usize, exit_code: i32) {
+    pub fn task_exit(&self, _tid: usize, exit_status: i32) {
         if self.task_count() == 0 {
             if self.state() == ProcState::Normal {
-                self.set_exit_code(exit_code);
+                self.set_exit_status(exit_status);
             }
             self.exit();
         }
     }
 
-    pub fn exec(&self, path: &str, args: Vec<String>, tf: &mut TrapFrame) -> isize {
+    pub fn exec(
+        &self,
+        path: &str,
+        args: Vec<String>,
+        envs: Vec<String>,
+        tf: &mut TrapFrame,
+    ) -> isize {
         assert!(!self.is_kernel());
         assert!(self.task_count() == 1);
         if let Some(elf_data) = open_file(path, OpenFlags::RDONLY) {
-            let mut vm = self.vm.lock();
-            let vm = vm.get_or_insert(MemorySet::new());
-            vm.clear();
-            crate::arch::flush_tlb_all();
-            let (entry, mut ustack_top) = vm.load_user(elf_data.read_all().as_slice());
-            let argc = args.len();
-            let mut argv_vec = Vec::<usize>::new();
-            for arg in args.iter().rev() {
-                ustack_top = push_c_str(ustack_top, arg);
-                argv_vec.push(ustack_top);
+            let elf_data = elf_data.read_all();
+            // check the limits before the old image is dropped
+            if !self.image_fits(elf_data.as_slice()) {
+                return -ENOMEM;
             }
-            ustack_top &= !0xF; // 16 bytes aligned
-            for arg in argv_vec {
-                ustack_top = push_stack(ustack_top, arg);
-            }
-            let argv_base = ustack_top;
-            *tf = TrapFrame::new_user_arg(entry, ustack_top, argc as _, argv_base as _);
-            argc as isize
+            let (vm, new_tf) = load_image(elf_data.as_slice(), &args, &envs);
+            let page_table_root = vm.page_table_root();
+            // the old address space is only dropped if it is not shared, e.g.
+            // borrowed from the parent by `vfork`
+            let old_vm = self.vm.lock().replace(Arc::new(Mutex::new(vm)));
+            CurrentTask::get().switch_vm(page_table_root);
+            drop(old_vm);
+            self.vfork_queue.notify_all();
+            // caught signals are reset, as their handlers are gone, in a table
+            // of its own if it was shared by `CLONE_SIGHAND`
+            let actions = self.signal_actions().lock().after_exec();
+            *self.signal_actions.lock() = Arc::new(Mutex::new(actions));
+            // so are the frames and the stack of the handlers
+            let task = CurrentTask::get();
+            let mut inner = task.signal.lock();
+            inner.frame = 0;
+            inner.altstack = SignalStack::disabled();
+            drop(inner);
+            FpState::default().restore();
+            *tf = new_tf;
+            args.len() as isize
         } else {
-            -1
+            -ENOENT
         }
     }
 
-    pub fn waitpid(&self, pid: isize, exit_code: &mut i32) -> isize {
+    /// Reaps a zombie child, or with `WUNTRACED` reports a stopped child,
+    /// returns `None` if the matched children are all still running.
+    fn reap_child(&self, pid: isize, exit_status: &mut i32, options: WaitOptions) -> Option<isize> {
         let mut children = self.children.lock();
         let mut found_pid = false;
         for (idx, t) in children.iter().enumerate() {
@@ -658,34 +1394,73 @@ impl Process {
                 if t.state() == ProcState::Zombie {
                     let child = children.remove(idx);
                     PROC_MAP.lock().remove(&child.pid().as_usize());
-                    assert_eq!(Arc::strong_count(&child), 1);
-                    *exit_code = child.exit_code();
-                    return child.pid().as_usize() as isize;
+                    *exit_status = child.exit_status();
+                    let child_times = child.cpu_times();
+                    let mut times = self.times.lock();
+                    times.cutime += child_times.utime + child_times.cutime;
+                    times.cstime += child_times.stime + child_times.cstime;
+                    return Some(child.pid().as_usize() as isize);
+                }
+                if options.contains(WaitOptions::WUNTRACED) {
+                    let signum = t.stop_signal.swap(0, Ordering::SeqCst);
+                    if signum != 0 {
+                        *exit_status = stopped_status(signum);
+                        return Some(t.pid().as_usize() as isize);
+                    }
                 }
             }
         }
         if found_pid {
-            -2
+            None
         } else {
-            -1
+            Some(-1)
         }
     }
 
-    pub fn waittid(&self, tid: usize) -> isize {
-        let mut tasks = self.tasks.lock();
-        if let Some(waited_task) = tasks.get(&tid).as_ref() {
-            if let TaskState::Zombie = waited_task.state() {
-                let code = waited_task.exit_code();
-                tasks.remove(&tid);
-                code as isize
+    /// Waits for a child to exit, returns its pid, or -1 if there is no such child.
+    /// With `WNOHANG`, returns 0 at once if the child is still running. With
+    /// `WUNTRACED`, also returns when the child is stopped. Fails with
+    /// `ERESTARTSYS` if interrupted by a signal.
+    pub fn waitpid(&self, pid: isize, exit_status: &mut i32, options: WaitOptions) -> isize {
+        let mut ret = 0;
+        let waited = self.wait_queue.wait_until_interruptible(|| {
+            if let Some(pid) = self.reap_child(pid, exit_status, options) {
+                ret = pid;
+                true
             } else {
-                -2
+                options.contains(WaitOptions::WNOHANG)
             }
-        } else {
-            // waited thread does not exist
-            -1
+        });
+        match waited {
+            Ok(()) => ret,
+            Err(err) => err,
         }
     }
+
+    /// Waits for a task to exit, returns its exit code, or -1 if there is no such task.
+    pub fn waittid(&self, tid: usize) -> isize {
+        let mut ret = -1;
+        self.wait_queue.wait_until(|| {
+            let mut tasks = self.tasks.lock();
+            match tasks.get(&tid).map(|t| t.state()) {
+                Some(TaskState::Zombie) => {
+                    let task = tasks.remove(&tid).unwrap();
+                    task.unmap_user_stack();
+                    self.dealloc_tid(tid);
+                    let task_times = *task.times.lock();
+                    let mut times = self.times.lock();
+                    times.utime += task_times.utime;
+                    times.stime += task_times.stime;
+                    ret = task.exit_code() as isize;
+                    true
+                }
+                Some(_) => false,
+                // waited thread does not exist
+                None => true,
+            }
+        });
+        ret
+    }
 }
 
 pub struct CurrentTask<'a>(pub &'a Arc<Task>);
@@ -703,50 +1478,128 @@ impl<'a> CurrentTask<'a> {
         TASK_MANAGER.lock().block_current(self)
     }
 
+    /// Blocks like `block_and_yield`, but is also resumed by a signal which
+    /// interrupts the blocking syscalls. Returns at once if there is such a
+    /// signal already, so the caller must check `is_interrupted` after this.
+    pub fn block_interruptible(&self) {
+        if self.is_interrupted() {
+            return;
+        }
+        self.interruptible.store(true, Ordering::SeqCst);
+        self.block_and_yield();
+        self.interruptible.store(false, Ordering::SeqCst);
+    }
+
+    /// Takes a pending signal in `set`, waiting for one until `deadline_ms`
+    /// if given. Fails with `EAGAIN` when the time is up, and with `EINTR` if
+    /// interrupted by another signal.
+    pub fn wait_signal(
+        &self,
+        set: SignalFlags,
+        deadline_ms: Option<usize>,
+    ) -> Result<SigInfo, isize> {
+        loop {
+            let inner = self.signal.lock();
+            let pending = inner.pending.signals;
+            drop(inner);
+            let pending = (pending | self.proc().pending_signals.lock().signals) & set;
+            if !pending.is_empty() {
+                return Ok(self.take_signal(pending.bits().trailing_zeros() as usize));
+            }
+            if self.is_interrupted() {
+                return Err(-EINTR);
+            }
+            if let Some(deadline_ms) = deadline_ms {
+                if get_time_ms() as usize >= deadline_ms {
+                    return Err(-EAGAIN);
+                }
+                add_timer(deadline_ms, self.0.clone());
+            }
+            self.signal.lock().sigwait = set;
+            self.block_interruptible();
+            self.signal.lock().sigwait = SignalFlags::empty();
+            if deadline_ms.is_some() {
+                remove_timer(self.0.clone());
+            }
+        }
+    }
+
     pub fn exit(&self, exit_code: i32) -> ! {
+        self.exit_with_status(exit_code, exited_status(exit_code))
+    }
+
+    /// Exits on a fatal signal, which kills the whole process.
+    pub fn exit_by_signal(&self, signum: usize) -> ! {
+        let signal = SignalFlags::from_bits(1 << signum).unwrap();
+        let mut exit_status = signaled_status(signum);
+        if signal.default_action() == DefaultAction::Core {
+            exit_status |= WCOREFLAG;
+        }
+        let proc = self.proc();
+        // the other tasks are killed by `SIGKILL`, which is not reported
+        if proc.state() == ProcState::Normal && !proc.is_root() {
+            proc.stop_with_status(exit_status);
+        }
+        drop(proc);
+        self.exit_with_status(-(signum as i32), exit_status)
+    }
+
+    fn exit_with_status(&self, exit_code: i32, exit_status: i32) -> ! {
+        // no preemption from now on, so waiters never see a half-exited task
+        crate::arch::disable_irqs();
+        let clear_child_tid = self.clear_child_tid.load(Ordering::SeqCst);
+        if clear_child_tid != 0 {
+            let mut child_tid: UserOutPtr<i32> = clear_child_tid.into();
+            if child_tid.check() {
+                child_tid.write(0);
+                // wakes a joiner waiting on the tid by futex
+                let vaddr = VirtAddr::new(clear_child_tid);
+                if let Some(key) = self.proc().vm().lock().translate(vaddr) {
+                    futex_wake(key, 1);
+                }
+            }
+        }
         self.set_state(TaskState::Zombie);
         self.set_exit_code(exit_code);
-        self.proc().task_exit(self.tid().as_usize(), exit_code);
+        let proc = self.proc();
+        proc.deadlock.lock().remove_task(self.tid().as_usize());
+        proc.task_exit(self.tid().as_usize(), exit_status);
+        proc.wait_queue.notify_all();
+        drop(proc);
         TASK_MANAGER.lock().exit_current(self, exit_code)
     }
 
-    fn check_pending_signals(&self, tf: &mut TrapFrame) -> Option<SignalFlags> {
-        for sig in 0..(MAX_SIG + 1) {
-            let (masked_singal, handling_sig_mask) = self.singal_metadata();
-            let signal = SignalFlags::from_bits(1 << sig).unwrap();
-            if masked_singal.contains(signal)
-                && (handling_sig_mask == None || handling_sig_mask.unwrap().contains(signal))
-            {
-                if SignalFlags::KERNEL_SIGNAL.contains(signal) {
-                    // signal is a kernel signal
-                    self.kernel_signal_handler(signal);
-                } else {
-                    // signal is a user signal
-                    self.user_signal_handler(sig, tf);
-                }
-                return Some(signal);
-            }
+    fn check_pending_signals(&self, tf: &mut TrapFrame, restart: &mut bool) -> Option<SignalFlags> {
+        let pending = self.unblocked_signals();
+        if pending.is_empty() {
+            return None;
         }
-        None
+        let sig = pending.bits().trailing_zeros() as usize;
+        let signal = SignalFlags::from_bits(1 << sig).unwrap();
+        let handler = self.proc().signal_actions().lock().table[sig].handler;
+        if SignalFlags::UNCATCHABLE.contains(signal) || handler == SIG_DFL {
+            self.default_signal_handler(sig);
+        } else if handler == SIG_IGN {
+            self.take_signal(sig);
+        } else {
+            self.user_signal_handler(sig, tf, restart);
+        }
+        Some(signal)
     }
 
-    pub fn handle_signals(&self, tf: &mut TrapFrame) -> Option<(i32, &'static str)> {
+    /// Delivers a pending signal, and blocks while the process is stopped.
+    /// Returns the signal which kills the task, if any.
+    pub fn handle_signals(&self, tf: &mut TrapFrame, restart: &mut bool) -> Option<usize> {
         loop {
-            self.check_pending_signals(tf);
-            let inner = self.signal.lock();
-            let frozen_flag = inner.frozen;
-            let killed_flag = inner.killed;
-            drop(inner);
-            if killed_flag {
-                break;
-            }
-            if frozen_flag {
-                self.block_and_yield();
-            } else {
-                break;
+            self.check_pending_signals(tf, restart);
+            let mut inner = self.signal.lock();
+            inner.frozen = inner.killed.is_none() && self.proc().is_stopped();
+            if !inner.frozen {
+                break inner.killed;
             }
+            drop(inner);
+            self.block_and_yield();
         }
-        self.signal.lock().signals.check_error()
     }
 }
 
@@ -0,0 +1,76 @@
+//! CPU time accounting with the system counter.
+
+use crate::timer::get_counter;
+
+/// User and system time of a task, in system counter cycles.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuTimes {
+    pub utime: u64,
+    pub stime: u64,
+    // counter value when the task last changed its mode or was switched in
+    stamp: u64,
+}
+
+impl CpuTimes {
+    /// Charges the time since the last stamp as user time, called when the
+    /// task enters the kernel.
+    pub fn account_user(&mut self) {
+        let now = get_counter();
+        self.utime += now - self.stamp;
+        self.stamp = now;
+    }
+
+    /// Charges the time since the last stamp as system time, called when the
+    /// task returns to user mode or is switched out.
+    pub fn account_system(&mut self) {
+        let now = get_counter();
+        self.stime += now - self.stamp;
+        self.stamp = now;
+    }
+
+    pub fn switch_in(&mut self) {
+        self.stamp = get_counter();
+    }
+}
+
+/// CPU times of a process, including those of its waited-for children.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProcTimes {
+    pub utime: u64,
+    pub stime: u64,
+    pub cutime: u64,
+    pub cstime: u64,
+}
+
+/// Interval timer counting down in real time, sends `SIGALRM`.
+pub const ITIMER_REAL: usize = 0;
+/// Interval timer counting down in user time, sends `SIGVTALRM`.
+pub const ITIMER_VIRTUAL: usize = 1;
+/// Interval timer counting down in user and system time, sends `SIGPROF`.
+pub const ITIMER_PROF: usize = 2;
+
+/// An interval timer set by `setitimer`, in system counter cycles of the time
+/// it counts.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ITimer {
+    /// When it expires, 0 if disarmed.
+    pub deadline: u64,
+    /// Period after the first expiry, 0 if it expires once.
+    pub interval: u64,
+}
+
+impl ITimer {
+    /// Arms the timer again after it expires at `now`, skipping the periods
+    /// already gone. Returns false if it is disarmed instead.
+    pub fn reload(&mut self, now: u64) -> bool {
+        if self.interval == 0 {
+            self.deadline = 0;
+            return false;
+        }
+        self.deadline += self.interval;
+        if self.deadline <= now {
+            self.deadline = now + self.interval;
+        }
+        true
+    }
+}
@@ -7,20 +7,56 @@ use crate::arch::gicv2::irq_set_mask;
 use crate::config::TICKS_PER_SEC;
 use crate::sync::LazyInit;
 use crate::sync::Mutex;
-use crate::task::Task;
+use crate::task::{Process, Task, TaskState};
 use alloc::collections::BinaryHeap;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use core::cmp::Ordering;
 
 const PHYS_TIMER_IRQ_NUM: usize = 30;
 
 const MSEC_PER_SEC: u64 = 1000;
+const USEC_PER_SEC: u64 = 1_000_000;
 
 static CLOCK_FREQ: LazyInit<u64> = LazyInit::new();
 pub static TIMERS: LazyInit<Mutex<BinaryHeap<TimerCondVar>>> = LazyInit::new();
 
+/// `a * b / c`, computed in 128 bits so that the product does not overflow
+/// after a long uptime, and saturated to `u64::MAX`.
+fn mul_div(a: u64, b: u64, c: u64) -> u64 {
+    u64::try_from(a as u128 * b as u128 / c as u128).unwrap_or(u64::MAX)
+}
+
 pub fn get_time_ms() -> u64 {
-    CNTPCT_EL0.get() * MSEC_PER_SEC / *CLOCK_FREQ
+    mul_div(CNTPCT_EL0.get(), MSEC_PER_SEC, *CLOCK_FREQ)
+}
+
+/// Current value of the system counter, which counts at `CNTFRQ_EL0`.
+pub fn get_counter() -> u64 {
+    CNTPCT_EL0.get()
+}
+
+pub fn counter_to_us(counter: u64) -> u64 {
+    mul_div(counter, USEC_PER_SEC, *CLOCK_FREQ)
+}
+
+/// Converts counter cycles to clock ticks, which are `TICKS_PER_SEC` per second.
+pub fn counter_to_ticks(counter: u64) -> u64 {
+    mul_div(counter, TICKS_PER_SEC, *CLOCK_FREQ)
+}
+
+pub fn counter_to_secs(counter: u64) -> u64 {
+    counter / *CLOCK_FREQ
+}
+
+pub fn us_to_counter(us: u64) -> u64 {
+    mul_div(us, *CLOCK_FREQ, USEC_PER_SEC)
+}
+
+/// The first `get_time_ms` when the counter has reached `counter`.
+fn counter_to_ms_ceil(counter: u64) -> u64 {
+    let freq = *CLOCK_FREQ as u128;
+    ((counter as u128 * MSEC_PER_SEC as u128 + freq - 1) / freq) as u64
 }
 
 pub fn set_next_trigger() {
@@ -37,16 +73,31 @@ pub fn init() {
 
 pub fn add_timer(expire_ms: usize, task: Arc<Task>) {
     let mut timers = TIMERS.lock();
-    timers.push(TimerCondVar { expire_ms, task });
+    timers.push(TimerCondVar {
+        expire_ms,
+        event: TimerEvent::Wake(task),
+    });
+}
+
+/// Fires the `ITIMER_REAL` of `proc` when the counter reaches `deadline`.
+pub fn add_real_timer(deadline: u64, proc: &Arc<Process>) {
+    let mut timers = TIMERS.lock();
+    timers.push(TimerCondVar {
+        expire_ms: counter_to_ms_ceil(deadline) as usize,
+        event: TimerEvent::RealTimer {
+            proc: Arc::downgrade(proc),
+            deadline,
+        },
+    });
 }
 
-#[allow(dead_code)]
 pub fn remove_timer(task: Arc<Task>) {
     let mut timers = TIMERS.lock();
     let mut temp = BinaryHeap::<TimerCondVar>::new();
     for condvar in timers.drain() {
-        if Arc::as_ptr(&task) != Arc::as_ptr(&condvar.task) {
-            temp.push(condvar);
+        match &condvar.event {
+            TimerEvent::Wake(t) if Arc::ptr_eq(t, &task) => {}
+            _ => temp.push(condvar),
         }
     }
     timers.clear();
@@ -55,20 +106,42 @@ pub fn remove_timer(task: Arc<Task>) {
 
 pub fn check_timer() {
     let current_ms = get_time_ms();
+    let mut fired = Vec::new();
     let mut timers = TIMERS.lock();
     while let Some(timer) = timers.peek() {
-        if timer.expire_ms <= current_ms as _ {
-            timer.task.resume();
-            timers.pop();
-        } else {
+        if timer.expire_ms > current_ms as _ {
             break;
         }
+        match timers.pop().unwrap().event {
+            TimerEvent::Wake(task) => {
+                // the task may be woken by others before its timer is removed
+                if task.state() == TaskState::Blocking {
+                    task.resume();
+                }
+            }
+            TimerEvent::RealTimer { proc, deadline } => fired.push((proc, deadline)),
+        }
+    }
+    drop(timers);
+    // the timers may be armed again
+    for (proc, deadline) in fired {
+        if let Some(proc) = proc.upgrade() {
+            proc.fire_real_timer(deadline);
+        }
     }
 }
 
 pub struct TimerCondVar {
     pub expire_ms: usize,
-    pub task: Arc<Task>,
+    pub event: TimerEvent,
+}
+
+pub enum TimerEvent {
+    /// Resumes the blocked task.
+    Wake(Arc<Task>),
+    /// Fires the `ITIMER_REAL` of the process, unless the timer has been set
+    /// again and no longer expires at `deadline`.
+    RealTimer { proc: Weak<Process>, deadline: u64 },
 }
 
 impl PartialEq for TimerCondVar {
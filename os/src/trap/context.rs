@@ -37,6 +37,21 @@ impl TrapFrame {
         tf
     }
 
+    /// Whether the trap is taken from user mode (EL0).
+    pub fn is_user(&self) -> bool {
+        self.spsr & 0b1111 == 0
+    }
+
+    /// Resumes the user context `saved`, which is read from user memory. Only
+    /// the condition flags are taken from its `spsr`, so that it cannot return
+    /// to the kernel or change the interrupt masks.
+    pub fn restore_user(&mut self, saved: &TrapFrame) {
+        const NZCV: u64 = 0xf << 28;
+        let spsr = (self.spsr & !NZCV) | (saved.spsr & NZCV);
+        *self = *saved;
+        self.spsr = spsr;
+    }
+
     pub fn new_fork(&self) -> Self {
         let mut tf = *self;
         tf.r[0] = 0; // for child process, fork returns 0
@@ -75,3 +90,88 @@ impl TrapFrame {
         )
     }
 }
+
+/// FP/SIMD registers of a user task.
+///
+/// The kernel is built without FP/SIMD, so the registers of the running user
+/// task stay live in hardware across traps. They are only saved and restored
+/// on context switches and around signal handlers.
+#[repr(C, align(16))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FpState {
+    /// Vector registers (V0..V31).
+    pub v: [u128; 32],
+    /// Floating-point Control Register.
+    pub fpcr: u64,
+    /// Floating-point Status Register.
+    pub fpsr: u64,
+}
+
+impl FpState {
+    /// The registers in hardware.
+    pub fn current() -> Self {
+        let mut state = Self::default();
+        state.save();
+        state
+    }
+
+    /// Saves the registers in hardware.
+    pub fn save(&mut self) {
+        unsafe {
+            asm!("
+                stp     q0, q1, [x0, 0 * 16]
+                stp     q2, q3, [x0, 2 * 16]
+                stp     q4, q5, [x0, 4 * 16]
+                stp     q6, q7, [x0, 6 * 16]
+                stp     q8, q9, [x0, 8 * 16]
+                stp     q10, q11, [x0, 10 * 16]
+                stp     q12, q13, [x0, 12 * 16]
+                stp     q14, q15, [x0, 14 * 16]
+                stp     q16, q17, [x0, 16 * 16]
+                stp     q18, q19, [x0, 18 * 16]
+                stp     q20, q21, [x0, 20 * 16]
+                stp     q22, q23, [x0, 22 * 16]
+                stp     q24, q25, [x0, 24 * 16]
+                stp     q26, q27, [x0, 26 * 16]
+                stp     q28, q29, [x0, 28 * 16]
+                stp     q30, q31, [x0, 30 * 16]
+                mrs     x9, fpcr
+                mrs     x10, fpsr
+                stp     x9, x10, [x0, 32 * 16]",
+                in("x0") self,
+                out("x9") _,
+                out("x10") _,
+            )
+        }
+    }
+
+    /// Loads the registers into hardware.
+    pub fn restore(&self) {
+        unsafe {
+            asm!("
+                ldp     q0, q1, [x0, 0 * 16]
+                ldp     q2, q3, [x0, 2 * 16]
+                ldp     q4, q5, [x0, 4 * 16]
+                ldp     q6, q7, [x0, 6 * 16]
+                ldp     q8, q9, [x0, 8 * 16]
+                ldp     q10, q11, [x0, 10 * 16]
+                ldp     q12, q13, [x0, 12 * 16]
+                ldp     q14, q15, [x0, 14 * 16]
+                ldp     q16, q17, [x0, 16 * 16]
+                ldp     q18, q19, [x0, 18 * 16]
+                ldp     q20, q21, [x0, 20 * 16]
+                ldp     q22, q23, [x0, 22 * 16]
+                ldp     q24, q25, [x0, 24 * 16]
+                ldp     q26, q27, [x0, 26 * 16]
+                ldp     q28, q29, [x0, 28 * 16]
+                ldp     q30, q31, [x0, 30 * 16]
+                ldp     x9, x10, [x0, 32 * 16]
+                msr     fpcr, x9
+                msr     fpsr, x10",
+                in("x0") self,
+                out("x9") _,
+                out("x10") _,
+            )
+        }
+    }
+}
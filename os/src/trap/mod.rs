@@ -1,13 +1,18 @@
 mod context;
 
-pub use context::TrapFrame;
+pub use context::{FpState, TrapFrame};
 
 use core::arch::global_asm;
 
 use cortex_a::registers::{ESR_EL1, FAR_EL1, VBAR_EL1};
 use tock_registers::interfaces::{Readable, Writeable};
 
-use crate::{syscall::syscall, task::CurrentTask};
+use crate::syscall::errno::ERESTARTSYS;
+use crate::syscall::{syscall, SYSCALL_SIGRETURN};
+use crate::task::{
+    CurrentTask, SigInfo, SignalFlags, BUS_ADRALN, FPE_FLTDIV, FPE_FLTINV, FPE_FLTOVF, FPE_FLTRES,
+    FPE_FLTUND, FPE_FLTUNK, ILL_ILLOPC, SEGV_ACCERR, SEGV_MAPERR,
+};
 
 global_asm!(include_str!("trap.S"));
 
@@ -55,39 +60,69 @@ fn invalid_exception(tf: &mut TrapFrame, kind: TrapKind, source: TrapSource) {
 
 #[no_mangle]
 fn handle_sync_exception(tf: &mut TrapFrame) {
+    enter_kernel(tf);
+    let mut restart = false;
     let esr = ESR_EL1.extract();
     match esr.read_as_enum(ESR_EL1::EC) {
         Some(ESR_EL1::EC::Value::Unknown) => {
-            println!(
-                "[kernel] Unknown exception @ {:#x}, kernel killed it.",
-                tf.elr
-            );
-            CurrentTask::get().exit(-1);
+            debug!("[kernel] Unknown exception @ {:#x}.", tf.elr);
+            user_fault(tf, SignalFlags::SIGILL, ILL_ILLOPC, tf.elr as usize);
         }
         Some(ESR_EL1::EC::Value::SVC64) => {
-            tf.r[0] = syscall(tf.r[8] as _, [tf.r[0] as _, tf.r[1] as _, tf.r[2] as _], tf) as u64
+            let id = tf.r[8] as usize;
+            let args = [
+                tf.r[0] as _,
+                tf.r[1] as _,
+                tf.r[2] as _,
+                tf.r[3] as _,
+                tf.r[4] as _,
+                tf.r[5] as _,
+            ];
+            let ret = syscall(id, args, tf);
+            // the registers restored by `sigreturn` are never a syscall result
+            if ret == -ERESTARTSYS && id != SYSCALL_SIGRETURN {
+                // make the `svc` again on return, unless a signal handler
+                // without `SA_RESTART` runs first
+                tf.r[0] = args[0] as u64;
+                tf.elr -= 4;
+                restart = true;
+            } else {
+                tf.r[0] = ret as u64;
+            }
         }
         Some(ESR_EL1::EC::Value::DataAbortLowerEL)
         | Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => {
             let iss = esr.read(ESR_EL1::ISS);
-            println!(
-                "[kernel] Data Abort @ {:#x}, FAR = {:#x}, ISS = {:#x}, kernel killed it.",
+            debug!(
+                "[kernel] Data Abort @ {:#x}, FAR = {:#x}, ISS = {:#x}.",
                 tf.elr,
                 FAR_EL1.get(),
                 iss
             );
-            CurrentTask::get().exit(-1);
+            let (signal, code) = abort_signal(iss);
+            user_fault(tf, signal, code, FAR_EL1.get() as usize);
         }
         Some(ESR_EL1::EC::Value::InstrAbortLowerEL)
         | Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => {
             let iss = esr.read(ESR_EL1::ISS);
-            println!(
-                "[kernel] Instruction Abort @ {:#x}, FAR = {:#x}, ISS = {:#x}, kernel killed it.",
+            debug!(
+                "[kernel] Instruction Abort @ {:#x}, FAR = {:#x}, ISS = {:#x}.",
                 tf.elr,
                 FAR_EL1.get(),
                 iss
             );
-            CurrentTask::get().exit(-1);
+            let (signal, code) = abort_signal(iss);
+            user_fault(tf, signal, code, FAR_EL1.get() as usize);
+        }
+        Some(ESR_EL1::EC::Value::PCAlignmentFault) => {
+            user_fault(tf, SignalFlags::SIGBUS, BUS_ADRALN, FAR_EL1.get() as usize);
+        }
+        Some(ESR_EL1::EC::Value::SPAlignmentFault) => {
+            user_fault(tf, SignalFlags::SIGBUS, BUS_ADRALN, tf.usp as usize);
+        }
+        Some(ESR_EL1::EC::Value::TrappedFP64) => {
+            let code = fpe_code(esr.read(ESR_EL1::ISS));
+            user_fault(tf, SignalFlags::SIGFPE, code, tf.elr as usize);
         }
         _ => {
             panic!(
@@ -100,18 +135,85 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
         }
     }
 
-    let task = CurrentTask::get();
-    if let Some((errno, msg)) = task.handle_signals(tf) {
-        warn!("Error in handling signals {} {}", errno, msg);
-        task.exit(errno);
-    }
-    drop(task);
+    handle_signals(tf, restart);
+    leave_kernel(tf);
 }
 
 #[no_mangle]
-fn handle_irq_exception(_tf: &mut TrapFrame) {
+fn handle_irq_exception(tf: &mut TrapFrame) {
+    enter_kernel(tf);
     if crate::arch::gicv2::handle_irq() == IrqHandlerResult::Reschedule {
         crate::timer::check_timer();
+        crate::task::timer_tick();
+        crate::fs::tty_poll();
+        let proc = CurrentTask::get().proc();
+        proc.check_cpu_limit();
+        proc.check_cpu_timers();
+        drop(proc);
         CurrentTask::get().yield_now();
     }
+    // a task which never makes syscalls must also be stoppable and killable
+    if tf.is_user() {
+        handle_signals(tf, false);
+    }
+    leave_kernel(tf);
+}
+
+/// Starts charging system time if the trap comes from user mode.
+fn enter_kernel(tf: &TrapFrame) {
+    if tf.is_user() {
+        CurrentTask::get().times.lock().account_user();
+    }
+}
+
+/// Starts charging user time if the trap returns to user mode.
+fn leave_kernel(tf: &TrapFrame) {
+    if tf.is_user() {
+        CurrentTask::get().times.lock().account_system();
+    }
+}
+
+/// Raises the synchronous fault `signal` with `code` at address `addr` in the
+/// current task, see `Task::force_signal`. A fault in the kernel is a bug, as
+/// the user pointers are checked before they are accessed, so it panics.
+fn user_fault(tf: &TrapFrame, signal: SignalFlags, code: i32, addr: usize) {
+    if !tf.is_user() {
+        panic!(
+            "Kernel fault @ {:#x}: signal {:?}, code {}, address {:#x}",
+            tf.elr, signal, code, addr
+        );
+    }
+    CurrentTask::get().force_signal(SigInfo::fault(signal, code, addr));
+}
+
+/// The signal and its code for a data or instruction abort, from the fault
+/// status code in `iss`.
+fn abort_signal(iss: u64) -> (SignalFlags, i32) {
+    match iss & 0x3f {
+        // alignment fault
+        0b10_0001 => (SignalFlags::SIGBUS, BUS_ADRALN),
+        // translation fault at any level
+        0b00_0100..=0b00_0111 => (SignalFlags::SIGSEGV, SEGV_MAPERR),
+        _ => (SignalFlags::SIGSEGV, SEGV_ACCERR),
+    }
+}
+
+/// The code of `SIGFPE` for a trapped floating-point exception, from the
+/// flags in `iss`.
+fn fpe_code(iss: u64) -> i32 {
+    const CODES: [i32; 5] = [FPE_FLTINV, FPE_FLTDIV, FPE_FLTOVF, FPE_FLTUND, FPE_FLTRES];
+    (0..CODES.len())
+        .find(|bit| iss & (1 << bit) != 0)
+        .map_or(FPE_FLTUNK, |bit| CODES[bit])
+}
+
+/// Handles the pending signals before returning to user mode, `restart` is
+/// whether the trap is a syscall to be made again.
+fn handle_signals(tf: &mut TrapFrame, mut restart: bool) {
+    let task = CurrentTask::get();
+    if let Some(signum) = task.handle_signals(tf, &mut restart) {
+        warn!("Killed by signal {}", signum);
+        task.exit_by_signal(signum);
+    }
+    drop(task);
 }
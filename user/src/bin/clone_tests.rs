@@ -0,0 +1,262 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::arch::asm;
+use core::ptr::{null, null_mut};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+const STACK_SIZE: usize = 0x2000;
+const TLS: usize = 0x1234_5678;
+
+static mut STACK: Stack<STACK_SIZE> = Stack::new();
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static CHILD_TID: AtomicI32 = AtomicI32::new(-1);
+static FLAG: AtomicBool = AtomicBool::new(false);
+
+fn thread_pointer() -> usize {
+    let tp;
+    unsafe { asm!("mrs {}, tpidr_el0", out(reg) tp) };
+    tp
+}
+
+extern "C" fn count(arg: usize) -> i32 {
+    COUNTER.fetch_add(1, Ordering::SeqCst);
+    arg as i32
+}
+
+extern "C" fn check_tls(_arg: usize) -> i32 {
+    COUNTER.fetch_add(1, Ordering::SeqCst);
+    if thread_pointer() == TLS {
+        0
+    } else {
+        1
+    }
+}
+
+extern "C" fn close_fd(fd: usize) -> i32 {
+    close(fd) as i32
+}
+
+fn clone_test_thread() {
+    let flags = CloneFlags::CLONE_VM
+        | CloneFlags::CLONE_FILES
+        | CloneFlags::CLONE_SIGHAND
+        | CloneFlags::CLONE_THREAD
+        | CloneFlags::CLONE_SETTLS
+        | CloneFlags::CLONE_PARENT_SETTID
+        | CloneFlags::CLONE_CHILD_CLEARTID;
+    let mut parent_tid: i32 = -1;
+    let tid = clone(
+        check_tls,
+        unsafe { STACK.top() },
+        flags,
+        0,
+        &mut parent_tid,
+        TLS,
+        &CHILD_TID as *const _ as *mut i32,
+    );
+    assert!(tid > 0);
+    assert_eq!(parent_tid as isize, tid);
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(COUNTER.load(Ordering::SeqCst), 1);
+    // cleared by the kernel when the thread exits
+    assert_eq!(CHILD_TID.load(Ordering::SeqCst), 0);
+    // the thread pointer of the caller is not changed
+    assert_ne!(thread_pointer(), TLS);
+}
+
+fn clone_test_process() {
+    let pid = clone(
+        count,
+        unsafe { STACK.top() },
+        CloneFlags::empty(),
+        7,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 7);
+    // the child counts in its own copy of the address space
+    assert_eq!(COUNTER.load(Ordering::SeqCst), 0);
+}
+
+fn clone_test_vm() {
+    let pid = clone(
+        count,
+        unsafe { STACK.top() },
+        CloneFlags::CLONE_VM,
+        0,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 0);
+    assert_eq!(COUNTER.load(Ordering::SeqCst), 1);
+}
+
+fn clone_test_files() {
+    // the child closes the fd in a copy of the fd table
+    let fd = dup(1);
+    assert!(fd > 0);
+    let pid = clone(
+        close_fd,
+        unsafe { STACK.top() },
+        CloneFlags::empty(),
+        fd as usize,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert_eq!(wait_exited(pid), 0);
+    assert_eq!(close(fd as usize), 0);
+    // the child closes the fd in the shared fd table
+    let fd = dup(1);
+    assert!(fd > 0);
+    let pid = clone(
+        close_fd,
+        unsafe { STACK.top() },
+        CloneFlags::CLONE_FILES,
+        fd as usize,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert_eq!(wait_exited(pid), 0);
+    assert_eq!(close(fd as usize), -EBADF);
+}
+
+fn clone_test_invalid() {
+    let invalid = [
+        CloneFlags::CLONE_THREAD | CloneFlags::CLONE_VM,
+        CloneFlags::CLONE_SIGHAND,
+    ];
+    for flags in invalid {
+        let ret = clone(
+            count,
+            unsafe { STACK.top() },
+            flags,
+            0,
+            null_mut(),
+            0,
+            null_mut(),
+        );
+        assert_eq!(ret, -EINVAL);
+    }
+}
+
+extern "C" fn exit_thread(arg: usize) -> i32 {
+    arg as i32
+}
+
+fn exit_created_thread(arg: usize) -> ! {
+    exit(arg as i32)
+}
+
+fn clone_test_recycle() {
+    // tids and the user stacks of exited threads are reused
+    let first_tid = thread_create(exit_created_thread as usize, 0);
+    assert!(first_tid > 0);
+    assert_eq!(waittid(first_tid as usize), 0);
+    for i in 1..64 {
+        let tid = thread_create(exit_created_thread as usize, i);
+        assert_eq!(tid, first_tid);
+        assert_eq!(waittid(tid as usize), i as isize);
+    }
+    let flags = CloneFlags::CLONE_VM | CloneFlags::CLONE_SIGHAND | CloneFlags::CLONE_THREAD;
+    for i in 0..64 {
+        let tid = clone(
+            exit_thread,
+            unsafe { STACK.top() },
+            flags,
+            i,
+            null_mut(),
+            0,
+            null_mut(),
+        );
+        assert_eq!(tid, first_tid);
+        assert_eq!(waittid(tid as usize), i as isize);
+    }
+}
+
+fn wait_flag(_arg: usize) -> ! {
+    while !FLAG.load(Ordering::SeqCst) {
+        sched_yield();
+    }
+    exit(0)
+}
+
+extern "C" fn create_thread(arg: usize) -> i32 {
+    let tid = thread_create(exit_created_thread as usize, arg);
+    if tid <= 0 {
+        return -1;
+    }
+    waittid(tid as usize) as i32
+}
+
+fn clone_test_vm_thread() {
+    // the threads of processes sharing the address space get stacks of
+    // their own, and the stack of one is unmapped when it is waited for
+    let tid = thread_create(wait_flag as usize, 0);
+    assert!(tid > 0);
+    let pid = clone(
+        create_thread,
+        unsafe { STACK.top() },
+        CloneFlags::CLONE_VM,
+        5,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 5);
+    FLAG.store(true, Ordering::SeqCst);
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+extern "C" fn exec_hello(_arg: usize) -> i32 {
+    exec("hello_world\0", &[null::<u8>()]) as i32
+}
+
+fn clone_test_sighand_exec() {
+    // `exec` in a child sharing the signal actions keeps the parent's handlers
+    set_handler(
+        SIGUSR1,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    let flags = CloneFlags::CLONE_VM | CloneFlags::CLONE_SIGHAND | CloneFlags::CLONE_VFORK;
+    let pid = clone(
+        exec_hello,
+        unsafe { STACK.top() },
+        flags,
+        0,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert_eq!(wait_exited(pid), 0);
+    kill(getpid() as usize, SIGUSR1);
+    check_log(&[SIGUSR1]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 8] = [
+        (clone_test_thread, "clone_test_thread"),
+        (clone_test_process, "clone_test_process"),
+        (clone_test_vm, "clone_test_vm"),
+        (clone_test_files, "clone_test_files"),
+        (clone_test_invalid, "clone_test_invalid"),
+        (clone_test_recycle, "clone_test_recycle"),
+        (clone_test_vm_thread, "clone_test_vm_thread"),
+        (clone_test_sighand_exec, "clone_test_sighand_exec"),
+    ];
+    run_tests("clone_tests", &tests)
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, getrusage, times, waitpid, Rusage, Tms, CLK_TCK, RUSAGE_CHILDREN,
+    RUSAGE_SELF,
+};
+
+fn spin(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+fn usage_ms(who: isize) -> i64 {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(who, &mut usage), 0);
+    let utime = usage.ru_utime.sec * 1000 + usage.ru_utime.usec / 1000;
+    let stime = usage.ru_stime.sec * 1000 + usage.ru_stime.usec / 1000;
+    utime + stime
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    spin(200);
+    let self_ms = usage_ms(RUSAGE_SELF);
+    println!("self: {}ms", self_ms);
+    assert!(self_ms >= 100);
+    assert_eq!(usage_ms(RUSAGE_CHILDREN), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        spin(300);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    // the times of the reaped child are rolled up
+    let children_ms = usage_ms(RUSAGE_CHILDREN);
+    println!("children: {}ms", children_ms);
+    assert!(children_ms >= 150);
+
+    let mut tms = Tms::default();
+    assert!(times(&mut tms) > 0);
+    assert!((tms.tms_cutime + tms.tms_cstime) * 1000 / CLK_TCK as i64 >= 150);
+    println!("cpu_times passed!");
+    0
+}
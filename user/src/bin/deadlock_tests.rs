@@ -0,0 +1,138 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+const N: usize = 5;
+
+static FORKS: [AtomicUsize; N] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+static HOLDING: AtomicUsize = AtomicUsize::new(0);
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static SEM: AtomicUsize = AtomicUsize::new(0);
+
+fn deadlock_test_enable() {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    assert_eq!(enable_deadlock_detect(false), 0);
+    // nothing is refused while disabled
+    let sem = semaphore_create(0) as usize;
+    semaphore_up(sem);
+    assert_eq!(semaphore_down(sem), 0);
+}
+
+fn deadlock_test_safe() {
+    // holding and waiting for a lock which will be given back is safe
+    assert_eq!(enable_deadlock_detect(true), 0);
+    let mutex = mutex_blocking_create() as usize;
+    assert_eq!(mutex_lock(mutex), 0);
+    mutex_unlock(mutex);
+    let sem = semaphore_create(2) as usize;
+    assert_eq!(semaphore_down(sem), 0);
+    assert_eq!(semaphore_down(sem), 0);
+    semaphore_up(sem);
+    semaphore_up(sem);
+}
+
+/// Takes the left fork, then the right one once everyone holds a fork.
+/// Exits with 1 if it is refused the right fork.
+fn philosopher(id: usize) -> ! {
+    let left = FORKS[id].load(Ordering::SeqCst);
+    let right = FORKS[(id + 1) % N].load(Ordering::SeqCst);
+    assert_eq!(mutex_lock(left), 0);
+    HOLDING.fetch_add(1, Ordering::SeqCst);
+    while HOLDING.load(Ordering::SeqCst) < N {
+        sched_yield();
+    }
+    let ret = mutex_lock(right);
+    if ret == -EDEADLK {
+        mutex_unlock(left);
+        exit(1);
+    }
+    assert_eq!(ret, 0);
+    mutex_unlock(right);
+    mutex_unlock(left);
+    exit(0)
+}
+
+fn deadlock_test_philosophers() {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    for fork in FORKS.iter() {
+        fork.store(mutex_blocking_create() as usize, Ordering::SeqCst);
+    }
+    let mut tids = [0; N];
+    for (id, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(philosopher as usize, id);
+    }
+    // the last request for a right fork closes the cycle and is refused
+    let refused: isize = tids.iter().map(|tid| waittid(*tid as usize)).sum();
+    assert_eq!(refused, 1);
+}
+
+fn hold_sem_wait_mutex(_arg: usize) -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    let sem = SEM.load(Ordering::SeqCst);
+    assert_eq!(semaphore_down(sem), 0);
+    HOLDING.store(1, Ordering::SeqCst);
+    assert_eq!(mutex_lock(mutex), 0);
+    mutex_unlock(mutex);
+    semaphore_up(sem);
+    exit(0)
+}
+
+fn deadlock_test_semaphore() {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    let mutex = mutex_blocking_create() as usize;
+    let sem = semaphore_create(1) as usize;
+    MUTEX.store(mutex, Ordering::SeqCst);
+    SEM.store(sem, Ordering::SeqCst);
+    assert_eq!(mutex_lock(mutex), 0);
+    let tid = thread_create(hold_sem_wait_mutex as usize, 0);
+    while HOLDING.load(Ordering::SeqCst) == 0 {
+        sched_yield();
+    }
+    // lets the thread block on the mutex
+    sleep(20);
+    // the thread holds the semaphore and may wait for the mutex
+    assert_eq!(semaphore_down(sem), -EDEADLK);
+    mutex_unlock(mutex);
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(semaphore_down(sem), 0);
+}
+
+fn deadlock_test_aliases() {
+    // the handles of a mutex are the same resource
+    assert_eq!(enable_deadlock_detect(true), 0);
+    let mutex = mutex_blocking_create() as usize;
+    let alias = dup(mutex);
+    assert!(alias > 0);
+    assert_eq!(mutex_lock(mutex), 0);
+    assert_eq!(mutex_lock(alias as usize), -EDEADLK);
+    mutex_unlock(mutex);
+    // a resource is still tracked while a handle is left
+    let sem = semaphore_create(1) as usize;
+    let alias = dup(sem);
+    assert_eq!(close(alias as usize), 0);
+    assert_eq!(semaphore_down(sem), 0);
+    semaphore_up(sem);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 5] = [
+        (deadlock_test_enable, "deadlock_test_enable"),
+        (deadlock_test_safe, "deadlock_test_safe"),
+        (deadlock_test_philosophers, "deadlock_test_philosophers"),
+        (deadlock_test_semaphore, "deadlock_test_semaphore"),
+        (deadlock_test_aliases, "deadlock_test_aliases"),
+    ];
+    run_tests("deadlock_tests", &tests)
+}
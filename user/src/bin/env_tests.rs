@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::string::String;
+use core::ptr::null;
+use user_lib::testing::*;
+use user_lib::*;
+
+fn env_test_set_get() {
+    assert_eq!(getenv("FOO"), None);
+    assert_eq!(setenv("FOO", "bar"), 0);
+    assert_eq!(getenv("FOO"), Some(String::from("bar")));
+    assert_eq!(setenv("FOO", "baz"), 0);
+    assert_eq!(getenv("FOO"), Some(String::from("baz")));
+    // a name which is the prefix of another one
+    assert_eq!(getenv("FO"), None);
+    assert_eq!(unsetenv("FOO"), 0);
+    assert_eq!(getenv("FOO"), None);
+    assert_eq!(setenv("A=B", "c"), -EINVAL);
+    assert_eq!(setenv("", "c"), -EINVAL);
+}
+
+fn env_test_fork() {
+    setenv("FOO", "bar");
+    let pid = fork();
+    if pid == 0 {
+        exit(if getenv("FOO").as_deref() == Some("bar") {
+            0
+        } else {
+            1
+        });
+    }
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn env_test_exec() {
+    setenv("FOO", "bar");
+    let pid = fork();
+    if pid == 0 {
+        exec(
+            "env_tests\0",
+            &["env_tests\0".as_ptr(), "bar\0".as_ptr(), null::<u8>()],
+        );
+        exit(-1);
+    }
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn env_test_execve() {
+    setenv("FOO", "bar");
+    let pid = fork();
+    if pid == 0 {
+        // only the given environment is passed
+        execve(
+            "env_tests\0",
+            &["env_tests\0".as_ptr(), "baz\0".as_ptr(), null::<u8>()],
+            &["FOO=baz\0".as_ptr(), null::<u8>()],
+        );
+        exit(-1);
+    }
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn env_test_spawn() {
+    setenv("FOO", "qux");
+    let pid = spawn(
+        "env_tests\0",
+        &["env_tests\0".as_ptr(), "qux\0".as_ptr(), null::<u8>()],
+        &[],
+    );
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 0);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 {
+        // executed by a test, checks the passed `FOO`
+        if getenv("FOO").as_deref() == Some(argv[1]) {
+            return 0;
+        }
+        println!("unexpected environment: {:?}", environ());
+        return 1;
+    }
+    let tests: [(fn(), &str); 5] = [
+        (env_test_set_get, "env_test_set_get"),
+        (env_test_fork, "env_test_fork"),
+        (env_test_exec, "env_test_exec"),
+        (env_test_execve, "env_test_execve"),
+        (env_test_spawn, "env_test_spawn"),
+    ];
+    run_tests("env_tests", &tests)
+}
@@ -3,7 +3,7 @@
 
 #[macro_use]
 extern crate user_lib;
-use user_lib::{exit, fork, sched_yield, wait, waitpid};
+use user_lib::{exit, fork, sched_yield, wait, waitpid, WEXITSTATUS, WIFEXITED};
 
 const MAGIC: i32 = -0x10384;
 
@@ -22,7 +22,8 @@ pub fn main() -> i32 {
     }
     println!("I am the parent, waiting now..");
     let mut xstate: i32 = 0;
-    assert!(waitpid(pid as usize, &mut xstate) == pid && xstate == MAGIC);
+    assert!(waitpid(pid as usize, &mut xstate) == pid && WIFEXITED(xstate));
+    assert_eq!(WEXITSTATUS(xstate), MAGIC & 0xff);
     assert!(waitpid(pid as usize, &mut xstate) < 0 && wait(&mut xstate) <= 0);
     println!("waitpid {} ok.", pid);
     println!("exit passed!");
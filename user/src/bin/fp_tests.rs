@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::arch::asm;
+use user_lib::testing::*;
+use user_lib::*;
+
+const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+
+const ROUNDS: usize = 1000;
+
+fn fp_test_compute() {
+    // the Basel problem, converges to pi^2 / 6
+    let mut sum = 0.0f64;
+    for i in 1..100_000 {
+        let x = i as f64;
+        sum += 1.0 / (x * x);
+    }
+    let pi2_6 = 1.644_934_066_848_226_4f64;
+    assert!(sum < pi2_6 && pi2_6 - sum < 1e-4);
+}
+
+/// Keeps `(lo, hi)` in `v8` across `ROUNDS` yields, returns what is read back.
+fn hold_v8(lo: u64, hi: u64) -> (u64, u64) {
+    let (out_lo, out_hi): (u64, u64);
+    unsafe {
+        asm!("
+            fmov    d8, {lo}
+            fmov    v8.d[1], {hi}
+        1:
+            mov     x8, {yield_}
+            svc     #0
+            subs    {n}, {n}, #1
+            b.ne    1b
+            fmov    {lo}, d8
+            fmov    {hi}, v8.d[1]",
+            yield_ = in(reg) SYSCALL_YIELD,
+            lo = inout(reg) lo => out_lo,
+            hi = inout(reg) hi => out_hi,
+            n = inout(reg) ROUNDS => _,
+            out("x0") _,
+            out("x8") _,
+            out("v8") _,
+        );
+    }
+    (out_lo, out_hi)
+}
+
+fn fp_test_switch() {
+    // the tasks hold different values in the same register while they are
+    // switched to each other
+    let mut pids = [0; 2];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            let lo = 0x1111_1111_1111_1111 * (i as u64 + 1);
+            let hi = 0x2222_2222_2222_2222 * (i as u64 + 1);
+            exit(if hold_v8(lo, hi) == (lo, hi) { 0 } else { 1 });
+        }
+    }
+    for (lo, hi) in [(3u64, 4u64), (5, 6)] {
+        assert_eq!(hold_v8(lo, hi), (lo, hi));
+    }
+    for pid in pids {
+        assert_eq!(wait_exited(pid), 0);
+    }
+}
+
+fn clobber_v8() {
+    unsafe {
+        asm!(
+            "movi v8.2d, #0xffffffffffffffff",
+            "fmov fpcr, {}",
+            in(reg) 0xc0_0000u64,
+            out("v8") _,
+        )
+    };
+    sigreturn();
+}
+
+fn fp_test_signal() {
+    // the registers are restored after the handler returns
+    let mut new = SignalAction::default();
+    let old = SignalAction::default();
+    new.handler = clobber_v8 as usize;
+    assert!(sigaction(SIGUSR1, &new, &old) >= 0);
+    let (lo, fpcr): (u64, u64);
+    unsafe {
+        asm!("
+            fmov    d8, {lo}
+            svc     #0
+            fmov    {lo}, d8
+            mrs     {fpcr}, fpcr",
+            lo = inout(reg) 0x1234_5678u64 => lo,
+            fpcr = out(reg) fpcr,
+            inout("x0") getpid() as usize => _,
+            in("x1") SIGUSR1 as usize,
+            in("x8") SYSCALL_KILL,
+            out("v8") _,
+        );
+    }
+    assert_eq!(lo, 0x1234_5678);
+    assert_eq!(fpcr, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 3] = [
+        (fp_test_compute, "fp_test_compute"),
+        (fp_test_switch, "fp_test_switch"),
+        (fp_test_signal, "fp_test_signal"),
+    ];
+    run_tests("fp_tests", &tests)
+}
@@ -0,0 +1,229 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+const THREAD_NUM: usize = 4;
+const PER_THREAD: usize = 200;
+const STACK_SIZE: usize = 0x2000;
+
+static mut STACK: Stack<STACK_SIZE> = Stack::new();
+
+static FUTEX: AtomicU32 = AtomicU32::new(0);
+static FUTEX2: AtomicU32 = AtomicU32::new(0);
+static WAITING: AtomicUsize = AtomicUsize::new(0);
+static CHILD_TID: AtomicI32 = AtomicI32::new(-1);
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static QUEUE: Mutex<(usize, bool)> = Mutex::new((0, false));
+static NOT_EMPTY: Condvar = Condvar::new();
+static INIT: Once = Once::new();
+static INIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn futex_test_wait() {
+    // the value is not the expected one
+    assert_eq!(futex_wait(&FUTEX, 1, None), -EAGAIN);
+    assert_eq!(futex_wake(&FUTEX, 1), 0);
+    let timeout = TimeSpec {
+        sec: 0,
+        nsec: 50_000_000,
+    };
+    let start = get_time();
+    assert_eq!(futex_wait(&FUTEX, 0, Some(&timeout)), -ETIMEDOUT);
+    assert!(get_time() - start >= 50);
+    let invalid = TimeSpec {
+        sec: 0,
+        nsec: 1_000_000_000,
+    };
+    assert_eq!(futex_wait(&FUTEX, 0, Some(&invalid)), -EINVAL);
+    // not aligned
+    let unaligned = unsafe { &*((&FUTEX as *const _ as usize + 1) as *const AtomicU32) };
+    assert_eq!(futex_wake(unaligned, 1), -EINVAL);
+}
+
+fn wait_flag(_arg: usize) -> ! {
+    WAITING.fetch_add(1, Ordering::SeqCst);
+    while FUTEX.load(Ordering::SeqCst) == 0 {
+        futex_wait(&FUTEX, 0, None);
+    }
+    exit(0)
+}
+
+fn futex_test_wake() {
+    let tids: [isize; THREAD_NUM] = spawn_threads(wait_flag);
+    while WAITING.load(Ordering::SeqCst) < THREAD_NUM {
+        sched_yield();
+    }
+    FUTEX.store(1, Ordering::SeqCst);
+    futex_wake(&FUTEX, usize::MAX);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+}
+
+fn futex_test_requeue() {
+    let tids: [isize; THREAD_NUM] = spawn_threads(wait_flag);
+    // all the waiters end up on the second futex, where nobody wakes them
+    let mut moved = 0;
+    while moved < THREAD_NUM as isize {
+        moved += futex_requeue(&FUTEX, 0, &FUTEX2, usize::MAX);
+        sched_yield();
+    }
+    assert_eq!(futex_wake(&FUTEX, usize::MAX), 0);
+    FUTEX.store(1, Ordering::SeqCst);
+    assert_eq!(futex_wake(&FUTEX2, usize::MAX), THREAD_NUM as isize);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+}
+
+extern "C" fn wake_parent(_arg: usize) -> i32 {
+    FUTEX.store(1, Ordering::SeqCst);
+    futex_wake(&FUTEX, 1);
+    0
+}
+
+fn futex_test_shared() {
+    // another process in the same address space uses the same futex
+    let pid = clone(
+        wake_parent,
+        unsafe { STACK.top() },
+        CloneFlags::CLONE_VM,
+        0,
+        null_mut(),
+        0,
+        null_mut(),
+    );
+    assert!(pid > 0);
+    while FUTEX.load(Ordering::SeqCst) == 0 {
+        futex_wait(&FUTEX, 0, None);
+    }
+    assert_eq!(wait_exited(pid), 0);
+}
+
+extern "C" fn idle(_arg: usize) -> i32 {
+    for _ in 0..10 {
+        sched_yield();
+    }
+    0
+}
+
+fn futex_test_cleartid() {
+    // the tid is cleared and the waiters are woken when the thread exits
+    let flags = CloneFlags::CLONE_VM
+        | CloneFlags::CLONE_SIGHAND
+        | CloneFlags::CLONE_THREAD
+        | CloneFlags::CLONE_PARENT_SETTID
+        | CloneFlags::CLONE_CHILD_CLEARTID;
+    let child_tid = &CHILD_TID as *const _ as *mut i32;
+    let tid = clone(
+        idle,
+        unsafe { STACK.top() },
+        flags,
+        0,
+        child_tid,
+        0,
+        child_tid,
+    );
+    assert!(tid > 0);
+    let futex = unsafe { &*(child_tid as *const AtomicU32) };
+    loop {
+        let val = futex.load(Ordering::SeqCst);
+        if val == 0 {
+            break;
+        }
+        futex_wait(futex, val, None);
+    }
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+fn add_counter(_arg: usize) -> ! {
+    for _ in 0..PER_THREAD {
+        let mut counter = COUNTER.lock();
+        let old = *counter;
+        sched_yield();
+        *counter = old + 1;
+    }
+    exit(0)
+}
+
+fn futex_test_mutex() {
+    let tids: [isize; THREAD_NUM] = spawn_threads(add_counter);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+    assert_eq!(*COUNTER.lock(), THREAD_NUM * PER_THREAD);
+    assert!(COUNTER.try_lock().is_some());
+    let _guard = COUNTER.lock();
+    assert!(COUNTER.try_lock().is_none());
+}
+
+fn consume(_arg: usize) -> ! {
+    let mut consumed = 0;
+    loop {
+        let mut queue = QUEUE.lock();
+        while queue.0 == 0 && !queue.1 {
+            queue = NOT_EMPTY.wait(queue);
+        }
+        if queue.0 == 0 {
+            exit(consumed);
+        }
+        queue.0 -= 1;
+        consumed += 1;
+    }
+}
+
+fn futex_test_condvar() {
+    let tids: [isize; THREAD_NUM] = spawn_threads(consume);
+    for _ in 0..THREAD_NUM * PER_THREAD {
+        QUEUE.lock().0 += 1;
+        NOT_EMPTY.notify_one();
+        if get_time() % 2 == 0 {
+            sched_yield();
+        }
+    }
+    QUEUE.lock().1 = true;
+    NOT_EMPTY.notify_all();
+    let total: isize = tids.iter().map(|tid| waittid(*tid as usize)).sum();
+    assert_eq!(total, (THREAD_NUM * PER_THREAD) as isize);
+}
+
+fn init_once(_arg: usize) -> ! {
+    INIT.call_once(|| {
+        for _ in 0..10 {
+            sched_yield();
+        }
+        INIT_COUNT.fetch_add(1, Ordering::SeqCst);
+    });
+    // the initialization is seen by everyone
+    exit(INIT_COUNT.load(Ordering::SeqCst) as i32)
+}
+
+fn futex_test_once() {
+    let tids: [isize; THREAD_NUM] = spawn_threads(init_once);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 1);
+    }
+    assert!(INIT.is_completed());
+    INIT.call_once(|| panic!("called twice"));
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 8] = [
+        (futex_test_wait, "futex_test_wait"),
+        (futex_test_wake, "futex_test_wake"),
+        (futex_test_requeue, "futex_test_requeue"),
+        (futex_test_shared, "futex_test_shared"),
+        (futex_test_cleartid, "futex_test_cleartid"),
+        (futex_test_mutex, "futex_test_mutex"),
+        (futex_test_condvar, "futex_test_condvar"),
+        (futex_test_once, "futex_test_once"),
+    ];
+    run_tests("futex_tests", &tests)
+}
@@ -0,0 +1,135 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::testing::*;
+use user_lib::*;
+
+fn handle_test_unified() {
+    // the sync objects are numbered after the fds
+    let mutex = mutex_blocking_create();
+    assert!(mutex > 2);
+    let sem = semaphore_create(1);
+    assert_eq!(sem, mutex + 1);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(pipe_fd[0] as isize, sem + 1);
+    // a dup refers to the same object
+    let dup_sem = dup(sem as usize);
+    assert!(dup_sem > 0);
+    assert_eq!(semaphore_down(sem as usize), 0);
+    semaphore_up(dup_sem as usize);
+    assert_eq!(semaphore_down(dup_sem as usize), 0);
+    // the lowest free number is reused
+    assert_eq!(close(mutex as usize), 0);
+    assert_eq!(condvar_create(), mutex);
+}
+
+fn handle_test_invalid() {
+    let mutex = mutex_blocking_create() as usize;
+    let mut buf = [0u8; 4];
+    // of another kind
+    assert_eq!(semaphore_up(mutex), -EINVAL);
+    assert_eq!(condvar_signal(mutex), -EINVAL);
+    assert_eq!(read(mutex, &mut buf), -EINVAL);
+    assert_eq!(mutex_lock(1), -EINVAL);
+    assert_eq!(condvar_wait(1, mutex), -EINVAL);
+    // no such handle
+    assert_eq!(mutex_lock(1000), -EBADF);
+    assert_eq!(rwlock_read(1000), -EBADF);
+    assert_eq!(barrier_wait(1000), -EBADF);
+    assert_eq!(dup(1000), -EBADF);
+    // destroyed
+    assert_eq!(close(mutex), 0);
+    assert_eq!(mutex_lock(mutex), -EBADF);
+    assert_eq!(close(mutex), -EBADF);
+}
+
+fn handle_test_rights() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let mut buf = [0u8; 4];
+    assert_eq!(write(pipe_fd[0], b"test"), -EBADF);
+    assert_eq!(read(pipe_fd[1], &mut buf), -EBADF);
+    let fd = dup(1) as usize;
+    assert_eq!(
+        handle_restrict(fd, Rights::all()),
+        (Rights::WRITE | Rights::INHERIT).bits() as isize
+    );
+    assert_eq!(
+        handle_restrict(fd, Rights::INHERIT),
+        Rights::INHERIT.bits() as isize
+    );
+    assert_eq!(write(fd, b"test"), -EBADF);
+    // the rights can not be given back
+    assert_eq!(
+        handle_restrict(fd, Rights::all()),
+        Rights::INHERIT.bits() as isize
+    );
+    let unknown = unsafe { Rights::from_bits_unchecked(1 << 31) };
+    assert_eq!(handle_restrict(fd, unknown), -EINVAL);
+    assert_eq!(handle_restrict(1000, Rights::all()), -EBADF);
+}
+
+fn handle_test_inherit() {
+    // the files are copied to the child, but not the sync objects
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let private = dup(pipe_fd[1]) as usize;
+    assert_eq!(
+        handle_restrict(private, Rights::WRITE),
+        Rights::WRITE.bits() as isize
+    );
+    let sem = semaphore_create(0) as usize;
+    let mutex = mutex_blocking_create() as usize;
+    let condvar = condvar_create() as usize;
+    let rwlock = rwlock_create() as usize;
+    let barrier = barrier_create(1) as usize;
+    let pid = fork();
+    if pid == 0 {
+        let ok = write(pipe_fd[1], b"x") == 1
+            && write(private, b"x") == -EBADF
+            && semaphore_up(sem) == -EBADF
+            && mutex_lock(mutex) == -EBADF
+            && condvar_signal(condvar) == -EBADF
+            && rwlock_read(rwlock) == -EBADF
+            && barrier_wait(barrier) == -EBADF;
+        exit(if ok { 0 } else { 1 });
+    }
+    assert_eq!(wait_exited(pid), 0);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    assert_eq!(mutex_lock(mutex), 0);
+    assert_eq!(rwlock_read(rwlock), 0);
+    assert_eq!(barrier_wait(barrier), 1);
+}
+
+fn handle_test_limit() {
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &Rlimit { cur: 8, max: 8 }), 0);
+    let mut last = 0;
+    loop {
+        let handle = mutex_create();
+        if handle < 0 {
+            assert_eq!(handle, -EMFILE);
+            break;
+        }
+        last = handle;
+    }
+    assert_eq!(last, 7);
+    assert_eq!(semaphore_create(1), -EMFILE);
+    assert_eq!(close(last as usize), 0);
+    assert_eq!(barrier_create(2), last);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 5] = [
+        (handle_test_unified, "handle_test_unified"),
+        (handle_test_invalid, "handle_test_invalid"),
+        (handle_test_rights, "handle_test_rights"),
+        (handle_test_inherit, "handle_test_inherit"),
+        (handle_test_limit, "handle_test_limit"),
+    ];
+    run_tests("handle_tests", &tests)
+}
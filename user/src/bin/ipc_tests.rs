@@ -0,0 +1,162 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::testing::*;
+use user_lib::*;
+
+const MSG_SIZE: usize = 16;
+const MAX_MSGS: usize = 4;
+const PER_CHILD: usize = 20;
+
+fn sem_test_named() {
+    let name = "/ipc_tests_sem\0";
+    let create = OpenFlags::CREATE | OpenFlags::EXCL;
+    let sem = sem_open(name, create, 0);
+    assert!(sem >= 0);
+    assert_eq!(sem_open(name, create, 0), -EEXIST);
+    assert_eq!(
+        sem_open("/ipc_tests_none\0", OpenFlags::empty(), 0),
+        -ENOENT
+    );
+    assert_eq!(sem_open("no_slash\0", OpenFlags::CREATE, 0), -EINVAL);
+    // another process opens the same semaphore by the name
+    let pid = fork();
+    if pid == 0 {
+        let sem = sem_open(name, OpenFlags::empty(), 0);
+        exit(if sem >= 0 && semaphore_up(sem as usize) == 0 {
+            0
+        } else {
+            1
+        });
+    }
+    assert_eq!(semaphore_down(sem as usize), 0);
+    assert_eq!(wait_exited(pid), 0);
+    // the semaphore lives on without the name
+    assert_eq!(sem_unlink(name), 0);
+    assert_eq!(sem_unlink(name), -ENOENT);
+    assert_eq!(sem_open(name, OpenFlags::empty(), 0), -ENOENT);
+    assert_eq!(semaphore_up(sem as usize), 0);
+    assert_eq!(semaphore_down(sem as usize), 0);
+    assert_eq!(close(sem as usize), 0);
+}
+
+fn receive(mqd: usize) -> ([u8; MSG_SIZE], usize, u32) {
+    let mut buf = [0u8; MSG_SIZE];
+    let mut prio = 0;
+    let len = mq_receive(mqd, &mut buf, &mut prio);
+    assert!(len >= 0);
+    (buf, len as usize, prio)
+}
+
+fn mq_test_priority() {
+    let name = "/ipc_tests_prio\0";
+    let mqd = mq_open(
+        name,
+        OpenFlags::CREATE | OpenFlags::RDWR,
+        MAX_MSGS,
+        MSG_SIZE,
+    );
+    assert!(mqd >= 0);
+    let mqd = mqd as usize;
+    assert_eq!(mq_send(mqd, b"low1", 1), 0);
+    assert_eq!(mq_send(mqd, b"high", 5), 0);
+    assert_eq!(mq_send(mqd, b"low2", 1), 0);
+    assert_eq!(mq_send(mqd, b"", 0), 0);
+    // the highest priority first, then in the order sent
+    for (msg, prio) in [(&b"high"[..], 5), (b"low1", 1), (b"low2", 1), (b"", 0)] {
+        let (buf, len, got_prio) = receive(mqd);
+        assert_eq!(&buf[..len], msg);
+        assert_eq!(got_prio, prio);
+    }
+    let mut small = [0u8; MSG_SIZE - 1];
+    let mut prio = 0;
+    assert_eq!(mq_receive(mqd, &mut small, &mut prio), -EMSGSIZE);
+    assert_eq!(mq_send(mqd, &[0u8; MSG_SIZE + 1], 0), -EMSGSIZE);
+    assert_eq!(mq_send(mqd, b"test", 32768), -EINVAL);
+    // the message runs past the end of the user address space
+    let bad = unsafe { core::slice::from_raw_parts(0xffff_ffff_fffc as *const u8, 8) };
+    assert_eq!(mq_send(mqd, bad, 0), -EFAULT);
+    let bad = unsafe { core::slice::from_raw_parts_mut(0xffff_ffff_fffc as *mut u8, MSG_SIZE) };
+    assert_eq!(mq_receive(mqd, bad, &mut prio), -EFAULT);
+    assert_eq!(mq_unlink(name), 0);
+}
+
+fn mq_test_nonblock() {
+    let name = "/ipc_tests_nonblock\0";
+    let flags = OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::NONBLOCK;
+    let mqd = mq_open(name, flags, MAX_MSGS, MSG_SIZE) as usize;
+    let mut buf = [0u8; MSG_SIZE];
+    let mut prio = 0;
+    assert_eq!(mq_receive(mqd, &mut buf, &mut prio), -EAGAIN);
+    for _ in 0..MAX_MSGS {
+        assert_eq!(mq_send(mqd, b"test", 0), 0);
+    }
+    assert_eq!(mq_send(mqd, b"test", 0), -EAGAIN);
+    assert_eq!(mq_unlink(name), 0);
+}
+
+fn mq_test_access() {
+    let name = "/ipc_tests_access\0";
+    let create = OpenFlags::CREATE | OpenFlags::EXCL;
+    let sender = mq_open(name, create | OpenFlags::WRONLY, MAX_MSGS, MSG_SIZE) as usize;
+    let receiver = mq_open(name, OpenFlags::RDONLY, 0, 0) as usize;
+    let mut buf = [0u8; MSG_SIZE];
+    let mut prio = 0;
+    assert_eq!(mq_receive(sender, &mut buf, &mut prio), -EBADF);
+    assert_eq!(mq_send(receiver, b"test", 0), -EBADF);
+    assert_eq!(mq_send(sender, b"test", 0), 0);
+    assert_eq!(mq_receive(receiver, &mut buf, &mut prio), 4);
+    // too large to create
+    let huge = "/ipc_tests_huge\0";
+    assert_eq!(mq_open(huge, OpenFlags::CREATE, 1000, MSG_SIZE), -EINVAL);
+    assert_eq!(mq_unlink(huge), -ENOENT);
+    assert_eq!(mq_unlink(name), 0);
+    // the queue lives on without the name
+    assert_eq!(mq_open(name, OpenFlags::RDONLY, 0, 0), -ENOENT);
+    assert_eq!(mq_send(sender, b"test", 0), 0);
+    assert_eq!(mq_receive(receiver, &mut buf, &mut prio), 4);
+}
+
+fn mq_test_processes() {
+    // the senders block while the small queue is full
+    let name = "/ipc_tests_procs\0";
+    let mqd = mq_open(name, OpenFlags::CREATE | OpenFlags::RDWR, 2, MSG_SIZE) as usize;
+    let mut pids = [0; 2];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            let mqd = mq_open(name, OpenFlags::WRONLY, 0, 0) as usize;
+            for j in 0..PER_CHILD {
+                assert_eq!(mq_send(mqd, &[i as u8, j as u8], 0), 0);
+            }
+            exit(0);
+        }
+    }
+    // the messages of each sender come in order
+    let mut next = [0u8; 2];
+    for _ in 0..2 * PER_CHILD {
+        let (buf, len, _) = receive(mqd);
+        assert_eq!(len, 2);
+        let sender = buf[0] as usize;
+        assert_eq!(buf[1], next[sender]);
+        next[sender] += 1;
+    }
+    for pid in pids {
+        assert_eq!(wait_exited(pid), 0);
+    }
+    assert_eq!(mq_unlink(name), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 5] = [
+        (sem_test_named, "sem_test_named"),
+        (mq_test_priority, "mq_test_priority"),
+        (mq_test_nonblock, "mq_test_nonblock"),
+        (mq_test_access, "mq_test_access"),
+        (mq_test_processes, "mq_test_processes"),
+    ];
+    run_tests("ipc_tests", &tests)
+}
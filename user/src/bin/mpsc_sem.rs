@@ -12,9 +12,9 @@ use user_lib::exit;
 use user_lib::{semaphore_create, semaphore_down, semaphore_up};
 use user_lib::{thread_create, waittid};
 
-const SEM_MUTEX: usize = 0;
-const SEM_EMPTY: usize = 1;
-const SEM_EXISTED: usize = 2;
+static mut SEM_MUTEX: usize = 0;
+static mut SEM_EMPTY: usize = 0;
+static mut SEM_EXISTED: usize = 0;
 const BUFFER_SIZE: usize = 8;
 static mut BUFFER: [usize; BUFFER_SIZE] = [0; BUFFER_SIZE];
 static mut FRONT: usize = 0;
@@ -51,9 +51,11 @@ unsafe fn consumer() -> ! {
 #[no_mangle]
 pub fn main() -> i32 {
     // create semaphores
-    assert_eq!(semaphore_create(1) as usize, SEM_MUTEX);
-    assert_eq!(semaphore_create(BUFFER_SIZE) as usize, SEM_EMPTY);
-    assert_eq!(semaphore_create(0) as usize, SEM_EXISTED);
+    unsafe {
+        SEM_MUTEX = semaphore_create(1) as usize;
+        SEM_EMPTY = semaphore_create(BUFFER_SIZE) as usize;
+        SEM_EXISTED = semaphore_create(0) as usize;
+    }
     // create threads
     let ids: Vec<_> = (0..PRODUCER_COUNT).collect();
     let mut threads = Vec::new();
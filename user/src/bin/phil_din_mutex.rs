@@ -30,6 +30,7 @@ const ARR: [[usize; ROUND * 2]; N] = [
 ];
 static mut THINK: [[usize; ROUND * 2]; N] = [[0; ROUND * 2]; N];
 static mut EAT: [[usize; ROUND * 2]; N] = [[0; ROUND * 2]; N];
+static mut FORKS: [usize; N] = [0; N];
 
 fn philosopher_dining_problem(id: *const usize) {
     let id = unsafe { *id };
@@ -47,6 +48,7 @@ fn philosopher_dining_problem(id: *const usize) {
             THINK[id][2 * round + 1] = get_time_u();
         }
         // wait for forks
+        let (min, max) = unsafe { (FORKS[min], FORKS[max]) };
         mutex_lock(min);
         mutex_lock(max);
         // eating
@@ -68,8 +70,12 @@ pub fn main() -> i32 {
     let mut v = Vec::new();
     let ids: Vec<_> = (0..N).collect();
     let start = get_time_u();
+    for fork in unsafe { FORKS.iter_mut() } {
+        let id = mutex_blocking_create();
+        assert!(id >= 0);
+        *fork = id as usize;
+    }
     for i in 0..N {
-        assert_eq!(mutex_blocking_create(), i as isize);
         v.push(thread_create(
             philosopher_dining_problem as usize,
             &ids.as_slice()[i] as *const _ as usize,
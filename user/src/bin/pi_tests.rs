@@ -0,0 +1,228 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+// a task can only lower its priority, so the test task runs at the highest
+// one and the others lower themselves
+const HIGH: usize = DEFAULT_PRIORITY;
+const MEDIUM: usize = DEFAULT_PRIORITY - 4;
+const LOW: usize = DEFAULT_PRIORITY - 8;
+const TIMEOUT_MS: isize = 1000;
+
+static MUTEX1: AtomicUsize = AtomicUsize::new(0);
+static MUTEX2: AtomicUsize = AtomicUsize::new(0);
+static FLAG: AtomicBool = AtomicBool::new(false);
+static LOW_DONE: AtomicBool = AtomicBool::new(false);
+
+/// Yields until the priority of the current task is `prio`, returns false
+/// if it does not happen in time.
+fn wait_priority(prio: usize) -> bool {
+    let start = get_time();
+    while get_priority() != prio as isize {
+        if get_time() - start > TIMEOUT_MS {
+            return false;
+        }
+        sched_yield();
+    }
+    true
+}
+
+fn priority_test_basic() {
+    assert_eq!(get_priority(), DEFAULT_PRIORITY as isize);
+    assert_eq!(set_priority(MAX_PRIORITY + 1), -EINVAL);
+    assert_eq!(set_priority(MEDIUM), 0);
+    assert_eq!(get_priority(), MEDIUM as isize);
+    // it cannot be raised again, also not by creating a task
+    assert_eq!(set_priority(HIGH), -EPERM);
+    assert_eq!(set_priority(MEDIUM), 0);
+    let tid = thread_create(exit_priority as usize, 0);
+    assert_eq!(waittid(tid as usize), MEDIUM as isize);
+}
+
+fn exit_priority(_arg: usize) -> ! {
+    exit(get_priority() as i32)
+}
+
+fn set_flag(_arg: usize) -> ! {
+    set_priority(LOW);
+    sched_yield();
+    FLAG.store(true, Ordering::SeqCst);
+    exit(0)
+}
+
+fn priority_test_order() {
+    // a lower-priority task does not run while a higher one is ready
+    let tid = thread_create(set_flag as usize, 0);
+    for _ in 0..100 {
+        sched_yield();
+    }
+    assert!(!FLAG.load(Ordering::SeqCst));
+    assert_eq!(set_priority(LOW - 1), 0);
+    sched_yield();
+    assert!(FLAG.load(Ordering::SeqCst));
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+fn priority_test_starvation() {
+    // a task yielding until a lower one makes progress does not starve it
+    let start = get_time();
+    let tid = thread_create(set_flag as usize, 0);
+    while !FLAG.load(Ordering::SeqCst) {
+        assert!(get_time() - start < TIMEOUT_MS);
+        sched_yield();
+    }
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+fn hold_until_boosted(_arg: usize) -> ! {
+    set_priority(LOW);
+    let mutex = MUTEX1.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    FLAG.store(true, Ordering::SeqCst);
+    let boosted = wait_priority(HIGH);
+    LOW_DONE.store(true, Ordering::SeqCst);
+    mutex_unlock(mutex);
+    // the boost ends with the unlock
+    let restored = get_priority() == LOW as isize;
+    exit(if boosted && restored { 0 } else { 1 })
+}
+
+fn busy_until_low_done(_arg: usize) -> ! {
+    set_priority(MEDIUM);
+    let start = get_time();
+    while !LOW_DONE.load(Ordering::SeqCst) {
+        if get_time() - start > TIMEOUT_MS {
+            exit(1);
+        }
+    }
+    exit(0)
+}
+
+fn pi_test_inversion() {
+    let mutex = mutex_pi_create();
+    assert!(mutex >= 0);
+    MUTEX1.store(mutex as usize, Ordering::SeqCst);
+    let low = thread_create(hold_until_boosted as usize, 0);
+    while !FLAG.load(Ordering::SeqCst) {
+        sleep(10);
+    }
+    // the medium task keeps the CPU from the low one, unless the low one
+    // inherits the priority of this task
+    let medium = thread_create(busy_until_low_done as usize, 0);
+    sleep(20);
+    mutex_lock(mutex as usize);
+    assert!(LOW_DONE.load(Ordering::SeqCst));
+    mutex_unlock(mutex as usize);
+    assert_eq!(waittid(low as usize), 0);
+    assert_eq!(waittid(medium as usize), 0);
+}
+
+fn hold_second(_arg: usize) -> ! {
+    set_priority(LOW);
+    let mutex2 = MUTEX2.load(Ordering::SeqCst);
+    mutex_lock(mutex2);
+    FLAG.store(true, Ordering::SeqCst);
+    let boosted = wait_priority(HIGH);
+    mutex_unlock(mutex2);
+    exit(if boosted { 0 } else { 1 })
+}
+
+fn hold_first_wait_second(_arg: usize) -> ! {
+    set_priority(LOW);
+    let mutex1 = MUTEX1.load(Ordering::SeqCst);
+    let mutex2 = MUTEX2.load(Ordering::SeqCst);
+    mutex_lock(mutex1);
+    mutex_lock(mutex2);
+    // boosted as the holder of the first mutex
+    let boosted = get_priority() == HIGH as isize;
+    mutex_unlock(mutex2);
+    mutex_unlock(mutex1);
+    exit(if boosted { 0 } else { 1 })
+}
+
+fn pi_test_chain() {
+    // this task -> mutex1 -> middle task -> mutex2 -> last task
+    let mutex1 = mutex_pi_create();
+    let mutex2 = mutex_pi_create();
+    MUTEX1.store(mutex1 as usize, Ordering::SeqCst);
+    MUTEX2.store(mutex2 as usize, Ordering::SeqCst);
+    let last = thread_create(hold_second as usize, 0);
+    while !FLAG.load(Ordering::SeqCst) {
+        sleep(10);
+    }
+    let middle = thread_create(hold_first_wait_second as usize, 0);
+    sleep(20);
+    mutex_lock(mutex1 as usize);
+    mutex_unlock(mutex1 as usize);
+    assert_eq!(waittid(last as usize), 0);
+    assert_eq!(waittid(middle as usize), 0);
+}
+
+fn hold_not_boosted(_arg: usize) -> ! {
+    set_priority(LOW);
+    let mutex = MUTEX1.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    FLAG.store(true, Ordering::SeqCst);
+    while !LOW_DONE.load(Ordering::SeqCst) {
+        sched_yield();
+    }
+    for _ in 0..10 {
+        sched_yield();
+    }
+    let prio = get_priority();
+    mutex_unlock(mutex);
+    exit(if prio == LOW as isize { 0 } else { 1 })
+}
+
+fn pi_test_no_inherit() {
+    // the holder of a mutex without PI keeps its priority
+    let mutex = mutex_blocking_create();
+    MUTEX1.store(mutex as usize, Ordering::SeqCst);
+    let low = thread_create(hold_not_boosted as usize, 0);
+    while !FLAG.load(Ordering::SeqCst) {
+        sleep(10);
+    }
+    LOW_DONE.store(true, Ordering::SeqCst);
+    mutex_lock(mutex as usize);
+    mutex_unlock(mutex as usize);
+    assert_eq!(waittid(low as usize), 0);
+}
+
+fn unlock_other(_arg: usize) -> ! {
+    exit(-mutex_unlock(MUTEX1.load(Ordering::SeqCst)) as i32)
+}
+
+fn mutex_test_unlock_errors() {
+    for mutex in [mutex_create(), mutex_blocking_create(), mutex_pi_create()] {
+        assert!(mutex >= 0);
+        assert_eq!(mutex_unlock(mutex as usize), -EINVAL);
+    }
+    // only the owner can unlock a mutex
+    for mutex in [mutex_create(), mutex_blocking_create(), mutex_pi_create()] {
+        MUTEX1.store(mutex as usize, Ordering::SeqCst);
+        mutex_lock(mutex as usize);
+        let tid = thread_create(unlock_other as usize, 0);
+        assert_eq!(waittid(tid as usize), EPERM);
+        assert_eq!(get_priority(), HIGH as isize);
+        assert_eq!(mutex_unlock(mutex as usize), 0);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 7] = [
+        (priority_test_basic, "priority_test_basic"),
+        (priority_test_order, "priority_test_order"),
+        (priority_test_starvation, "priority_test_starvation"),
+        (pi_test_inversion, "pi_test_inversion"),
+        (pi_test_chain, "pi_test_chain"),
+        (pi_test_no_inherit, "pi_test_no_inherit"),
+        (mutex_test_unlock_errors, "mutex_test_unlock_errors"),
+    ];
+    run_tests("pi_tests", &tests)
+}
@@ -10,20 +10,21 @@ use user_lib::{exit, get_time, thread_create, waittid};
 use user_lib::{mutex_create, mutex_lock, mutex_unlock};
 
 static mut A: usize = 0;
+static mut MUTEX_ID: usize = 0;
 const PER_THREAD: usize = 1000;
 const THREAD_COUNT: usize = 16;
 
 unsafe fn f() -> ! {
     let mut t = 2usize;
     for _ in 0..PER_THREAD {
-        mutex_lock(0);
+        mutex_lock(MUTEX_ID);
         let a = &mut A as *mut usize;
         let cur = a.read_volatile();
         for _ in 0..500 {
             t = t * t % 10007;
         }
         a.write_volatile(cur + 1);
-        mutex_unlock(0);
+        mutex_unlock(MUTEX_ID);
     }
     exit(t as i32)
 }
@@ -31,7 +32,11 @@ unsafe fn f() -> ! {
 #[no_mangle]
 pub fn main() -> i32 {
     let start = get_time();
-    assert_eq!(mutex_create(), 0);
+    let mutex_id = mutex_create();
+    assert!(mutex_id >= 0);
+    unsafe {
+        MUTEX_ID = mutex_id as usize;
+    }
     let mut v = Vec::new();
     for _ in 0..THREAD_COUNT {
         v.push(thread_create(f as usize, 0) as usize);
@@ -0,0 +1,166 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::testing::*;
+use user_lib::*;
+
+const PAGE_SIZE: usize = 4096;
+const MMAP_ADDR: usize = 0x1000_0000;
+
+fn limit(resource: usize, cur: usize, max: usize) {
+    assert_eq!(setrlimit(resource, &Rlimit { cur, max }), 0);
+}
+
+fn wait_signaled(pid: isize) -> i32 {
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFSIGNALED(exit_code));
+    WTERMSIG(exit_code)
+}
+
+fn rlimit_test_get_set() {
+    let mut rlim = Rlimit { cur: 0, max: 0 };
+    assert_eq!(getrlimit(RLIMIT_NOFILE, &mut rlim), 0);
+    assert!(rlim.cur <= rlim.max);
+    // the soft limit can not exceed the hard one
+    assert_eq!(
+        setrlimit(RLIMIT_NOFILE, &Rlimit { cur: 16, max: 8 }),
+        -EINVAL
+    );
+    limit(RLIMIT_NOFILE, 8, 16);
+    // the hard limit can only be lowered
+    assert_eq!(
+        setrlimit(RLIMIT_NOFILE, &Rlimit { cur: 8, max: 32 }),
+        -EPERM
+    );
+    assert_eq!(getrlimit(RLIMIT_NOFILE, &mut rlim), 0);
+    assert_eq!((rlim.cur, rlim.max), (8, 16));
+}
+
+fn rlimit_test_nofile() {
+    limit(RLIMIT_NOFILE, 8, 8);
+    let mut last_fd = 0;
+    loop {
+        let fd = dup(1);
+        if fd < 0 {
+            assert_eq!(fd, -EMFILE);
+            break;
+        }
+        last_fd = fd;
+    }
+    assert_eq!(last_fd, 7);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), -EMFILE);
+}
+
+fn rlimit_test_nproc() {
+    limit(RLIMIT_NPROC, 1, 1);
+    assert_eq!(fork(), -EAGAIN);
+    // threads count too
+    assert_eq!(thread_create(spin as usize, 0), -EAGAIN);
+}
+
+fn rlimit_test_nproc_tree() {
+    limit(RLIMIT_NPROC, 3, 3);
+    let mut pids = [0; 2];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            sleep(100);
+            exit(0);
+        }
+        assert!(*pid > 0);
+    }
+    // the limit counts the children of the process
+    assert_eq!(fork(), -EAGAIN);
+    for pid in pids {
+        assert_eq!(wait_exited(pid), 0);
+    }
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn rlimit_test_data() {
+    limit(RLIMIT_DATA, 2 * PAGE_SIZE, 2 * PAGE_SIZE);
+    let heap = sbrk(PAGE_SIZE as isize);
+    assert!(heap > 0);
+    let heap = heap as usize as *mut u8;
+    unsafe {
+        heap.write_volatile(1);
+        assert_eq!(heap.read_volatile(), 1);
+    }
+    assert_eq!(sbrk(2 * PAGE_SIZE as isize), -ENOMEM);
+    assert_eq!(
+        sbrk(-(PAGE_SIZE as isize)),
+        heap as isize + PAGE_SIZE as isize
+    );
+    // mmap is not part of the data segment
+    assert_eq!(
+        mmap(MMAP_ADDR, 4 * PAGE_SIZE, PROT_READ | PROT_WRITE),
+        MMAP_ADDR as isize
+    );
+    let mem = MMAP_ADDR as *mut u8;
+    unsafe {
+        mem.add(3 * PAGE_SIZE).write_volatile(1);
+        assert_eq!(mem.add(3 * PAGE_SIZE).read_volatile(), 1);
+    }
+    assert_eq!(munmap(MMAP_ADDR, 4 * PAGE_SIZE), 0);
+    assert_eq!(munmap(MMAP_ADDR, 4 * PAGE_SIZE), -EINVAL);
+}
+
+fn rlimit_test_as() {
+    limit(RLIMIT_AS, 4096, 4096);
+    assert_eq!(thread_create(rlimit_test_as as usize, 0), -ENOMEM);
+    assert_eq!(exec("hello_world\0", &[core::ptr::null::<u8>()]), -ENOMEM);
+    assert_eq!(mmap(MMAP_ADDR, PAGE_SIZE, PROT_READ), -ENOMEM);
+    assert_eq!(sbrk(PAGE_SIZE as isize), -ENOMEM);
+}
+
+fn rlimit_test_fsize() {
+    let pid = fork();
+    if pid == 0 {
+        limit(RLIMIT_FSIZE, 100, RLIM_INFINITY);
+        let fd = open("rlimit_fsize\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd >= 0);
+        let fd = fd as usize;
+        let buf = [b'x'; 64];
+        assert_eq!(write(fd, &buf), 64);
+        // only the part below the limit is written
+        assert_eq!(write(fd, &buf), 36);
+        // killed by SIGXFSZ
+        write(fd, &buf);
+        exit(-1);
+    }
+    assert_eq!(wait_signaled(pid), SIGXFSZ);
+}
+
+fn rlimit_test_cpu() {
+    let pid = fork();
+    if pid == 0 {
+        limit(RLIMIT_CPU, 1, RLIM_INFINITY);
+        // killed by SIGXCPU after one second
+        #[allow(clippy::empty_loop)]
+        loop {}
+    }
+    assert_eq!(wait_signaled(pid), SIGXCPU);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 8] = [
+        (rlimit_test_get_set, "rlimit_test_get_set"),
+        (rlimit_test_nofile, "rlimit_test_nofile"),
+        (rlimit_test_nproc, "rlimit_test_nproc"),
+        (rlimit_test_nproc_tree, "rlimit_test_nproc_tree"),
+        (rlimit_test_data, "rlimit_test_data"),
+        (rlimit_test_as, "rlimit_test_as"),
+        (rlimit_test_fsize, "rlimit_test_fsize"),
+        (rlimit_test_cpu, "rlimit_test_cpu"),
+    ];
+    run_tests("rlimit_tests", &tests)
+}
@@ -0,0 +1,208 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+const THREAD_NUM: usize = 4;
+const ROUNDS: usize = 50;
+const TIMEOUT_MS: isize = 1000;
+
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static CONDVAR: AtomicUsize = AtomicUsize::new(0);
+static RWLOCK: AtomicUsize = AtomicUsize::new(0);
+static BARRIER: AtomicUsize = AtomicUsize::new(0);
+static WAITING: AtomicUsize = AtomicUsize::new(0);
+static READING: AtomicUsize = AtomicUsize::new(0);
+static WRITING: AtomicBool = AtomicBool::new(false);
+static FLAG: AtomicBool = AtomicBool::new(false);
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static PHASES: [AtomicUsize; THREAD_NUM] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+fn wait_flag(_arg: usize) -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    let condvar = CONDVAR.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    WAITING.fetch_add(1, Ordering::SeqCst);
+    while !FLAG.load(Ordering::SeqCst) {
+        condvar_wait(condvar, mutex);
+    }
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+fn condvar_test_broadcast() {
+    let mutex = mutex_blocking_create() as usize;
+    let condvar = condvar_create() as usize;
+    MUTEX.store(mutex, Ordering::SeqCst);
+    CONDVAR.store(condvar, Ordering::SeqCst);
+    let tids: [isize; THREAD_NUM] = spawn_threads(wait_flag);
+    // all the threads are waiting once they gave back the mutex
+    loop {
+        mutex_lock(mutex);
+        if WAITING.load(Ordering::SeqCst) == THREAD_NUM {
+            break;
+        }
+        mutex_unlock(mutex);
+        sched_yield();
+    }
+    FLAG.store(true, Ordering::SeqCst);
+    condvar_broadcast(condvar);
+    mutex_unlock(mutex);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+}
+
+fn read_together(_arg: usize) -> ! {
+    let rwlock = RWLOCK.load(Ordering::SeqCst);
+    rwlock_read(rwlock);
+    READING.fetch_add(1, Ordering::SeqCst);
+    // every reader gets in while the others hold the lock
+    let start = get_time();
+    while READING.load(Ordering::SeqCst) < THREAD_NUM {
+        if get_time() - start > TIMEOUT_MS {
+            exit(1);
+        }
+        sched_yield();
+    }
+    assert_eq!(rwlock_unlock(rwlock), 0);
+    exit(0)
+}
+
+fn rwlock_test_readers() {
+    let rwlock = rwlock_create() as usize;
+    RWLOCK.store(rwlock, Ordering::SeqCst);
+    assert_eq!(rwlock_unlock(rwlock), -EINVAL);
+    let tids: [isize; THREAD_NUM] = spawn_threads(read_together);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+}
+
+fn read_or_write(id: usize) -> ! {
+    let rwlock = RWLOCK.load(Ordering::SeqCst);
+    for _ in 0..ROUNDS {
+        if id % 2 == 0 {
+            rwlock_write(rwlock);
+            assert!(!WRITING.swap(true, Ordering::SeqCst));
+            assert_eq!(READING.load(Ordering::SeqCst), 0);
+            let old = COUNTER.load(Ordering::SeqCst);
+            sched_yield();
+            COUNTER.store(old + 1, Ordering::SeqCst);
+            WRITING.store(false, Ordering::SeqCst);
+        } else {
+            rwlock_read(rwlock);
+            READING.fetch_add(1, Ordering::SeqCst);
+            assert!(!WRITING.load(Ordering::SeqCst));
+            sched_yield();
+            READING.fetch_sub(1, Ordering::SeqCst);
+        }
+        assert_eq!(rwlock_unlock(rwlock), 0);
+    }
+    exit(0)
+}
+
+fn rwlock_test_writers() {
+    let rwlock = rwlock_create() as usize;
+    RWLOCK.store(rwlock, Ordering::SeqCst);
+    let tids: [isize; THREAD_NUM] = spawn_threads(read_or_write);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), THREAD_NUM / 2 * ROUNDS);
+}
+
+fn write_once(_arg: usize) -> ! {
+    let rwlock = RWLOCK.load(Ordering::SeqCst);
+    rwlock_write(rwlock);
+    FLAG.store(true, Ordering::SeqCst);
+    rwlock_unlock(rwlock);
+    exit(0)
+}
+
+fn read_after_write(_arg: usize) -> ! {
+    let rwlock = RWLOCK.load(Ordering::SeqCst);
+    rwlock_read(rwlock);
+    READING.fetch_add(1, Ordering::SeqCst);
+    let wrote = FLAG.load(Ordering::SeqCst);
+    rwlock_unlock(rwlock);
+    exit(if wrote { 0 } else { 1 })
+}
+
+fn rwlock_test_writer_first() {
+    // a new reader does not get in before a waiting writer
+    let rwlock = rwlock_create() as usize;
+    RWLOCK.store(rwlock, Ordering::SeqCst);
+    rwlock_read(rwlock);
+    let writer = thread_create(write_once as usize, 0);
+    sleep(20);
+    let reader = thread_create(read_after_write as usize, 0);
+    sleep(20);
+    assert_eq!(READING.load(Ordering::SeqCst), 0);
+    assert_eq!(rwlock_unlock(rwlock), 0);
+    assert_eq!(waittid(writer as usize), 0);
+    assert_eq!(waittid(reader as usize), 0);
+}
+
+fn unlock_other(_arg: usize) -> ! {
+    exit(-rwlock_unlock(RWLOCK.load(Ordering::SeqCst)) as i32)
+}
+
+fn rwlock_test_unlock_owner() {
+    // only the writer can unlock the lock held for writing
+    let rwlock = rwlock_create() as usize;
+    RWLOCK.store(rwlock, Ordering::SeqCst);
+    rwlock_write(rwlock);
+    let tid = thread_create(unlock_other as usize, 0);
+    assert_eq!(waittid(tid as usize), EPERM);
+    assert_eq!(rwlock_unlock(rwlock), 0);
+    assert_eq!(rwlock_unlock(rwlock), -EINVAL);
+}
+
+fn step(id: usize) -> ! {
+    let barrier = BARRIER.load(Ordering::SeqCst);
+    let mut last = 0;
+    for round in 1..=ROUNDS {
+        PHASES[id].store(round, Ordering::SeqCst);
+        last += barrier_wait(barrier);
+        // nobody passes the barrier before everyone reached it
+        for phase in PHASES.iter() {
+            assert!(phase.load(Ordering::SeqCst) >= round);
+        }
+        // nobody reaches it again before everyone passed it
+        barrier_wait(barrier);
+    }
+    exit(last as i32)
+}
+
+fn barrier_test() {
+    assert_eq!(barrier_create(0), -EINVAL);
+    let barrier = barrier_create(THREAD_NUM) as usize;
+    BARRIER.store(barrier, Ordering::SeqCst);
+    let tids: [isize; THREAD_NUM] = spawn_threads(step);
+    // one thread is the last to arrive each round
+    let last: isize = tids.iter().map(|tid| waittid(*tid as usize)).sum();
+    assert_eq!(last, ROUNDS as isize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 6] = [
+        (condvar_test_broadcast, "condvar_test_broadcast"),
+        (rwlock_test_readers, "rwlock_test_readers"),
+        (rwlock_test_writers, "rwlock_test_writers"),
+        (rwlock_test_writer_first, "rwlock_test_writer_first"),
+        (rwlock_test_unlock_owner, "rwlock_test_unlock_owner"),
+        (barrier_test, "barrier_test"),
+    ];
+    run_tests("rwlock_barrier_tests", &tests)
+}
@@ -85,8 +85,16 @@ fn user_sig_test_restore() {
 }
 
 fn kernel_sig_test_ignore() {
-    sigprocmask(SignalFlags::SIGSTOP.bits() as u32);
-    if kill(getpid() as usize, SignalFlags::SIGSTOP.bits()) < 0 {
+    // `SIGSTOP` cannot be blocked, unlike `SIGUSR1`
+    let blocked = SignalFlags::SIGSTOP | SignalFlags::SIGUSR1;
+    sigprocmask(SIG_SETMASK, Some(blocked), None);
+    let mut mask = SignalFlags::empty();
+    sigprocmask(SIG_SETMASK, None, Some(&mut mask));
+    if mask != SignalFlags::SIGUSR1 {
+        println!("SIGSTOP blocked\n");
+        exit(-1);
+    }
+    if kill(getpid() as usize, SIGUSR1) < 0 {
         println!("kill faild\n");
         exit(-1);
     }
@@ -95,12 +103,12 @@ fn kernel_sig_test_ignore() {
 fn kernel_sig_test_stop_cont() {
     let pid = fork();
     if pid == 0 {
-        kill(getpid() as usize, SignalFlags::SIGSTOP.bits());
+        kill(getpid() as usize, SIGSTOP);
         sleep(1000);
         exit(-1);
     } else {
         sleep(5000);
-        kill(pid as usize, SignalFlags::SIGCONT.bits());
+        kill(pid as usize, SIGCONT);
         let mut exit_code = 0;
         wait(&mut exit_code);
     }
@@ -147,12 +155,12 @@ fn final_sig_test() {
         }
     } else {
         sleep(1000);
-        if kill(pid as usize, 1 << 14) < 0 {
+        if kill(pid as usize, SIGALRM) < 0 {
             println!("Kill failed!");
             exit(-1);
         }
         sleep(1000);
-        kill(pid as usize, SignalFlags::SIGKILL.bits());
+        kill(pid as usize, SIGKILL);
     }
 }
 
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr::null;
+use user_lib::testing::*;
+use user_lib::*;
+
+/// Forks a child which sends `sig` to itself, returns its wait status.
+fn status_after(sig: i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        kill(getpid() as usize, sig);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn signal_test_default() {
+    let status = status_after(SIGUSR1);
+    assert!(WIFSIGNALED(status) && WTERMSIG(status) == SIGUSR1 && !WCOREDUMP(status));
+    let status = status_after(SIGQUIT);
+    assert!(WIFSIGNALED(status) && WTERMSIG(status) == SIGQUIT && WCOREDUMP(status));
+    for sig in [SIGCHLD, SIGURG, SIGWINCH, SIGCONT] {
+        let status = status_after(sig);
+        assert!(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    }
+}
+
+fn signal_test_ignore() {
+    for sig in [SIGUSR1, SIGINT, SIGTSTP] {
+        set_handler(sig, SIG_IGN, SignalFlags::empty(), SigActionFlags::empty());
+        assert_eq!(kill(getpid() as usize, sig), 0);
+    }
+    // a pending signal is discarded once ignored
+    sigprocmask(SIG_SETMASK, Some(SignalFlags::SIGUSR2), None);
+    kill(getpid() as usize, SIGUSR2);
+    set_handler(
+        SIGUSR2,
+        SIG_IGN,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    set_handler(
+        SIGUSR2,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None);
+    check_log(&[]);
+    let action = SignalAction {
+        handler: SIG_IGN,
+        ..SignalAction::default()
+    };
+    let mut old = SignalAction::default();
+    assert_eq!(sigaction(SIGKILL, &action, &old), -EINVAL);
+    assert_eq!(sigaction(SIGSTOP, &action, &old), -EINVAL);
+    assert_eq!(sigaction(0, &action, &old), -EINVAL);
+    assert_eq!(sigaction(64, &action, &old), -EINVAL);
+    // only get the action, which can be done for SIGKILL too
+    assert_eq!(sigaction(SIGKILL, null(), &mut old as *mut _), 0);
+    assert_eq!(old.handler, SIG_DFL);
+    assert_eq!(sigaction(SIGUSR2, null(), &mut old as *mut _), 0);
+    assert_eq!(old.handler, log_signal as usize);
+    // only set it
+    assert_eq!(sigaction(SIGUSR2, &action, null()), 0);
+    assert_eq!(sigaction(SIGUSR2, null(), &mut old as *mut _), 0);
+    assert_eq!(old.handler, SIG_IGN);
+    let bad = usize::MAX as *const SignalAction;
+    assert_eq!(sigaction(SIGUSR2, &action, bad), -EFAULT);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 2] = [
+        (signal_test_default, "signal_test_default"),
+        (signal_test_ignore, "signal_test_ignore"),
+    ];
+    run_tests("signal_default_tests", &tests)
+}
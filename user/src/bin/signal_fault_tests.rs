@@ -0,0 +1,151 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+// an address where nothing is mapped
+const BAD_ADDR: usize = 0x1000;
+
+static FAULT_CODE: AtomicI32 = AtomicI32::new(0);
+static FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn skip_fault(sig: i32, info: &SigInfo, uc: &mut UContext) {
+    log(sig);
+    assert_eq!(info.signo, sig);
+    FAULT_CODE.store(info.code, Ordering::SeqCst);
+    FAULT_ADDR.store(info.addr, Ordering::SeqCst);
+    // go on after the faulting instruction
+    uc.mcontext.pc += 4;
+}
+
+fn check_fault(code: i32, addr: usize) {
+    assert_eq!(FAULT_CODE.load(Ordering::SeqCst), code);
+    assert_eq!(FAULT_ADDR.load(Ordering::SeqCst), addr);
+}
+
+fn store_to_bad_address() {
+    unsafe { asm!("strb wzr, [{}]", in(reg) BAD_ADDR) };
+}
+
+fn signal_test_fault() {
+    // the handlers are given the fault address, and can recover
+    for sig in [SIGSEGV, SIGBUS, SIGILL] {
+        set_handler(
+            sig,
+            skip_fault as usize,
+            SignalFlags::empty(),
+            SigActionFlags::SA_SIGINFO,
+        );
+    }
+    store_to_bad_address();
+    check_log(&[SIGSEGV]);
+    check_fault(SEGV_MAPERR, BAD_ADDR);
+
+    // exclusive loads must be aligned
+    let word = [0u64; 2];
+    let misaligned = word.as_ptr() as usize + 1;
+    unsafe { asm!("ldxr {}, [{}]", out(reg) _, in(reg) misaligned) };
+    check_log(&[SIGSEGV, SIGBUS]);
+    check_fault(BUS_ADRALN, misaligned);
+
+    // `hvc` is undefined in user mode
+    let pc: usize;
+    unsafe { asm!("adr {}, 1f", "1: hvc #0", out(reg) pc) };
+    check_log(&[SIGSEGV, SIGBUS, SIGILL]);
+    check_fault(ILL_ILLOPC, pc);
+
+    // a fault which is blocked or ignored still kills the process
+    for (handler, mask) in [
+        (skip_fault as usize, SignalFlags::SIGSEGV),
+        (SIG_IGN, SignalFlags::empty()),
+    ] {
+        let pid = fork();
+        if pid == 0 {
+            set_handler(
+                SIGSEGV,
+                handler,
+                SignalFlags::empty(),
+                SigActionFlags::empty(),
+            );
+            sigprocmask(SIG_SETMASK, Some(mask), None);
+            store_to_bad_address();
+            exit(0);
+        }
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert!(WIFSIGNALED(exit_code) && WTERMSIG(exit_code) == SIGSEGV);
+        assert!(WCOREDUMP(exit_code));
+    }
+}
+
+fn signal_test_pipe() {
+    set_handler(
+        SIGPIPE,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"test"), -EPIPE);
+    check_log(&[SIGPIPE]);
+    set_handler(
+        SIGPIPE,
+        SIG_IGN,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    assert_eq!(write(pipe_fd[1], b"test"), -EPIPE);
+    check_log(&[SIGPIPE]);
+    close(pipe_fd[1]);
+
+    // a writer blocked on a full pipe returns what it has written once the
+    // last reader exits
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        assert_eq!(write(pipe_fd[1], &[0u8; 512]), 256);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    sleep(20);
+    close(pipe_fd[0]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // by default the writer is killed
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    let pid = fork();
+    if pid == 0 {
+        set_handler(
+            SIGPIPE,
+            SIG_DFL,
+            SignalFlags::empty(),
+            SigActionFlags::empty(),
+        );
+        write(pipe_fd[1], b"test");
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFSIGNALED(exit_code) && WTERMSIG(exit_code) == SIGPIPE);
+    assert!(!WCOREDUMP(exit_code));
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 2] = [
+        (signal_test_fault, "signal_test_fault"),
+        (signal_test_pipe, "signal_test_pipe"),
+    ];
+    run_tests("signal_fault_tests", &tests)
+}
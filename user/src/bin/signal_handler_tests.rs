@@ -0,0 +1,271 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+const SYSCALL_KILL: usize = 129;
+
+const ALT_STACK_SIZE: usize = 0x4000;
+// an address where nothing is mapped
+const BAD_SP: usize = 0x1000;
+
+static mut ALT_STACK: Stack<ALT_STACK_SIZE> = Stack::new();
+
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static HANDLER_SP: AtomicUsize = AtomicUsize::new(0);
+
+fn signal_test_return() {
+    // the handler returns through the trampoline, and the interrupted
+    // context goes on with its registers
+    set_handler(
+        SIGUSR1,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    check_log(&[SIGUSR1]);
+    // nothing to return to
+    assert_eq!(sigreturn(), -1);
+}
+
+extern "C" fn raise_usr2(sig: i32) {
+    log(sig);
+    kill(getpid() as usize, SIGUSR2);
+    log(-sig);
+}
+
+fn signal_test_nested() {
+    // a handler is interrupted by another signal, and both return in order
+    set_handler(
+        SIGUSR1,
+        raise_usr2 as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    set_handler(
+        SIGUSR2,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    kill(getpid() as usize, SIGUSR1);
+    check_log(&[SIGUSR1, SIGUSR2, -SIGUSR1]);
+}
+
+fn signal_test_mask() {
+    // the signals in the mask of the action wait until the handler returns
+    set_handler(
+        SIGUSR1,
+        raise_usr2 as usize,
+        SignalFlags::SIGUSR2,
+        SigActionFlags::empty(),
+    );
+    set_handler(
+        SIGUSR2,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    kill(getpid() as usize, SIGUSR1);
+    check_log(&[SIGUSR1, -SIGUSR1, SIGUSR2]);
+}
+
+extern "C" fn reenter(sig: i32) {
+    let depth = DEPTH.fetch_add(1, Ordering::SeqCst) + 1;
+    MAX_DEPTH.fetch_max(depth, Ordering::SeqCst);
+    log(sig);
+    if log_len() < 3 {
+        kill(getpid() as usize, sig);
+    }
+    DEPTH.fetch_sub(1, Ordering::SeqCst);
+}
+
+fn signal_test_defer() {
+    // the signal is blocked while its own handler runs
+    set_handler(
+        SIGUSR1,
+        reenter as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    kill(getpid() as usize, SIGUSR1);
+    check_log(&[SIGUSR1, SIGUSR1, SIGUSR1]);
+    assert_eq!(MAX_DEPTH.load(Ordering::SeqCst), 1);
+}
+
+fn signal_test_nodefer() {
+    // with SA_NODEFER, the handler is re-entered
+    set_handler(
+        SIGUSR1,
+        reenter as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_NODEFER,
+    );
+    kill(getpid() as usize, SIGUSR1);
+    check_log(&[SIGUSR1, SIGUSR1, SIGUSR1]);
+    assert_eq!(MAX_DEPTH.load(Ordering::SeqCst), 3);
+}
+
+extern "C" fn explicit_sigreturn(sig: i32) {
+    log(sig);
+    sigreturn();
+    unreachable!();
+}
+
+fn signal_test_sigreturn() {
+    // a handler can also return by calling `sigreturn` itself
+    set_handler(
+        SIGUSR1,
+        explicit_sigreturn as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    check_log(&[SIGUSR1]);
+}
+
+extern "C" fn check_info(sig: i32, info: &SigInfo, uc: &mut UContext) {
+    log(sig);
+    assert_eq!(info.signo, sig);
+    assert_eq!(info.code, SI_USER);
+    assert_eq!(info.pid, getpid() as i32);
+    // the interrupted `kill` returns what the handler puts in `x0`
+    uc.mcontext.regs[0] = 42;
+}
+
+fn signal_test_siginfo() {
+    set_handler(
+        SIGUSR1,
+        check_info as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_SIGINFO,
+    );
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 42);
+    check_log(&[SIGUSR1]);
+}
+
+fn signal_test_resethand() {
+    // the action is reset to the default when the handler is called
+    set_handler(
+        SIGUSR1,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_RESETHAND,
+    );
+    kill(getpid() as usize, SIGUSR1);
+    let new = SignalAction::default();
+    let old = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, &new, &old), 0);
+    assert_eq!(old.handler, 0);
+    check_log(&[SIGUSR1]);
+}
+
+fn alt_stack() -> SignalStack {
+    SignalStack {
+        sp: unsafe { ALT_STACK.bottom() },
+        flags: 0,
+        size: ALT_STACK_SIZE,
+    }
+}
+
+extern "C" fn on_alt_stack(sig: i32) {
+    let local = 0;
+    HANDLER_SP.store(&local as *const _ as usize, Ordering::SeqCst);
+    let mut old = SignalStack::default();
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    assert_eq!(old.flags, SS_ONSTACK);
+    // cannot be changed while running on it
+    assert_eq!(sigaltstack(Some(&SignalStack::default()), None), -EPERM);
+    log(sig);
+}
+
+fn signal_test_altstack() {
+    let mut old = alt_stack();
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    assert_eq!(old.flags, SS_DISABLE);
+    let small = SignalStack {
+        size: MINSIGSTKSZ - 1,
+        ..alt_stack()
+    };
+    assert_eq!(sigaltstack(Some(&small), None), -ENOMEM);
+    let invalid = SignalStack {
+        flags: SS_ONSTACK,
+        ..alt_stack()
+    };
+    assert_eq!(sigaltstack(Some(&invalid), None), -EINVAL);
+    assert_eq!(sigaltstack(Some(&alt_stack()), None), 0);
+    set_handler(
+        SIGUSR1,
+        on_alt_stack as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_ONSTACK,
+    );
+    kill(getpid() as usize, SIGUSR1);
+    check_log(&[SIGUSR1]);
+    let stack = alt_stack();
+    let sp = HANDLER_SP.load(Ordering::SeqCst);
+    assert!(sp > stack.sp && sp < stack.sp + stack.size);
+}
+
+/// Sends `SIGUSR1` to itself while the stack pointer is `BAD_SP`.
+fn kill_on_bad_stack() {
+    unsafe {
+        asm!("
+            mov     {saved}, sp
+            mov     sp, {bad}
+            svc     #0
+            mov     sp, {saved}",
+            saved = out(reg) _,
+            bad = in(reg) BAD_SP,
+            inout("x0") getpid() as usize => _,
+            in("x1") SIGUSR1 as usize,
+            in("x8") SYSCALL_KILL,
+        );
+    }
+}
+
+fn signal_test_overflow() {
+    set_handler(
+        SIGUSR1,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_ONSTACK,
+    );
+    // without an alternate stack, there is no room for the handler
+    let pid = fork();
+    if pid == 0 {
+        kill_on_bad_stack();
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFSIGNALED(exit_code) && WTERMSIG(exit_code) == SIGSEGV);
+    // the handler runs on the alternate stack
+    assert_eq!(sigaltstack(Some(&alt_stack()), None), 0);
+    kill_on_bad_stack();
+    check_log(&[SIGUSR1]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 10] = [
+        (signal_test_return, "signal_test_return"),
+        (signal_test_nested, "signal_test_nested"),
+        (signal_test_mask, "signal_test_mask"),
+        (signal_test_defer, "signal_test_defer"),
+        (signal_test_nodefer, "signal_test_nodefer"),
+        (signal_test_sigreturn, "signal_test_sigreturn"),
+        (signal_test_siginfo, "signal_test_siginfo"),
+        (signal_test_resethand, "signal_test_resethand"),
+        (signal_test_altstack, "signal_test_altstack"),
+        (signal_test_overflow, "signal_test_overflow"),
+    ];
+    run_tests("signal_handler_tests", &tests)
+}
@@ -0,0 +1,200 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicI32, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+static CHILD_CODE: AtomicI32 = AtomicI32::new(0);
+static CHILD_STATUS: AtomicI32 = AtomicI32::new(0);
+
+fn signal_test_stop_process() {
+    // all threads are stopped and continued together, and killed together
+    let pid = fork();
+    if pid == 0 {
+        thread_create(spin as usize, 0);
+        spin(0);
+    }
+    sleep(20);
+    let mut exit_code: i32 = 0;
+    kill(pid as usize, SIGSTOP);
+    assert_eq!(
+        waitpid_options(pid, &mut exit_code, WaitOptions::WUNTRACED),
+        pid
+    );
+    assert!(WIFSTOPPED(exit_code) && WSTOPSIG(exit_code) == SIGSTOP);
+    // reported once, though stopped in each thread
+    assert_eq!(
+        waitpid_options(
+            pid,
+            &mut exit_code,
+            WaitOptions::WUNTRACED | WaitOptions::WNOHANG
+        ),
+        0
+    );
+    // not delivered until continued
+    kill(pid as usize, SIGTERM);
+    sleep(20);
+    assert_eq!(waitpid_nb(pid as usize, &mut exit_code), 0);
+    kill(pid as usize, SIGCONT);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFSIGNALED(exit_code) && WTERMSIG(exit_code) == SIGTERM);
+}
+
+fn signal_test_background_read() {
+    // children in a group of their own are in the background
+    let old_fg = tcgetpgrp(0);
+    assert_eq!(tcsetpgrp(0, getpgid(0) as usize), 0);
+    let mut exit_code: i32 = 0;
+    let pid = fork();
+    if pid == 0 {
+        setpgid(0, 0);
+        let mut buf = [0u8; 1];
+        set_handler(
+            SIGTTIN,
+            SIG_IGN,
+            SignalFlags::empty(),
+            SigActionFlags::empty(),
+        );
+        assert_eq!(read(0, &mut buf), -EIO);
+        set_handler(
+            SIGTTIN,
+            SIG_DFL,
+            SignalFlags::empty(),
+            SigActionFlags::empty(),
+        );
+        sigprocmask(SIG_SETMASK, Some(SignalFlags::SIGTTIN), None);
+        assert_eq!(read(0, &mut buf), -EIO);
+        sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None);
+        read(0, &mut buf);
+        exit(-1);
+    }
+    // stopped by `SIGTTIN` once it neither blocks nor ignores it
+    assert_eq!(
+        waitpid_options(pid, &mut exit_code, WaitOptions::WUNTRACED),
+        pid
+    );
+    assert!(WIFSTOPPED(exit_code) && WSTOPSIG(exit_code) == SIGTTIN);
+    kill(pid as usize, SIGKILL);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFSIGNALED(exit_code) && WTERMSIG(exit_code) == SIGKILL);
+    if old_fg > 0 {
+        tcsetpgrp(0, old_fg as usize);
+    }
+}
+
+fn signal_test_pgrp_errors() {
+    // a process which has been reaped is no longer found
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert_eq!(wait_exited(pid), 0);
+    assert_eq!(getpgid(pid as usize), -ESRCH);
+    assert_eq!(getsid(pid as usize), -ESRCH);
+    assert_eq!(setpgid(pid as usize, 0), -ESRCH);
+    assert_eq!(tcsetpgrp(0, pid as usize), -EPERM);
+    // a child in a session of its own is out of reach
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setsid(), getpid());
+        write(fds[1], b"x");
+        sleep(100);
+        exit(0);
+    }
+    let mut buf = [0u8; 1];
+    assert_eq!(read(fds[0], &mut buf), 1);
+    assert_eq!(setpgid(pid as usize, 0), -EPERM);
+    assert_eq!(setpgid(0, pid as usize), -EPERM);
+    assert_eq!(wait_exited(pid), 0);
+    // a group leader can not start a session
+    assert_eq!(setpgid(0, 0), 0);
+    assert_eq!(setsid(), -EPERM);
+}
+
+extern "C" fn record_child(sig: i32, info: &SigInfo, _uc: &mut UContext) {
+    log(sig);
+    CHILD_PID.store(info.pid, Ordering::SeqCst);
+    CHILD_CODE.store(info.code, Ordering::SeqCst);
+    CHILD_STATUS.store(info.status, Ordering::SeqCst);
+}
+
+fn check_child(pid: isize, code: i32, status: i32) {
+    assert_eq!(CHILD_PID.load(Ordering::SeqCst), pid as i32);
+    assert_eq!(CHILD_CODE.load(Ordering::SeqCst), code);
+    assert_eq!(CHILD_STATUS.load(Ordering::SeqCst), status);
+}
+
+fn signal_test_sigchld() {
+    set_handler(
+        SIGCHLD,
+        record_child as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_SIGINFO | SigActionFlags::SA_RESTART,
+    );
+    let pid = fork();
+    if pid == 0 {
+        exit(3);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    check_child(pid, CLD_EXITED, 3);
+
+    let pid = fork();
+    if pid == 0 {
+        kill(getpid() as usize, SIGQUIT);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    check_child(pid, CLD_DUMPED, SIGQUIT);
+
+    let pid = fork();
+    if pid == 0 {
+        spin(0);
+    }
+    kill(pid as usize, SIGSTOP);
+    assert_eq!(
+        waitpid_options(pid, &mut exit_code, WaitOptions::WUNTRACED),
+        pid
+    );
+    check_child(pid, CLD_STOPPED, SIGSTOP);
+    kill(pid as usize, SIGCONT);
+    check_child(pid, CLD_CONTINUED, SIGCONT);
+    check_log(&[SIGCHLD, SIGCHLD, SIGCHLD, SIGCHLD]);
+
+    // only the exit is told with SA_NOCLDSTOP
+    set_handler(
+        SIGCHLD,
+        record_child as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_SIGINFO | SigActionFlags::SA_RESTART | SigActionFlags::SA_NOCLDSTOP,
+    );
+    kill(pid as usize, SIGSTOP);
+    assert_eq!(
+        waitpid_options(pid, &mut exit_code, WaitOptions::WUNTRACED),
+        pid
+    );
+    kill(pid as usize, SIGCONT);
+    check_log(&[SIGCHLD, SIGCHLD, SIGCHLD, SIGCHLD]);
+    kill(pid as usize, SIGKILL);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFSIGNALED(exit_code) && WTERMSIG(exit_code) == SIGKILL);
+    check_child(pid, CLD_KILLED, SIGKILL);
+    check_log(&[SIGCHLD, SIGCHLD, SIGCHLD, SIGCHLD, SIGCHLD]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 4] = [
+        (signal_test_stop_process, "signal_test_stop_process"),
+        (signal_test_background_read, "signal_test_background_read"),
+        (signal_test_pgrp_errors, "signal_test_pgrp_errors"),
+        (signal_test_sigchld, "signal_test_sigchld"),
+    ];
+    run_tests("signal_job_tests", &tests)
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::testing::*;
+use user_lib::*;
+
+extern "C" fn log_value(sig: i32, info: &SigInfo, _uc: &mut UContext) {
+    assert_eq!(info.signo, sig);
+    assert_eq!(info.code, SI_QUEUE);
+    assert_eq!(info.pid, getpid() as i32);
+    log(sig * 100 + info.value as i32);
+}
+
+fn signal_test_queue() {
+    // real-time signals are queued with their values, and delivered after
+    // the lower signals, while the others are merged
+    let blocked = SignalFlags::SIGUSR1 | SignalFlags::SIGRT;
+    for sig in [SIGUSR1, SIGRTMIN, SIGRTMIN + 1, SIGRTMAX] {
+        set_handler(sig, log_value as usize, blocked, SigActionFlags::SA_SIGINFO);
+    }
+    let pid = getpid() as usize;
+    sigprocmask(SIG_SETMASK, Some(blocked), None);
+    assert_eq!(sigqueue(pid, SIGRTMAX, 1), 0);
+    assert_eq!(sigqueue(pid, SIGRTMIN + 1, 2), 0);
+    assert_eq!(sigqueue(pid, SIGRTMIN, 3), 0);
+    assert_eq!(sigqueue(pid, SIGRTMIN, 4), 0);
+    assert_eq!(sigqueue(pid, SIGUSR1, 5), 0);
+    assert_eq!(sigqueue(pid, SIGUSR1, 6), 0);
+    check_log(&[]);
+    sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None);
+    check_log(&[
+        SIGUSR1 * 100 + 5,
+        SIGRTMIN * 100 + 3,
+        SIGRTMIN * 100 + 4,
+        (SIGRTMIN + 1) * 100 + 2,
+        SIGRTMAX * 100 + 1,
+    ]);
+
+    // the queue is limited by `RLIMIT_SIGPENDING`
+    clear_log();
+    let limit = Rlimit { cur: 2, max: 2 };
+    assert_eq!(setrlimit(RLIMIT_SIGPENDING, &limit), 0);
+    sigprocmask(SIG_SETMASK, Some(blocked), None);
+    assert_eq!(sigqueue(pid, SIGRTMIN, 1), 0);
+    assert_eq!(sigqueue(pid, SIGRTMIN, 2), 0);
+    assert_eq!(sigqueue(pid, SIGRTMIN, 3), -EAGAIN);
+    sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None);
+    check_log(&[SIGRTMIN * 100 + 1, SIGRTMIN * 100 + 2]);
+
+    // the code of `kill` cannot be claimed
+    let info = SigInfo {
+        code: SI_USER,
+        ..SigInfo::default()
+    };
+    assert_eq!(rt_sigqueueinfo(pid, SIGRTMIN, &info), -EPERM);
+    assert_eq!(sigqueue(pid, SIGRTMAX + 1, 0), -EINVAL);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 1] = [(signal_test_queue, "signal_test_queue")];
+    run_tests("signal_queue_tests", &tests)
+}
@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+static FUTEX: AtomicU32 = AtomicU32::new(0);
+static MUTEX_ID: AtomicUsize = AtomicUsize::new(0);
+static HELD: AtomicBool = AtomicBool::new(false);
+
+/// Holds the mutex for a while, with `SIGUSR1` blocked so that the main
+/// thread takes it.
+fn hold_mutex(_arg: usize) -> ! {
+    sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR1), None);
+    let mutex_id = MUTEX_ID.load(Ordering::SeqCst);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    HELD.store(true, Ordering::SeqCst);
+    sleep(100);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    exit(0)
+}
+
+fn signal_test_eintr() {
+    // blocking syscalls fail when a handler without SA_RESTART runs
+    set_handler(
+        SIGUSR1,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    let pid = kill_later(0);
+    assert_eq!(sleep(1000), -EINTR);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+
+    let sem_id = semaphore_create(0) as usize;
+    let pid = kill_later(0);
+    assert_eq!(semaphore_down(sem_id), -EINTR);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+
+    // a timed futex wait is interrupted, not timed out
+    let timeout = TimeSpec { sec: 1, nsec: 0 };
+    let pid = kill_later(0);
+    assert_eq!(futex_wait(&FUTEX, 0, Some(&timeout)), -EINTR);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+
+    for mutex_id in [mutex_create(), mutex_blocking_create()] {
+        MUTEX_ID.store(mutex_id as usize, Ordering::SeqCst);
+        HELD.store(false, Ordering::SeqCst);
+        let tid = thread_create(hold_mutex as usize, 0);
+        while !HELD.load(Ordering::SeqCst) {
+            sched_yield();
+        }
+        let pid = kill_later(0);
+        assert_eq!(mutex_lock_timeout(mutex_id as usize, 1000), -EINTR);
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(waittid(tid as usize), 0);
+    }
+
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = kill_later(pipe_fd[1]);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), -EINTR);
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    check_log(&[SIGUSR1, SIGUSR1, SIGUSR1, SIGUSR1, SIGUSR1, SIGUSR1]);
+}
+
+fn signal_test_restart() {
+    // with SA_RESTART, they go on after the handler returns
+    set_handler(
+        SIGUSR1,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::SA_RESTART,
+    );
+    let pid = kill_later(0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(WEXITSTATUS(exit_code), 7);
+
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = kill_later(pipe_fd[1]);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    assert_eq!(buf[0], b'x');
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    check_log(&[SIGUSR1, SIGUSR1]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 2] = [
+        (signal_test_eintr, "signal_test_eintr"),
+        (signal_test_restart, "signal_test_restart"),
+    ];
+    run_tests("signal_restart_tests", &tests)
+}
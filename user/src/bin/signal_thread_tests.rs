@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::testing::*;
+use user_lib::*;
+
+fn signal_test_procmask() {
+    let mut old = SignalFlags::all();
+    let blocked = SignalFlags::SIGUSR1 | SignalFlags::SIGKILL | SignalFlags::SIGSTOP;
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(blocked), Some(&mut old)), 0);
+    assert_eq!(old, SignalFlags::empty());
+    // `SIGKILL` and `SIGSTOP` cannot be blocked
+    assert_eq!(
+        sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR2), Some(&mut old)),
+        0
+    );
+    assert_eq!(old, SignalFlags::SIGUSR1);
+    assert_eq!(
+        sigprocmask(SIG_UNBLOCK, Some(SignalFlags::SIGUSR1), Some(&mut old)),
+        0
+    );
+    assert_eq!(old, SignalFlags::SIGUSR1 | SignalFlags::SIGUSR2);
+    assert_eq!(sigprocmask(SIG_SETMASK, None, Some(&mut old)), 0);
+    assert_eq!(old, SignalFlags::SIGUSR2);
+    assert_eq!(sigprocmask(3, Some(SignalFlags::SIGUSR1), None), -EINVAL);
+    // a pending signal is delivered once unblocked
+    set_handler(
+        SIGUSR2,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    kill(getpid() as usize, SIGUSR2);
+    check_log(&[]);
+    sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None);
+    check_log(&[SIGUSR2]);
+}
+
+extern "C" fn log_tid(_sig: i32) {
+    log(gettid() as i32);
+}
+
+fn exit_after_signal(_arg: usize) -> ! {
+    while log_len() == 0 {
+        sched_yield();
+    }
+    exit(0)
+}
+
+fn signal_test_thread() {
+    // a signal sent to the process is taken by a thread which does not
+    // block it
+    set_handler(
+        SIGUSR1,
+        log_tid as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR1), None);
+    let tid = thread_create(exit_after_signal as usize, 0);
+    assert!(tid > 0);
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    assert_eq!(waittid(tid as usize), 0);
+    check_log(&[tid as i32]);
+
+    // a signal sent to a thread is taken by that thread only
+    clear_log();
+    sigprocmask(SIG_UNBLOCK, Some(SignalFlags::SIGUSR1), None);
+    let tid = thread_create(exit_after_signal as usize, 0);
+    assert_eq!(tgkill(getpid() as usize, tid as usize, SIGUSR1), 0);
+    assert_eq!(waittid(tid as usize), 0);
+    check_log(&[tid as i32]);
+    assert_eq!(tgkill(getpid() as usize, tid as usize, SIGUSR1), -ESRCH);
+}
+
+fn signal_test_sigwait() {
+    for sig in [SIGUSR1, SIGUSR2, SIGRTMIN] {
+        set_handler(
+            sig,
+            log_signal as usize,
+            SignalFlags::empty(),
+            SigActionFlags::empty(),
+        );
+    }
+    let set = SignalFlags::SIGUSR1 | SignalFlags::SIGRT;
+    sigprocmask(SIG_BLOCK, Some(set), None);
+    let pid = getpid() as usize;
+    // a pending signal is taken at once, without its handler
+    assert_eq!(sigqueue(pid, SIGRTMIN, 7), 0);
+    let mut info = SigInfo::default();
+    assert_eq!(sigwaitinfo(set, Some(&mut info)), SIGRTMIN as isize);
+    assert_eq!((info.signo, info.code, info.value), (SIGRTMIN, SI_QUEUE, 7));
+    let timeout = TimeSpec {
+        sec: 0,
+        nsec: 20_000_000,
+    };
+    assert_eq!(sigtimedwait(set, None, &timeout), -EAGAIN);
+
+    // or when it is sent
+    let child = kill_later(0);
+    assert_eq!(sigwaitinfo(set, Some(&mut info)), SIGUSR1 as isize);
+    assert_eq!((info.code, info.pid), (SI_USER, child as i32));
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    check_log(&[]);
+
+    // a signal which is handled interrupts the wait
+    let child = fork();
+    if child == 0 {
+        sleep(20);
+        kill(pid, SIGUSR2);
+        exit(0);
+    }
+    assert_eq!(sigwaitinfo(set, None), -EINTR);
+    check_log(&[SIGUSR2]);
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 3] = [
+        (signal_test_procmask, "signal_test_procmask"),
+        (signal_test_thread, "signal_test_thread"),
+        (signal_test_sigwait, "signal_test_sigwait"),
+    ];
+    run_tests("signal_thread_tests", &tests)
+}
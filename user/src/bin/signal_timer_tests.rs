@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::testing::*;
+use user_lib::*;
+
+fn signal_test_alarm() {
+    set_handler(
+        SIGALRM,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    assert_eq!(alarm(10), 0);
+    // the seconds left are rounded up
+    assert_eq!(alarm(1), 10);
+    assert_eq!(sleep(3000), -EINTR);
+    check_log(&[SIGALRM]);
+    // cancelled
+    assert_eq!(alarm(1), 0);
+    assert_eq!(alarm(0), 1);
+    assert_eq!(sleep(1500), 0);
+    check_log(&[SIGALRM]);
+}
+
+fn ms(ms: i64) -> TimeVal {
+    TimeVal {
+        sec: ms / 1000,
+        usec: ms % 1000 * 1000,
+    }
+}
+
+fn signal_test_itimer_real() {
+    set_handler(
+        SIGALRM,
+        log_signal as usize,
+        SignalFlags::empty(),
+        SigActionFlags::empty(),
+    );
+    let timer = ITimerVal {
+        interval: ms(20),
+        value: ms(20),
+    };
+    assert_eq!(setitimer(ITIMER_REAL, &timer, None), 0);
+    while log_len() < 3 {
+        sleep(1000);
+    }
+    let mut old = ITimerVal::default();
+    assert_eq!(
+        setitimer(ITIMER_REAL, &ITimerVal::default(), Some(&mut old)),
+        0
+    );
+    assert!(old.interval.sec == 0 && old.interval.usec > 19_000 && old.interval.usec <= 20_000);
+    assert!(old.value.sec == 0 && old.value.usec <= 20_000);
+    let mut curr = timer;
+    assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+    assert_eq!((curr.value.sec, curr.value.usec), (0, 0));
+    assert_eq!(sleep(100), 0);
+    check_log(&[SIGALRM, SIGALRM, SIGALRM]);
+    let invalid = ITimerVal {
+        value: TimeVal {
+            sec: 0,
+            usec: 1_000_000,
+        },
+        ..ITimerVal::default()
+    };
+    assert_eq!(setitimer(ITIMER_REAL, &invalid, None), -EINVAL);
+    assert_eq!(setitimer(3, &timer, None), -EINVAL);
+}
+
+fn signal_test_itimer_cpu() {
+    // they only count while running
+    for (which, sig) in [(ITIMER_VIRTUAL, SIGVTALRM), (ITIMER_PROF, SIGPROF)] {
+        clear_log();
+        set_handler(
+            sig,
+            log_signal as usize,
+            SignalFlags::empty(),
+            SigActionFlags::empty(),
+        );
+        let timer = ITimerVal {
+            interval: ms(0),
+            value: ms(50),
+        };
+        assert_eq!(setitimer(which, &timer, None), 0);
+        assert_eq!(sleep(100), 0);
+        let mut curr = ITimerVal::default();
+        assert_eq!(getitimer(which, &mut curr), 0);
+        assert!(curr.value.sec == 0 && curr.value.usec > 0);
+        while log_len() == 0 {}
+        check_log(&[sig]);
+        assert_eq!(getitimer(which, &mut curr), 0);
+        assert_eq!((curr.value.sec, curr.value.usec), (0, 0));
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 3] = [
+        (signal_test_alarm, "signal_test_alarm"),
+        (signal_test_itimer_real, "signal_test_itimer_real"),
+        (signal_test_itimer_cpu, "signal_test_itimer_cpu"),
+    ];
+    run_tests("signal_timer_tests", &tests)
+}
@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::ptr::null;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn spawn_test_vfork() {
+    let pid = vfork();
+    if pid == 0 {
+        COUNTER.fetch_add(1, Ordering::SeqCst);
+        exit(3);
+    }
+    // the child has exited and its write is seen, as the memory is shared
+    assert!(pid > 0);
+    assert_eq!(COUNTER.load(Ordering::SeqCst), 1);
+    assert_eq!(wait_exited(pid), 3);
+}
+
+fn spawn_test_vfork_exec() {
+    let pid = vfork();
+    if pid == 0 {
+        exec("hello_world\0", &[null::<u8>()]);
+        exit(-1);
+    }
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn spawn_test_spawn() {
+    let pid = spawn(
+        "hello_world\0",
+        &["hello_world\0".as_ptr(), null::<u8>()],
+        &[],
+    );
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn spawn_test_file_actions() {
+    let fd = open("spawn_out\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let actions = [
+        SpawnFileAction::dup2(fd as usize, 1),
+        SpawnFileAction::close(fd as usize),
+    ];
+    let pid = spawn("hello_world\0", &[null::<u8>()], &actions);
+    assert!(pid > 0);
+    assert_eq!(wait_exited(pid), 0);
+    close(fd as usize);
+    // the output of the child went to the file
+    let fd = open("spawn_out\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 64];
+    let len = read(fd as usize, &mut buf);
+    assert!(len > 0);
+    assert!(buf.starts_with(b"Hello world"));
+    close(fd as usize);
+
+    let actions = [SpawnFileAction::open(0, "spawn_out\0", OpenFlags::RDONLY)];
+    let pid = spawn("hello_world\0", &[null::<u8>()], &actions);
+    assert_eq!(wait_exited(pid), 0);
+}
+
+fn spawn_test_errors() {
+    assert_eq!(spawn("no_such_app\0", &[null::<u8>()], &[]), -ENOENT);
+    assert_eq!(exec("no_such_app\0", &[null::<u8>()]), -ENOENT);
+    let actions = [SpawnFileAction::dup2(100, 1)];
+    assert_eq!(spawn("hello_world\0", &[null::<u8>()], &actions), -EBADF);
+    let actions = [SpawnFileAction::open(
+        0,
+        "no_such_file\0",
+        OpenFlags::RDONLY,
+    )];
+    assert_eq!(spawn("hello_world\0", &[null::<u8>()], &actions), -ENOENT);
+}
+
+fn spawn_test_bad_args() {
+    let bad = usize::MAX as *const u8;
+    assert_eq!(spawn("hello_world\0", &[bad, null::<u8>()], &[]), -EFAULT);
+    assert_eq!(exec("hello_world\0", &[bad, null::<u8>()]), -EFAULT);
+    // 5 copies of a 1K string do not fit in the room for the arguments
+    let mut arg = [b'a'; 1024];
+    arg[1023] = 0;
+    let mut args = [arg.as_ptr(); 6];
+    args[5] = null();
+    assert_eq!(spawn("hello_world\0", &args, &[]), -E2BIG);
+    assert_eq!(execve("hello_world\0", &[null::<u8>()], &args), -E2BIG);
+    // the image is kept when `exec` fails
+    assert_eq!(exec("hello_world\0", &args), -E2BIG);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 6] = [
+        (spawn_test_vfork, "spawn_test_vfork"),
+        (spawn_test_vfork_exec, "spawn_test_vfork_exec"),
+        (spawn_test_spawn, "spawn_test_spawn"),
+        (spawn_test_file_actions, "spawn_test_file_actions"),
+        (spawn_test_errors, "spawn_test_errors"),
+        (spawn_test_bad_args, "spawn_test_bad_args"),
+    ];
+    run_tests("spawn_tests", &tests)
+}
@@ -11,7 +11,7 @@ use user_lib::exit;
 use user_lib::{semaphore_create, semaphore_down, semaphore_up};
 use user_lib::{sleep, thread_create, waittid};
 
-const SEM_SYNC: usize = 0;
+static mut SEM_SYNC: usize = 0;
 
 unsafe fn first() -> ! {
     sleep(10);
@@ -30,7 +30,9 @@ unsafe fn second() -> ! {
 #[no_mangle]
 pub fn main() -> i32 {
     // create semaphores
-    assert_eq!(semaphore_create(0) as usize, SEM_SYNC);
+    unsafe {
+        SEM_SYNC = semaphore_create(0) as usize;
+    }
     // create threads
     let threads = vec![
         thread_create(first as usize, 0),
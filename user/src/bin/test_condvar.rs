@@ -15,8 +15,8 @@ use user_lib::{sleep, thread_create, waittid};
 
 static mut A: usize = 0;
 
-const CONDVAR_ID: usize = 0;
-const MUTEX_ID: usize = 0;
+static mut CONDVAR_ID: usize = 0;
+static mut MUTEX_ID: usize = 0;
 
 unsafe fn first() -> ! {
     sleep(10);
@@ -43,8 +43,10 @@ unsafe fn second() -> ! {
 #[no_mangle]
 pub fn main() -> i32 {
     // create condvar & mutex
-    assert_eq!(condvar_create() as usize, CONDVAR_ID);
-    assert_eq!(mutex_blocking_create() as usize, MUTEX_ID);
+    unsafe {
+        CONDVAR_ID = condvar_create() as usize;
+        MUTEX_ID = mutex_blocking_create() as usize;
+    }
     // create threads
     let threads = vec![
         thread_create(first as usize, 0),
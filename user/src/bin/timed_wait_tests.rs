@@ -0,0 +1,154 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::testing::*;
+use user_lib::*;
+
+const TIMEOUT_MS: usize = 50;
+const LONG_TIMEOUT_MS: usize = 1000;
+const ROUNDS: usize = 100;
+
+static SEM: AtomicUsize = AtomicUsize::new(0);
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static CONDVAR: AtomicUsize = AtomicUsize::new(0);
+static FLAG: AtomicBool = AtomicBool::new(false);
+static RELEASE: AtomicBool = AtomicBool::new(false);
+
+/// Runs `f` and returns its result with the milliseconds it took.
+fn timed(f: impl FnOnce() -> isize) -> (isize, isize) {
+    let start = get_time();
+    let ret = f();
+    (ret, get_time() - start)
+}
+
+fn up_later(_arg: usize) -> ! {
+    sleep(20);
+    semaphore_up(SEM.load(Ordering::SeqCst));
+    exit(0)
+}
+
+fn timed_wait_test_semaphore() {
+    let sem = semaphore_create(0) as usize;
+    SEM.store(sem, Ordering::SeqCst);
+    let (ret, elapsed) = timed(|| semaphore_down_timeout(sem, TIMEOUT_MS));
+    assert_eq!(ret, -ETIMEDOUT);
+    assert!(elapsed >= TIMEOUT_MS as isize);
+    // the count is given back on timeout
+    semaphore_up(sem);
+    assert_eq!(semaphore_down_timeout(sem, TIMEOUT_MS), 0);
+    assert_eq!(semaphore_down_timeout(sem, 0), -ETIMEDOUT);
+    // woken before the timeout
+    let tid = thread_create(up_later as usize, 0);
+    let (ret, elapsed) = timed(|| semaphore_down_timeout(sem, LONG_TIMEOUT_MS));
+    assert_eq!(ret, 0);
+    assert!(elapsed < LONG_TIMEOUT_MS as isize);
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+fn hold_mutex(_arg: usize) -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    FLAG.store(true, Ordering::SeqCst);
+    while !RELEASE.load(Ordering::SeqCst) {
+        sleep(10);
+    }
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+fn lock_timeout(mutex: usize) {
+    MUTEX.store(mutex, Ordering::SeqCst);
+    FLAG.store(false, Ordering::SeqCst);
+    RELEASE.store(false, Ordering::SeqCst);
+    let tid = thread_create(hold_mutex as usize, 0);
+    while !FLAG.load(Ordering::SeqCst) {
+        sleep(10);
+    }
+    let (ret, elapsed) = timed(|| mutex_lock_timeout(mutex, TIMEOUT_MS));
+    assert_eq!(ret, -ETIMEDOUT);
+    assert!(elapsed >= TIMEOUT_MS as isize);
+    RELEASE.store(true, Ordering::SeqCst);
+    assert_eq!(mutex_lock_timeout(mutex, LONG_TIMEOUT_MS), 0);
+    mutex_unlock(mutex);
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+fn timed_wait_test_mutex() {
+    lock_timeout(mutex_blocking_create() as usize);
+    lock_timeout(mutex_pi_create() as usize);
+    lock_timeout(mutex_create() as usize);
+}
+
+fn signal_later(_arg: usize) -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    sleep(20);
+    mutex_lock(mutex);
+    FLAG.store(true, Ordering::SeqCst);
+    condvar_signal(CONDVAR.load(Ordering::SeqCst));
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+fn timed_wait_test_condvar() {
+    let mutex = mutex_blocking_create() as usize;
+    let condvar = condvar_create() as usize;
+    MUTEX.store(mutex, Ordering::SeqCst);
+    CONDVAR.store(condvar, Ordering::SeqCst);
+    mutex_lock(mutex);
+    let (ret, elapsed) = timed(|| condvar_wait_timeout(condvar, mutex, TIMEOUT_MS));
+    assert_eq!(ret, -ETIMEDOUT);
+    assert!(elapsed >= TIMEOUT_MS as isize);
+    // a signal with nobody waiting is lost
+    condvar_signal(condvar);
+    assert_eq!(condvar_wait_timeout(condvar, mutex, 0), -ETIMEDOUT);
+    let tid = thread_create(signal_later as usize, 0);
+    while !FLAG.load(Ordering::SeqCst) {
+        let ret = condvar_wait_timeout(condvar, mutex, LONG_TIMEOUT_MS);
+        assert_eq!(ret, 0);
+    }
+    mutex_unlock(mutex);
+    assert_eq!(waittid(tid as usize), 0);
+}
+
+fn up_many(_arg: usize) -> ! {
+    let sem = SEM.load(Ordering::SeqCst);
+    for i in 0..ROUNDS {
+        if i % 3 == 0 {
+            sleep(1);
+        }
+        semaphore_up(sem);
+    }
+    exit(0)
+}
+
+fn timed_wait_test_race() {
+    // every up is got exactly once, whether it races with a timeout or not
+    let sem = semaphore_create(0) as usize;
+    SEM.store(sem, Ordering::SeqCst);
+    let tid = thread_create(up_many as usize, 0);
+    let mut got = 0;
+    let start = get_time();
+    while got < ROUNDS && get_time() - start < LONG_TIMEOUT_MS as isize {
+        match semaphore_down_timeout(sem, 1) {
+            0 => got += 1,
+            ret => assert_eq!(ret, -ETIMEDOUT),
+        }
+    }
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(got, ROUNDS);
+    assert_eq!(semaphore_down_timeout(sem, 0), -ETIMEDOUT);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tests: [(fn(), &str); 4] = [
+        (timed_wait_test_semaphore, "timed_wait_test_semaphore"),
+        (timed_wait_test_mutex, "timed_wait_test_mutex"),
+        (timed_wait_test_condvar, "timed_wait_test_condvar"),
+        (timed_wait_test_race, "timed_wait_test_race"),
+    ];
+    run_tests("timed_wait_tests", &tests)
+}
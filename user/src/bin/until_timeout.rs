@@ -4,7 +4,12 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, get_time, kill, waitpid, waitpid_nb, SignalFlags};
+use user_lib::{
+    exec, fork, get_time, kill, setitimer, sigaction, waitpid, ITimerVal, SignalAction, TimeVal,
+    ITIMER_REAL, SIGALRM, SIGINT,
+};
+
+extern "C" fn on_alarm(_sig: i32) {}
 
 #[no_mangle]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
@@ -19,25 +24,33 @@ pub fn main(argc: usize, argv: &[&str]) -> i32 {
             return -4;
         }
     } else {
+        // without SA_RESTART, the alarm interrupts waitpid
+        let action = SignalAction {
+            handler: on_alarm as usize,
+            ..SignalAction::default()
+        };
+        let old = SignalAction::default();
+        assert_eq!(sigaction(SIGALRM, &action, &old), 0);
+        let timer = ITimerVal {
+            value: TimeVal {
+                sec: timeout_ms as i64 / 1000,
+                usec: timeout_ms as i64 % 1000 * 1000,
+            },
+            ..ITimerVal::default()
+        };
         let start_time = get_time();
-        let mut child_exited = false;
+        assert_eq!(setitimer(ITIMER_REAL, &timer, None), 0);
         let mut exit_code: i32 = 0;
-        loop {
-            if get_time() - start_time > timeout_ms {
-                break;
-            }
-            if waitpid_nb(pid, &mut exit_code) as usize == pid {
-                child_exited = true;
-                println!(
-                    "child exited in {}ms, exit_code = {}",
-                    get_time() - start_time,
-                    exit_code,
-                );
-            }
-        }
-        if !child_exited {
+        if waitpid(pid, &mut exit_code) as usize == pid {
+            setitimer(ITIMER_REAL, &ITimerVal::default(), None);
+            println!(
+                "child exited in {}ms, exit_code = {}",
+                get_time() - start_time,
+                exit_code,
+            );
+        } else {
             println!("child has run for {}ms, kill it!", timeout_ms);
-            kill(pid, SignalFlags::SIGINT.bits());
+            kill(pid, SIGINT);
             assert_eq!(waitpid(pid, &mut exit_code) as usize, pid);
             println!("exit code of the child is {}", exit_code);
         }
@@ -13,10 +13,204 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 const LINE_START: &str = ">> ";
 
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{
+    close, dup, environ, exec, exit, get_time, getenv, getpid, killpg, open, pipe, setenv, setpgid,
+    sigaction, sigreturn, tcsetpgrp, times, vfork, waitpid_options, OpenFlags, SignalAction, Tms,
+    WaitOptions, CLK_TCK, SIGCONT, SIGINT, SIGTSTP, WIFSTOPPED,
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum JobState {
+    Running,
+    Stopped,
+}
+
+/// A pipeline started by one command line, all in the same process group.
+struct Job {
+    id: usize,
+    pgid: usize,
+    /// Processes which have not exited yet.
+    pids: Vec<isize>,
+    state: JobState,
+    command: String,
+}
+
+struct JobList {
+    shell_pgid: usize,
+    jobs: Vec<Job>,
+}
+
+impl JobList {
+    fn add(&mut self, pgid: usize, pids: Vec<isize>, state: JobState, command: &str) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pgid,
+            pids,
+            state,
+            command: command.to_string(),
+        });
+        id
+    }
+
+    /// Finds the job by the id given as `args[1]`, or the latest job.
+    fn find(&mut self, args: &[&str]) -> Option<usize> {
+        match args.get(1) {
+            Some(arg) => {
+                let id: usize = arg.trim_start_matches('%').parse().ok()?;
+                self.jobs.iter().position(|job| job.id == id)
+            }
+            None => self.jobs.len().checked_sub(1),
+        }
+    }
+
+    /// Gives the terminal to the job and waits until all its processes exit
+    /// or one of them is stopped. The job is removed if it is done.
+    fn wait_foreground(&mut self, idx: usize) {
+        let job = &mut self.jobs[idx];
+        tcsetpgrp(0, job.pgid);
+        while let Some(&pid) = job.pids.first() {
+            let mut exit_code: i32 = 0;
+            let exit_pid = waitpid_options(pid, &mut exit_code, WaitOptions::WUNTRACED);
+            assert_eq!(pid, exit_pid);
+            if WIFSTOPPED(exit_code) {
+                job.state = JobState::Stopped;
+                println!("");
+                println!("[{}] Stopped    {}", job.id, job.command);
+                break;
+            }
+            job.pids.remove(0);
+        }
+        tcsetpgrp(0, self.shell_pgid);
+        if self.jobs[idx].pids.is_empty() {
+            self.jobs.remove(idx);
+        }
+    }
+
+    /// Reaps background processes and reports the jobs which are stopped or done.
+    fn update(&mut self) {
+        loop {
+            let mut exit_code: i32 = 0;
+            let pid = waitpid_options(
+                -1,
+                &mut exit_code,
+                WaitOptions::WNOHANG | WaitOptions::WUNTRACED,
+            );
+            if pid <= 0 {
+                break;
+            }
+            if let Some(idx) = self.jobs.iter().position(|job| job.pids.contains(&pid)) {
+                let job = &mut self.jobs[idx];
+                if WIFSTOPPED(exit_code) {
+                    if job.state != JobState::Stopped {
+                        job.state = JobState::Stopped;
+                        println!("[{}] Stopped    {}", job.id, job.command);
+                    }
+                } else {
+                    job.pids.retain(|&p| p != pid);
+                    if job.pids.is_empty() {
+                        println!("[{}] Done       {}", job.id, job.command);
+                        self.jobs.remove(idx);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the builtin commands `jobs`, `fg`, `bg` and `export`, returns
+    /// false if the command is not a builtin.
+    fn run_builtin(&mut self, line: &str) -> bool {
+        let args: Vec<_> = line.split(' ').filter(|arg| !arg.is_empty()).collect();
+        match args.first().copied() {
+            Some("jobs") => {
+                for job in self.jobs.iter() {
+                    let state = match job.state {
+                        JobState::Running => "Running",
+                        JobState::Stopped => "Stopped",
+                    };
+                    println!("[{}] {:<10} {}", job.id, state, job.command);
+                }
+            }
+            Some("fg") => match self.find(&args) {
+                Some(idx) => {
+                    let job = &mut self.jobs[idx];
+                    println!("{}", job.command);
+                    job.state = JobState::Running;
+                    tcsetpgrp(0, job.pgid);
+                    killpg(job.pgid, SIGCONT);
+                    self.wait_foreground(idx);
+                }
+                None => println!("fg: no such job"),
+            },
+            Some("bg") => match self.find(&args) {
+                Some(idx) => {
+                    let job = &mut self.jobs[idx];
+                    job.state = JobState::Running;
+                    killpg(job.pgid, SIGCONT);
+                    println!("[{}] {} &", job.id, job.command);
+                }
+                None => println!("bg: no such job"),
+            },
+            Some("export") => {
+                if args.len() == 1 {
+                    for var in environ() {
+                        println!("export {}", var);
+                    }
+                }
+                for arg in &args[1..] {
+                    let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+                    if setenv(name, &expand_vars(value)) != 0 {
+                        println!("export: invalid name {}", name);
+                    }
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Prints the elapsed time in ms, and the CPU times of children in clock ticks.
+fn print_times(real_ms: isize, user_ticks: i64, sys_ticks: i64) {
+    let to_ms = |ticks: i64| ticks as isize * 1000 / CLK_TCK;
+    for (name, ms) in [
+        ("real", real_ms),
+        ("user", to_ms(user_ticks)),
+        ("sys", to_ms(sys_ticks)),
+    ] {
+        println!("{:<8}{}.{:03}s", name, ms / 1000, ms % 1000);
+    }
+}
+
+/// Replaces `$NAME` in `arg` with the value of the environment variable, or
+/// nothing if it is not set.
+fn expand_vars(arg: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = arg;
+    while let Some(idx) = rest.find('$') {
+        expanded.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            expanded.push('$');
+        } else if let Some(value) = getenv(&rest[..len]) {
+            expanded.push_str(&value);
+        }
+        rest = &rest[len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Keyboard signals are for the foreground job, not the shell itself.
+fn ignore_signal() {
+    sigreturn();
+}
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -33,8 +227,7 @@ impl ProcessArguments {
             .iter()
             .filter(|&arg| !arg.is_empty())
             .map(|&arg| {
-                let mut string = String::new();
-                string.push_str(arg);
+                let mut string = expand_vars(arg);
                 string.push('\0');
                 string
             })
@@ -77,15 +270,40 @@ impl ProcessArguments {
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    let mut ignore = SignalAction::default();
+    let old = SignalAction::default();
+    ignore.handler = ignore_signal as usize;
+    sigaction(SIGINT, &ignore, &old);
+    sigaction(SIGTSTP, &ignore, &old);
+    let mut job_list = JobList {
+        shell_pgid: getpid() as usize,
+        jobs: Vec::new(),
+    };
+    tcsetpgrp(0, job_list.shell_pgid);
     let mut line: String = String::new();
     print!("{}", LINE_START);
     loop {
         let c = getchar();
         match c {
+            // the read is interrupted by a signal
+            0 => {}
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
-                    let splited: Vec<_> = line.as_str().split('|').collect();
+                job_list.update();
+                if !line.is_empty() && !job_list.run_builtin(line.as_str()) {
+                    let command = line.trim_end();
+                    let (command, background) = match command.strip_suffix('&') {
+                        Some(command) => (command.trim_end(), true),
+                        None => (command, false),
+                    };
+                    let (command, timed) = match command.strip_prefix("time ") {
+                        Some(command) => (command.trim_start(), !background),
+                        None => (command, false),
+                    };
+                    let start_time = get_time();
+                    let mut start_tms = Tms::default();
+                    times(&mut start_tms);
+                    let splited: Vec<_> = command.split('|').collect();
                     let process_arguments_list: Vec<_> = splited
                         .iter()
                         .map(|&cmd| ProcessArguments::new(cmd))
@@ -121,9 +339,18 @@ pub fn main() -> i32 {
                             }
                         }
                         let mut children: Vec<_> = Vec::new();
+                        // the first process leads the process group of the job
+                        let mut pgid = 0;
                         for (i, process_argument) in process_arguments_list.iter().enumerate() {
-                            let pid = fork();
+                            // the shell is suspended until the child calls `exec`,
+                            // so the child must exit instead of returning
+                            let pid = vfork();
                             if pid == 0 {
+                                // join the job before exec, on behalf of the shell too
+                                setpgid(0, pgid);
+                                if !background {
+                                    tcsetpgrp(0, if pgid == 0 { getpid() as usize } else { pgid });
+                                }
                                 let input = &process_argument.input;
                                 let output = &process_argument.output;
                                 let args_copy = &process_argument.args_copy;
@@ -133,7 +360,7 @@ pub fn main() -> i32 {
                                     let input_fd = open(input.as_str(), OpenFlags::RDONLY);
                                     if input_fd == -1 {
                                         println!("Error when opening file {}", input);
-                                        return -4;
+                                        exit(-4);
                                     }
                                     let input_fd = input_fd as usize;
                                     close(0);
@@ -148,7 +375,7 @@ pub fn main() -> i32 {
                                     );
                                     if output_fd == -1 {
                                         println!("Error when opening file {}", output);
-                                        return -4;
+                                        exit(-4);
                                     }
                                     let output_fd = output_fd as usize;
                                     close(1);
@@ -173,12 +400,16 @@ pub fn main() -> i32 {
                                     close(pipe_fd[1]);
                                 }
                                 // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
+                                if exec(args_copy[0].as_str(), args_addr.as_slice()) < 0 {
                                     println!("Error when executing!");
-                                    return -4;
+                                    exit(-4);
                                 }
                                 unreachable!();
                             } else {
+                                if pgid == 0 {
+                                    pgid = pid as usize;
+                                }
+                                setpgid(pid as usize, pgid);
                                 children.push(pid);
                             }
                         }
@@ -186,15 +417,24 @@ pub fn main() -> i32 {
                             close(pipe_fd[0]);
                             close(pipe_fd[1]);
                         }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        let id = job_list.add(pgid, children, JobState::Running, command);
+                        if background {
+                            println!("[{}] {}", id, pgid);
+                        } else {
+                            job_list.wait_foreground(job_list.jobs.len() - 1);
+                            if timed {
+                                let mut tms = Tms::default();
+                                times(&mut tms);
+                                print_times(
+                                    get_time() - start_time,
+                                    tms.tms_cutime - start_tms.tms_cutime,
+                                    tms.tms_cstime - start_tms.tms_cstime,
+                                );
+                            }
                         }
                     }
-                    line.clear();
                 }
+                line.clear();
                 print!("{}", LINE_START);
             }
             BS | DL => {
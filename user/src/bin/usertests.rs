@@ -24,6 +24,27 @@ static TESTS: &[&str] = &[
     "sig_simple\0",
     "sig_simple2\0",
     "sig_tests\0",
+    "rlimit_tests\0",
+    "cpu_times\0",
+    "clone_tests\0",
+    "spawn_tests\0",
+    "env_tests\0",
+    "fp_tests\0",
+    "futex_tests\0",
+    "pi_tests\0",
+    "deadlock_tests\0",
+    "timed_wait_tests\0",
+    "rwlock_barrier_tests\0",
+    "handle_tests\0",
+    "ipc_tests\0",
+    "signal_handler_tests\0",
+    "signal_restart_tests\0",
+    "signal_default_tests\0",
+    "signal_job_tests\0",
+    "signal_timer_tests\0",
+    "signal_queue_tests\0",
+    "signal_thread_tests\0",
+    "signal_fault_tests\0",
     "run_cmdline_args\0",
     "threads_arg\0",
     "threads\0",
@@ -45,7 +66,7 @@ static TESTS: &[&str] = &[
     "race_adder_loop\0",
 ];
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{exec, fork, waitpid, WEXITSTATUS, WIFSIGNALED, WTERMSIG};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -53,7 +74,7 @@ pub fn main() -> i32 {
         println!("Usertests: Running '{}':", test);
         let pid = fork();
         if pid == 0 {
-            if exec(*test, &[core::ptr::null::<u8>()]) == -1 {
+            if exec(*test, &[core::ptr::null::<u8>()]) < 0 {
                 panic!("usertest '{}' not found!", test);
             } else {
                 panic!("unreachable!");
@@ -63,10 +84,23 @@ pub fn main() -> i32 {
             let wait_pid = waitpid(pid as usize, &mut exit_code);
             assert_eq!(pid, wait_pid);
             let color = if exit_code == 0 { 32 } else { 31 };
-            println!(
-                "\x1b[{}mUsertests: Test '{}' in Process {} exited with code {}.\x1b[0m",
-                color, test, pid, exit_code
-            );
+            if WIFSIGNALED(exit_code) {
+                println!(
+                    "\x1b[{}mUsertests: Test '{}' in Process {} killed by signal {}.\x1b[0m",
+                    color,
+                    test,
+                    pid,
+                    WTERMSIG(exit_code)
+                );
+            } else {
+                println!(
+                    "\x1b[{}mUsertests: Test '{}' in Process {} exited with code {}.\x1b[0m",
+                    color,
+                    test,
+                    pid,
+                    WEXITSTATUS(exit_code) as i8
+                );
+            }
         }
     }
     println!("usertests passed!");
@@ -0,0 +1,86 @@
+//! Environment variables, passed by `execve` and inherited by `fork`.
+
+use super::EINVAL;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The variables as `NAME=value\0`.
+static mut ENVIRON: Vec<String> = Vec::new();
+
+fn environ_mut() -> &'static mut Vec<String> {
+    unsafe { &mut ENVIRON }
+}
+
+/// Finds the variable `name`, returns its index and the value.
+fn find(name: &str) -> Option<(usize, &'static str)> {
+    environ_mut().iter().enumerate().find_map(|(idx, var)| {
+        let value = var.strip_prefix(name)?.strip_prefix('=')?;
+        Some((idx, value.trim_end_matches('\0')))
+    })
+}
+
+/// Collects the null-terminated `envp` given to `_start`.
+pub(crate) fn init_environ(envp: usize) {
+    let environ = environ_mut();
+    let mut ptr = envp as *const usize;
+    while !ptr.is_null() {
+        let str_start = unsafe { ptr.read_volatile() };
+        if str_start == 0 {
+            break;
+        }
+        let len = (0usize..)
+            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() == 0 })
+            .unwrap();
+        let var = unsafe { core::slice::from_raw_parts(str_start as *const u8, len) };
+        let mut var = String::from(core::str::from_utf8(var).unwrap());
+        var.push('\0');
+        environ.push(var);
+        ptr = unsafe { ptr.add(1) };
+    }
+}
+
+/// The null-terminated `envp` to pass to `execve`.
+pub(crate) fn envp() -> Vec<*const u8> {
+    let mut envp: Vec<*const u8> = environ_mut().iter().map(|var| var.as_ptr()).collect();
+    envp.push(core::ptr::null());
+    envp
+}
+
+pub fn getenv(name: &str) -> Option<String> {
+    find(name).map(|(_, value)| String::from(value))
+}
+
+/// Sets the variable `name` to `value`, returns `-EINVAL` if `name` is empty
+/// or contains `=`.
+pub fn setenv(name: &str, value: &str) -> isize {
+    if name.is_empty() || name.contains('=') {
+        return -EINVAL;
+    }
+    let mut var = String::from(name);
+    var.push('=');
+    var.push_str(value);
+    var.push('\0');
+    match find(name) {
+        Some((idx, _)) => environ_mut()[idx] = var,
+        None => environ_mut().push(var),
+    }
+    0
+}
+
+pub fn unsetenv(name: &str) -> isize {
+    if name.is_empty() || name.contains('=') {
+        return -EINVAL;
+    }
+    if let Some((idx, _)) = find(name) {
+        environ_mut().remove(idx);
+    }
+    0
+}
+
+/// All the variables as `NAME=value`.
+pub fn environ() -> Vec<String> {
+    environ_mut()
+        .iter()
+        .map(|var| String::from(var.trim_end_matches('\0')))
+        .collect()
+}
@@ -5,11 +5,18 @@
 
 #[macro_use]
 pub mod console;
+mod env;
+mod errno;
 mod lang_items;
 mod signals;
+mod sync;
 mod syscall;
+pub mod testing;
 
+pub use env::*;
+pub use errno::*;
 pub use signals::*;
+pub use sync::*;
 
 extern crate alloc;
 #[macro_use]
@@ -17,6 +24,8 @@ extern crate bitflags;
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::ptr::{null, null_mut};
+use core::sync::atomic::AtomicU32;
 use syscall::*;
 
 const USER_HEAP_SIZE: usize = 32768;
@@ -33,11 +42,12 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
+    env::init_environ(envp);
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
@@ -66,20 +76,222 @@ bitflags! {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
+        const EXCL = 1 << 7;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
     }
 }
 
+bitflags! {
+    pub struct WaitOptions: u32 {
+        const WNOHANG = 1;
+        const WUNTRACED = 2;
+    }
+}
+
+bitflags! {
+    pub struct CloneFlags: u32 {
+        const CLONE_VM = 0x100;
+        const CLONE_FILES = 0x400;
+        const CLONE_SIGHAND = 0x800;
+        const CLONE_VFORK = 0x4000;
+        const CLONE_THREAD = 0x1_0000;
+        const CLONE_SETTLS = 0x8_0000;
+        const CLONE_PARENT_SETTID = 0x10_0000;
+        const CLONE_CHILD_CLEARTID = 0x20_0000;
+    }
+}
+
+bitflags! {
+    /// Rights of a handle, see `handle_restrict`.
+    pub struct Rights: u32 {
+        const READ = 1;
+        const WRITE = 1 << 1;
+        const INHERIT = 1 << 2;
+    }
+}
+
+const SPAWN_CLOSE: usize = 0;
+const SPAWN_DUP2: usize = 1;
+const SPAWN_OPEN: usize = 2;
+
+/// A change to the handle table of the child made by `spawn`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SpawnFileAction {
+    op: usize,
+    fd: usize,
+    arg: usize,
+    flags: usize,
+}
+
+impl SpawnFileAction {
+    /// Closes `fd`.
+    pub fn close(fd: usize) -> Self {
+        Self {
+            op: SPAWN_CLOSE,
+            fd,
+            arg: 0,
+            flags: 0,
+        }
+    }
+
+    /// Makes `new_fd` refer to the file of `old_fd`.
+    pub fn dup2(old_fd: usize, new_fd: usize) -> Self {
+        Self {
+            op: SPAWN_DUP2,
+            fd: new_fd,
+            arg: old_fd,
+            flags: 0,
+        }
+    }
+
+    /// Opens the file at `path`, which ends with `\0`, as `fd`.
+    pub fn open(fd: usize, path: &str, flags: OpenFlags) -> Self {
+        Self {
+            op: SPAWN_OPEN,
+            fd,
+            arg: path.as_ptr() as usize,
+            flags: flags.bits as usize,
+        }
+    }
+}
+
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_FSIZE: usize = 1;
+pub const RLIMIT_DATA: usize = 2;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIMIT_SIGPENDING: usize = 11;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rlimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+/// Clock ticks per second, the unit of `times`.
+pub const CLK_TCK: isize = 100;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tms {
+    pub tms_utime: i64,
+    pub tms_stime: i64,
+    pub tms_cutime: i64,
+    pub tms_cstime: i64,
+}
+
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeVal {
+    pub sec: i64,
+    pub usec: i64,
+}
+
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: i64,
+    pub nsec: i64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rusage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    pub ru_others: [i64; 14],
+}
+
+pub const MAX_PRIORITY: usize = 31;
+pub const DEFAULT_PRIORITY: usize = 16;
+
+const MUTEX_BLOCKING: usize = 1;
+const MUTEX_PI: usize = 2;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_REQUEUE: usize = 3;
+
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+
+#[allow(non_snake_case)]
+pub const fn WIFEXITED(status: i32) -> bool {
+    status & 0x7f == 0
+}
+#[allow(non_snake_case)]
+pub const fn WEXITSTATUS(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+#[allow(non_snake_case)]
+pub const fn WIFSIGNALED(status: i32) -> bool {
+    status & 0x7f != 0 && status & 0x7f != 0x7f
+}
+#[allow(non_snake_case)]
+pub const fn WTERMSIG(status: i32) -> i32 {
+    status & 0x7f
+}
+/// Whether the child was terminated by a signal whose default action dumps
+/// core.
+#[allow(non_snake_case)]
+pub const fn WCOREDUMP(status: i32) -> bool {
+    status & 0x80 != 0
+}
+#[allow(non_snake_case)]
+pub const fn WIFSTOPPED(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+#[allow(non_snake_case)]
+pub const fn WSTOPSIG(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
+/// Closes a handle of any kind, the sync objects are destroyed this way.
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid: i32 = 0;
+    if sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut _ as usize) < 0 {
+        return -1;
+    }
+    pgid as isize
+}
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const _ as usize)
+}
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -92,8 +304,9 @@ pub fn exit(exit_code: i32) -> ! {
 pub fn exit_group(exit_code: i32) -> ! {
     sys_exit_group(exit_code);
 }
-pub fn sleep(sleep_ms: usize) {
-    sys_sleep(sleep_ms);
+/// Returns `-EINTR` if a signal handler runs before `sleep_ms` passes.
+pub fn sleep(sleep_ms: usize) -> isize {
+    sys_sleep(sleep_ms)
 }
 pub fn sched_yield() -> isize {
     sys_yield()
@@ -107,38 +320,138 @@ pub fn getpid() -> isize {
 pub fn fork() -> isize {
     sys_fork()
 }
+/// Creates a child process which shares the address space, and suspends the
+/// caller until the child calls `exec` or exits. The child must not return
+/// from the calling function.
+#[inline(always)]
+pub fn vfork() -> isize {
+    sys_vfork()
+}
+/// Creates a child process running the program `path`, whose fd table is a
+/// copy of the caller's changed by `file_actions` in order.
+pub fn spawn(path: &str, args: &[*const u8], file_actions: &[SpawnFileAction]) -> isize {
+    sys_spawn(path, args, &env::envp(), file_actions)
+}
+/// Creates a task running `entry(arg)` on the stack `stack_top`. Returns the
+/// tid with `CLONE_THREAD`, or the pid of the new child process.
+pub fn clone(
+    entry: extern "C" fn(usize) -> i32,
+    stack_top: usize,
+    flags: CloneFlags,
+    arg: usize,
+    parent_tid: *mut i32,
+    tls: usize,
+    child_tid: *mut i32,
+) -> isize {
+    sys_clone(
+        flags.bits, stack_top, parent_tid, tls, child_tid, entry, arg,
+    )
+}
+/// `execve` with the environment of the caller.
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
-    sys_exec(path, args)
+    sys_execve(path, args, &env::envp())
+}
+pub fn execve(path: &str, args: &[*const u8], envp: &[*const u8]) -> isize {
+    sys_execve(path, args, envp)
 }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }
 pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                sched_yield();
-            }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
-        }
-    }
+    sys_waitpid(-1, exit_code as *mut _, 0)
 }
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
-                sched_yield();
-            }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
-        }
-    }
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+
+pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _, WaitOptions::WNOHANG.bits)
+}
+
+pub fn waitpid_options(pid: isize, exit_code: &mut i32, options: WaitOptions) -> isize {
+    sys_waitpid(pid, exit_code as *mut _, options.bits)
 }
 
 pub fn kill(pid: usize, signal: i32) -> isize {
-    sys_kill(pid, signal)
+    sys_kill(pid as isize, signal)
+}
+
+/// Sends `signal` to the task `tid` of the process `tgid`, rather than to any
+/// task of the process which does not block it.
+pub fn tgkill(tgid: usize, tid: usize, signal: i32) -> isize {
+    sys_tgkill(tgid, tid, signal)
+}
+
+pub fn killpg(pgid: usize, signal: i32) -> isize {
+    sys_kill(-(pgid as isize), signal)
+}
+
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
+pub fn times(tms: &mut Tms) -> isize {
+    sys_times(tms as *mut _)
+}
+
+pub fn getrusage(who: isize, usage: &mut Rusage) -> isize {
+    sys_getrusage(who, usage as *mut _)
+}
+
+pub fn getitimer(which: usize, curr_value: &mut ITimerVal) -> isize {
+    sys_getitimer(which, curr_value as *mut _)
+}
+
+/// Sets interval timer `which`, `ITIMER_REAL` sends `SIGALRM`,
+/// `ITIMER_VIRTUAL` sends `SIGVTALRM`, and `ITIMER_PROF` sends `SIGPROF`.
+pub fn setitimer(which: usize, new_value: &ITimerVal, old_value: Option<&mut ITimerVal>) -> isize {
+    let old_value = old_value.map_or(null_mut(), |v| v as *mut _);
+    sys_setitimer(which, new_value as *const _, old_value)
+}
+
+/// Returns the seconds left of the old alarm.
+pub fn alarm(secs: usize) -> isize {
+    sys_alarm(secs)
+}
+
+pub fn getrlimit(resource: usize, rlim: &mut Rlimit) -> isize {
+    sys_getrlimit(resource, rlim as *mut _)
+}
+
+pub fn setrlimit(resource: usize, rlim: &Rlimit) -> isize {
+    sys_setrlimit(resource, rlim as *const _)
+}
+
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const PROT_EXEC: usize = 4;
+
+/// Moves the program break by `increment` bytes, returns the old break.
+pub fn sbrk(increment: isize) -> isize {
+    sys_sbrk(increment)
+}
+
+/// Maps `len` bytes of zeroed memory at the page aligned `start`, returns
+/// `start`.
+pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
+    sys_mmap(start, len, prot)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
 }
 
 pub fn thread_create(entry: usize, arg: usize) -> isize {
@@ -148,60 +461,253 @@ pub fn thread_create(entry: usize, arg: usize) -> isize {
 pub fn gettid() -> isize {
     sys_gettid()
 }
+/// Sets the priority of the current task, in `0..=MAX_PRIORITY`. Ready
+/// tasks of the highest priority always run first.
+pub fn set_priority(prio: usize) -> isize {
+    sys_set_priority(prio)
+}
+/// The priority of the current task, which may be raised by the waiters of
+/// the PI mutexes which it holds.
+pub fn get_priority() -> isize {
+    sys_get_priority()
+}
 
 pub fn waittid(tid: usize) -> isize {
-    loop {
-        match sys_waittid(tid) {
-            -2 => {
-                sched_yield();
-            }
-            exit_code => return exit_code,
-        }
-    }
+    sys_waittid(tid)
 }
 
+/// Sets the action for `signum`. The handler may simply return, unless it
+/// is given its own restorer by `SA_RESTORER`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn sigaction(
     signum: i32,
     action: *const SignalAction,
     old_action: *const SignalAction,
 ) -> isize {
-    sys_sigaction(signum, action, old_action)
+    if action.is_null() {
+        return sys_sigaction(signum, action, old_action);
+    }
+    let mut action = unsafe { *action };
+    if !action.flags.contains(SigActionFlags::SA_RESTORER) {
+        action.flags |= SigActionFlags::SA_RESTORER;
+        action.restorer = __sigreturn_trampoline as usize;
+    }
+    sys_sigaction(signum, &action, old_action)
+}
+
+/// Sets the stack for the handlers with `SA_ONSTACK` to `ss`, and gets the
+/// old one into `old_ss`.
+pub fn sigaltstack(ss: Option<&SignalStack>, old_ss: Option<&mut SignalStack>) -> isize {
+    let ss = ss.map_or(null(), |ss| ss as *const _);
+    let old_ss = old_ss.map_or(null_mut(), |ss| ss as *mut _);
+    sys_sigaltstack(ss, old_ss)
+}
+
+/// Blocks the signals in `set`, unblocks them or blocks exactly them, as
+/// `how` says with `SIG_BLOCK`, `SIG_UNBLOCK` or `SIG_SETMASK`, and gets the
+/// old blocked signals into `old_set`.
+pub fn sigprocmask(
+    how: usize,
+    set: Option<SignalFlags>,
+    old_set: Option<&mut SignalFlags>,
+) -> isize {
+    let set = set.map(|set| set.bits());
+    let set_ptr = set.as_ref().map_or(null(), |set| set as *const _);
+    let mut old = 0;
+    let old_ptr = if old_set.is_some() {
+        &mut old as *mut _
+    } else {
+        null_mut()
+    };
+    let ret = sys_sigprocmask(how, set_ptr, old_ptr);
+    if let Some(old_set) = old_set {
+        *old_set = SignalFlags::from_bits_truncate(old);
+    }
+    ret
+}
+
+/// Waits for a signal in `set`, which should be blocked, and takes it without
+/// calling its handler. Returns its number, and its information in `info`.
+pub fn sigwaitinfo(set: SignalFlags, info: Option<&mut SigInfo>) -> isize {
+    let info = info.map_or(null_mut(), |info| info as *mut _);
+    sys_sigtimedwait(&set.bits(), info, null())
+}
+
+/// Like `sigwaitinfo`, but fails with `EAGAIN` after `timeout`.
+pub fn sigtimedwait(set: SignalFlags, info: Option<&mut SigInfo>, timeout: &TimeSpec) -> isize {
+    let info = info.map_or(null_mut(), |info| info as *mut _);
+    sys_sigtimedwait(&set.bits(), info, timeout)
+}
+
+/// Sends `signal` with `value` to the process `pid`, which gets the value in
+/// `SigInfo::value`. A real-time signal is queued even if already pending.
+pub fn sigqueue(pid: usize, signal: i32, value: usize) -> isize {
+    let info = SigInfo {
+        code: SI_QUEUE,
+        value,
+        ..SigInfo::default()
+    };
+    rt_sigqueueinfo(pid, signal, &info)
 }
 
-pub fn sigprocmask(mask: u32) -> isize {
-    sys_sigprocmask(mask)
+/// Sends `signal` described by `info`, whose code must be negative, to the
+/// process `pid`.
+pub fn rt_sigqueueinfo(pid: usize, signal: i32, info: &SigInfo) -> isize {
+    sys_rt_sigqueueinfo(pid, signal, info)
 }
 
 pub fn sigreturn() -> isize {
     sys_sigreturn()
 }
+/// Blocks while `futex` holds `val`, until woken or `timeout` passes.
+pub fn futex_wait(futex: &AtomicU32, val: u32, timeout: Option<&TimeSpec>) -> isize {
+    let timeout = timeout.map_or(0, |t| t as *const _ as usize);
+    sys_futex(
+        futex as *const _ as *const u32,
+        FUTEX_WAIT,
+        val as usize,
+        timeout,
+        null(),
+    )
+}
+/// Wakes at most `count` tasks waiting on `futex`.
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> isize {
+    sys_futex(
+        futex as *const _ as *const u32,
+        FUTEX_WAKE,
+        count,
+        0,
+        null(),
+    )
+}
+/// Wakes at most `count` tasks waiting on `futex`, and moves at most
+/// `requeue_count` of the others to wait on `futex2`.
+pub fn futex_requeue(
+    futex: &AtomicU32,
+    count: usize,
+    futex2: &AtomicU32,
+    requeue_count: usize,
+) -> isize {
+    sys_futex(
+        futex as *const _ as *const u32,
+        FUTEX_REQUEUE,
+        count,
+        requeue_count,
+        futex2 as *const _ as *const u32,
+    )
+}
 pub fn mutex_create() -> isize {
-    sys_mutex_create(false)
+    sys_mutex_create(0)
 }
 pub fn mutex_blocking_create() -> isize {
-    sys_mutex_create(true)
+    sys_mutex_create(MUTEX_BLOCKING)
+}
+/// Creates a blocking mutex whose holder inherits the priorities of the
+/// waiters.
+pub fn mutex_pi_create() -> isize {
+    sys_mutex_create(MUTEX_BLOCKING | MUTEX_PI)
+}
+/// Returns `-EDEADLK` if the deadlock detection is enabled and blocking
+/// could deadlock.
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
 }
-pub fn mutex_lock(mutex_id: usize) {
-    sys_mutex_lock(mutex_id);
+/// Returns `-ETIMEDOUT` if the mutex is not got in `timeout_ms`.
+pub fn mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_mutex_lock_timeout(mutex_id, timeout_ms)
 }
-pub fn mutex_unlock(mutex_id: usize) {
-    sys_mutex_unlock(mutex_id);
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
 }
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
 }
-pub fn semaphore_up(sem_id: usize) {
-    sys_semaphore_up(sem_id);
+pub fn semaphore_up(sem_id: usize) -> isize {
+    sys_semaphore_up(sem_id)
 }
-pub fn semaphore_down(sem_id: usize) {
-    sys_semaphore_down(sem_id);
+/// Returns `-EDEADLK` if the deadlock detection is enabled and blocking
+/// could deadlock.
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
+}
+/// Returns `-ETIMEDOUT` if the semaphore is not got in `timeout_ms`.
+pub fn semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    sys_semaphore_down_timeout(sem_id, timeout_ms)
 }
 pub fn condvar_create() -> isize {
     sys_condvar_create(0)
 }
-pub fn condvar_signal(condvar_id: usize) {
-    sys_condvar_signal(condvar_id);
+pub fn condvar_signal(condvar_id: usize) -> isize {
+    sys_condvar_signal(condvar_id)
+}
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(condvar_id, mutex_id)
+}
+/// Returns `-ETIMEDOUT` if not signaled in `timeout_ms`, the mutex is locked
+/// again either way.
+pub fn condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_condvar_wait_timeout(condvar_id, mutex_id, timeout_ms)
+}
+pub fn condvar_broadcast(condvar_id: usize) -> isize {
+    sys_condvar_broadcast(condvar_id)
+}
+pub fn rwlock_create() -> isize {
+    sys_rwlock_create()
+}
+pub fn rwlock_read(rwlock_id: usize) -> isize {
+    sys_rwlock_read(rwlock_id)
+}
+pub fn rwlock_write(rwlock_id: usize) -> isize {
+    sys_rwlock_write(rwlock_id)
+}
+/// Returns `-EINVAL` if the lock is not held.
+pub fn rwlock_unlock(rwlock_id: usize) -> isize {
+    sys_rwlock_unlock(rwlock_id)
+}
+/// Returns `-EINVAL` if `count` is zero.
+pub fn barrier_create(count: usize) -> isize {
+    sys_barrier_create(count)
+}
+/// Returns 1 for the last thread arrived at the barrier, and 0 for the others.
+pub fn barrier_wait(barrier_id: usize) -> isize {
+    sys_barrier_wait(barrier_id)
+}
+/// Enables the deadlock detection for the mutexes and semaphores of the
+/// current process.
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
+}
+/// Opens the semaphore `name` shared by all processes, which is created with
+/// `value` by `OpenFlags::CREATE`. Returns a handle for `semaphore_up` and
+/// `semaphore_down`. `name` is like `"/name\0"`.
+pub fn sem_open(name: &str, flags: OpenFlags, value: usize) -> isize {
+    sys_sem_open(name, flags.bits, value)
+}
+pub fn sem_unlink(name: &str) -> isize {
+    sys_sem_unlink(name)
+}
+/// Opens the message queue `name` shared by all processes, which is created
+/// by `OpenFlags::CREATE` to hold `max_msgs` messages of up to `msg_size`
+/// bytes, or the defaults if zero. `name` is like `"/name\0"`.
+pub fn mq_open(name: &str, flags: OpenFlags, max_msgs: usize, msg_size: usize) -> isize {
+    sys_mq_open(name, flags.bits, max_msgs, msg_size)
+}
+pub fn mq_unlink(name: &str) -> isize {
+    sys_mq_unlink(name)
+}
+/// Blocks while the queue is full, unless opened with `OpenFlags::NONBLOCK`.
+pub fn mq_send(mqd: usize, msg: &[u8], prio: u32) -> isize {
+    sys_mq_send(mqd, msg, prio)
+}
+/// Receives the message of the highest priority, the oldest one among them,
+/// into `buf` of at least the message size. Returns the length of the
+/// message, and sets `prio` to its priority.
+pub fn mq_receive(mqd: usize, buf: &mut [u8], prio: &mut u32) -> isize {
+    sys_mq_receive(mqd, buf, prio)
 }
-pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
-    sys_condvar_wait(condvar_id, mutex_id);
+/// Keeps only `rights` of a handle, returns the rights left. A handle without
+/// `Rights::INHERIT` is not copied to the child by `fork` and `spawn`, like
+/// those of the mutexes and semaphores.
+pub fn handle_restrict(handle: usize, rights: Rights) -> isize {
+    sys_handle_restrict(handle, rights.bits())
 }
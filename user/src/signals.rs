@@ -3,7 +3,12 @@
 #[derive(Debug, Clone, Copy)]
 pub struct SignalAction {
     pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself.
     pub mask: SignalFlags,
+    pub flags: SigActionFlags,
+    /// Where the handler returns to, set by `sigaction` unless
+    /// `SA_RESTORER` is given.
+    pub restorer: usize,
 }
 
 impl Default for SignalAction {
@@ -11,10 +16,141 @@ impl Default for SignalAction {
         Self {
             handler: 0,
             mask: SignalFlags::empty(),
+            flags: SigActionFlags::empty(),
+            restorer: 0,
         }
     }
 }
 
+/// `how` of `sigprocmask`.
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// `SignalAction::handler` for the default action of the signal.
+pub const SIG_DFL: usize = 0;
+/// `SignalAction::handler` to ignore the signal.
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    pub struct SigActionFlags: u32 {
+        /// No `SIGCHLD` when a child stops or continues.
+        const SA_NOCLDSTOP = 1;
+        /// The handler is `fn(i32, &SigInfo, &mut UContext)`.
+        const SA_SIGINFO = 4;
+        const SA_RESTORER = 0x0400_0000;
+        /// The handler runs on the stack set by `sigaltstack`.
+        const SA_ONSTACK = 0x0800_0000;
+        const SA_RESTART = 0x1000_0000;
+        const SA_NODEFER = 0x4000_0000;
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
+pub const SI_USER: i32 = 0;
+pub const SI_QUEUE: i32 = -1;
+pub const SI_TKILL: i32 = -6;
+pub const SI_KERNEL: i32 = 0x80;
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+pub const ILL_ILLOPC: i32 = 1;
+pub const FPE_FLTDIV: i32 = 3;
+pub const FPE_FLTOVF: i32 = 4;
+pub const FPE_FLTUND: i32 = 5;
+pub const FPE_FLTRES: i32 = 6;
+pub const FPE_FLTINV: i32 = 7;
+pub const FPE_FLTUNK: i32 = 14;
+pub const SEGV_MAPERR: i32 = 1;
+pub const SEGV_ACCERR: i32 = 2;
+pub const BUS_ADRALN: i32 = 1;
+
+/// Information about a signal, given to the handlers with `SA_SIGINFO`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    /// Process which sent the signal, or the child of `SIGCHLD`.
+    pub pid: i32,
+    /// Exit code of the child of `SIGCHLD`, or the signal which terminated,
+    /// stopped or continued it.
+    pub status: i32,
+    /// Faulting address of `SIGSEGV`, `SIGBUS`, `SIGILL` and `SIGFPE`.
+    pub addr: usize,
+    /// Value sent with the signal by `sigqueue`.
+    pub value: usize,
+}
+
+pub const SS_ONSTACK: u32 = 1;
+pub const SS_DISABLE: u32 = 2;
+pub const MINSIGSTKSZ: usize = 5120;
+
+/// Stack for the handlers with `SA_ONSTACK`, see `sigaltstack`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalStack {
+    pub sp: usize,
+    pub flags: u32,
+    pub size: usize,
+}
+
+impl Default for SignalStack {
+    fn default() -> Self {
+        Self {
+            sp: 0,
+            flags: SS_DISABLE,
+            size: 0,
+        }
+    }
+}
+
+/// General-purpose registers of the interrupted context.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MContext {
+    pub regs: [u64; 31],
+    pub sp: u64,
+    pub pc: u64,
+    pub pstate: u64,
+}
+
+/// FP/SIMD registers of the interrupted context.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct FpContext {
+    pub v: [u128; 32],
+    pub fpcr: u64,
+    pub fpsr: u64,
+}
+
+/// Context interrupted by a handler with `SA_SIGINFO`. The changes made by
+/// the handler take effect when it returns.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UContext {
+    pub stack: SignalStack,
+    pub mask: SignalFlags,
+    pub mcontext: MContext,
+    pub fpcontext: FpContext,
+}
+
+core::arch::global_asm!(
+    "
+    .globl __sigreturn_trampoline
+__sigreturn_trampoline:
+    mov     x8, #139 // SYSCALL_SIGRETURN
+    svc     #0"
+);
+
+extern "C" {
+    /// The default restorer, which calls `sigreturn` when a handler returns.
+    pub fn __sigreturn_trampoline();
+}
+
 pub const SIGDEF: i32 = 0; // Default signal handling
 pub const SIGHUP: i32 = 1;
 pub const SIGINT: i32 = 2;
@@ -47,9 +183,12 @@ pub const SIGWINCH: i32 = 28;
 pub const SIGIO: i32 = 29;
 pub const SIGPWR: i32 = 30;
 pub const SIGSYS: i32 = 31;
+/// Real-time signals, which are queued, from `SIGRTMIN` to `SIGRTMAX`.
+pub const SIGRTMIN: i32 = 32;
+pub const SIGRTMAX: i32 = 63;
 
 bitflags! {
-    pub struct SignalFlags: i32 {
+    pub struct SignalFlags: u64 {
         const SIGDEF = 1; // Default signal handling
         const SIGHUP = 1 << 1;
         const SIGINT = 1 << 2;
@@ -82,5 +221,6 @@ bitflags! {
         const SIGIO = 1 << 29;
         const SIGPWR = 1 << 30;
         const SIGSYS = 1 << 31;
+        const SIGRT = 0xffff_ffff << SIGRTMIN;
     }
 }
@@ -0,0 +1,207 @@
+//! Mutex, condvar and once built on atomics, which only make a syscall to
+//! block on a futex or to wake its waiters.
+
+use super::{futex_requeue, futex_wait, futex_wake};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+// locked, and maybe with waiters to wake on unlock
+const CONTENDED: u32 = 2;
+
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// Takes the lock as `CONTENDED`, as there may be other waiters which
+    /// the unlock has to wake.
+    fn lock_contended(&self) {
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            futex_wait(&self.state, CONTENDED, None);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+pub struct Condvar {
+    // bumped by every notification, so a waiter never misses one which comes
+    // after it unlocks the mutex
+    seq: AtomicU32,
+    // state of the mutex used with this condvar, for `notify_all`
+    mutex: AtomicPtr<AtomicU32>,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            mutex: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        self.mutex.store(
+            &mutex.state as *const _ as *mut AtomicU32,
+            Ordering::Relaxed,
+        );
+        let seq = self.seq.load(Ordering::Relaxed);
+        drop(guard);
+        futex_wait(&self.seq, seq, None);
+        mutex.lock_contended();
+        MutexGuard { mutex }
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.seq, 1);
+    }
+
+    /// Wakes one waiter and moves the others to the mutex, each of them is
+    /// woken by the unlock of the previous one.
+    pub fn notify_all(&self) {
+        let mutex = self.mutex.load(Ordering::Relaxed);
+        if mutex.is_null() {
+            return;
+        }
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_requeue(&self.seq, 1, unsafe { &*mutex }, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+// running, and with waiters to wake when complete
+const QUEUED: u32 = 2;
+const COMPLETE: u32 = 3;
+
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Runs `f` if it is the first call, otherwise blocks until the first
+    /// call completes.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match state {
+                COMPLETE => return,
+                INCOMPLETE => {
+                    if let Err(s) = self.state.compare_exchange(
+                        INCOMPLETE,
+                        RUNNING,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    ) {
+                        state = s;
+                        continue;
+                    }
+                    f();
+                    if self.state.swap(COMPLETE, Ordering::Release) == QUEUED {
+                        futex_wake(&self.state, usize::MAX);
+                    }
+                    return;
+                }
+                _ => {
+                    if state == RUNNING {
+                        if let Err(s) = self.state.compare_exchange(
+                            RUNNING,
+                            QUEUED,
+                            Ordering::Acquire,
+                            Ordering::Acquire,
+                        ) {
+                            state = s;
+                            continue;
+                        }
+                    }
+                    futex_wait(&self.state, QUEUED, None);
+                    state = self.state.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
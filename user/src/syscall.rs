@@ -1,7 +1,11 @@
-use super::SignalAction;
+use super::{
+    CloneFlags, ITimerVal, Rlimit, Rusage, SigInfo, SignalAction, SignalStack, SpawnFileAction,
+    TimeSpec, Tms,
+};
 use core::arch::asm;
 
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_IOCTL: usize = 29;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE2: usize = 59;
@@ -9,16 +13,40 @@ const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_GETITIMER: usize = 102;
+const SYSCALL_SETITIMER: usize = 103;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_TGKILL: usize = 131;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_SIGALTSTACK: usize = 132;
 const SYSCALL_SIGACTION: usize = 134;
 const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGTIMEDWAIT: usize = 137;
+const SYSCALL_RT_SIGQUEUEINFO: usize = 138;
 const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
+const SYSCALL_GETRUSAGE: usize = 165;
+const SYSCALL_SBRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
+const SYSCALL_HANDLE_RESTRICT: usize = 470;
+const SYSCALL_ALARM: usize = 471;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
-const SYSCALL_FORK: usize = 220;
-const SYSCALL_EXEC: usize = 221;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXECVE: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 
 const SYSCALL_THREAD_CREATE: usize = 1000;
@@ -27,12 +55,28 @@ const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1013;
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1023;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1033;
+const SYSCALL_CONDVAR_BROADCAST: usize = 1034;
+const SYSCALL_RWLOCK_CREATE: usize = 1040;
+const SYSCALL_RWLOCK_READ: usize = 1041;
+const SYSCALL_RWLOCK_WRITE: usize = 1042;
+const SYSCALL_RWLOCK_UNLOCK: usize = 1043;
+const SYSCALL_BARRIER_CREATE: usize = 1050;
+const SYSCALL_BARRIER_WAIT: usize = 1051;
+const SYSCALL_SEM_OPEN: usize = 1060;
+const SYSCALL_SEM_UNLINK: usize = 1061;
+const SYSCALL_MQ_OPEN: usize = 1070;
+const SYSCALL_MQ_UNLINK: usize = 1071;
+const SYSCALL_MQ_SEND: usize = 1072;
+const SYSCALL_MQ_RECEIVE: usize = 1073;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let ret;
@@ -48,6 +92,23 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        asm!(
+            "svc #0",
+            inlateout("x0") args[0] => ret,
+            in("x1") args[1],
+            in("x2") args[2],
+            in("x3") args[3],
+            in("x4") args[4],
+            in("x5") args[5],
+            in("x8") id,
+        );
+    }
+    ret
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
@@ -63,6 +124,10 @@ pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
 pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE2, [pipe.as_mut_ptr() as usize, 0, 0])
 }
@@ -94,18 +159,108 @@ pub fn sys_getpid() -> isize {
 }
 
 pub fn sys_fork() -> isize {
-    syscall(SYSCALL_FORK, [0, 0, 0])
+    syscall(SYSCALL_CLONE, [0, 0, 0])
+}
+
+/// `clone` with `CLONE_VM | CLONE_VFORK`. It is always inlined, so the child
+/// does not return from a function whose frame the parent still uses.
+#[inline(always)]
+pub fn sys_vfork() -> isize {
+    let ret;
+    unsafe {
+        asm!(
+            "svc #0",
+            inlateout("x0") (CloneFlags::CLONE_VM | CloneFlags::CLONE_VFORK).bits() as usize => ret,
+            in("x1") 0,
+            in("x8") SYSCALL_CLONE,
+        );
+    }
+    ret
+}
+
+/// `clone`, the new task calls `entry(arg)` on `stack` and exits with its
+/// return value.
+pub fn sys_clone(
+    flags: u32,
+    stack: usize,
+    parent_tid: *mut i32,
+    tls: usize,
+    child_tid: *mut i32,
+    entry: extern "C" fn(usize) -> i32,
+    arg: usize,
+) -> isize {
+    let ret;
+    unsafe {
+        asm!(
+            "svc #0",
+            "cbnz x0, 1f",
+            // in the new task
+            "mov x0, x10",
+            "blr x9",
+            "mov x8, #93", // SYSCALL_EXIT
+            "svc #0",
+            "1:",
+            inlateout("x0") flags as usize => ret,
+            in("x1") stack,
+            in("x2") parent_tid,
+            in("x3") tls,
+            in("x4") child_tid,
+            in("x8") SYSCALL_CLONE,
+            in("x9") entry,
+            in("x10") arg,
+        );
+    }
+    ret
+}
+
+pub fn sys_futex(
+    uaddr: *const u32,
+    op: usize,
+    val: usize,
+    val2: usize,
+    uaddr2: *const u32,
+) -> isize {
+    syscall6(
+        SYSCALL_FUTEX,
+        [uaddr as usize, op, val, val2, uaddr2 as usize, 0],
+    )
+}
+
+pub fn sys_spawn(
+    path: &str,
+    args: &[*const u8],
+    envp: &[*const u8],
+    file_actions: &[SpawnFileAction],
+) -> isize {
+    syscall6(
+        SYSCALL_SPAWN,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envp.as_ptr() as usize,
+            file_actions.as_ptr() as usize,
+            file_actions.len(),
+            0,
+        ],
+    )
 }
 
-pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
+pub fn sys_execve(path: &str, args: &[*const u8], envp: &[*const u8]) -> isize {
     syscall(
-        SYSCALL_EXEC,
-        [path.as_ptr() as usize, args.as_ptr() as usize, 0],
+        SYSCALL_EXECVE,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envp.as_ptr() as usize,
+        ],
     )
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: u32) -> isize {
+    syscall(
+        SYSCALL_WAITPID,
+        [pid as usize, exit_code as usize, options as usize],
+    )
 }
 
 pub fn sys_open(path: &str, flags: u32) -> isize {
@@ -124,6 +279,14 @@ pub fn sys_gettid() -> isize {
     syscall(SYSCALL_GETTID, [0; 3])
 }
 
+pub fn sys_set_priority(prio: usize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [prio, 0, 0])
+}
+
+pub fn sys_get_priority() -> isize {
+    syscall(SYSCALL_GETPRIORITY, [0; 3])
+}
+
 pub fn sys_waittid(tid: usize) -> isize {
     syscall(SYSCALL_WAITTID, [tid, 0, 0])
 }
@@ -139,20 +302,105 @@ pub fn sys_sigaction(
     )
 }
 
-pub fn sys_sigprocmask(mask: u32) -> isize {
-    syscall(SYSCALL_SIGPROCMASK, [mask as usize, 0, 0])
+pub fn sys_sigaltstack(ss: *const SignalStack, old_ss: *mut SignalStack) -> isize {
+    syscall(SYSCALL_SIGALTSTACK, [ss as usize, old_ss as usize, 0])
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u64, old_set: *mut u64) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, old_set as usize])
+}
+
+pub fn sys_sigtimedwait(set: *const u64, info: *mut SigInfo, timeout: *const TimeSpec) -> isize {
+    syscall(
+        SYSCALL_SIGTIMEDWAIT,
+        [set as usize, info as usize, timeout as usize],
+    )
+}
+
+pub fn sys_rt_sigqueueinfo(pid: usize, signal: i32, info: *const SigInfo) -> isize {
+    syscall(
+        SYSCALL_RT_SIGQUEUEINFO,
+        [pid, signal as usize, info as usize],
+    )
 }
 
 pub fn sys_sigreturn() -> isize {
     syscall(SYSCALL_SIGRETURN, [0, 0, 0])
 }
 
-pub fn sys_kill(pid: usize, signal: i32) -> isize {
-    syscall(SYSCALL_KILL, [pid, signal as usize, 0])
+pub fn sys_kill(pid: isize, signal: i32) -> isize {
+    syscall(SYSCALL_KILL, [pid as usize, signal as usize, 0])
 }
 
-pub fn sys_mutex_create(blocking: bool) -> isize {
-    syscall(SYSCALL_MUTEX_CREATE, [blocking as usize, 0, 0])
+pub fn sys_tgkill(tgid: usize, tid: usize, signal: i32) -> isize {
+    syscall(SYSCALL_TGKILL, [tgid, tid, signal as usize])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
+pub fn sys_times(tms: *mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as usize, 0, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: *mut Rusage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as usize, 0])
+}
+
+pub fn sys_getitimer(which: usize, curr_value: *mut ITimerVal) -> isize {
+    syscall(SYSCALL_GETITIMER, [which, curr_value as usize, 0])
+}
+
+pub fn sys_setitimer(
+    which: usize,
+    new_value: *const ITimerVal,
+    old_value: *mut ITimerVal,
+) -> isize {
+    syscall(
+        SYSCALL_SETITIMER,
+        [which, new_value as usize, old_value as usize],
+    )
+}
+
+pub fn sys_alarm(secs: usize) -> isize {
+    syscall(SYSCALL_ALARM, [secs, 0, 0])
+}
+
+pub fn sys_getrlimit(resource: usize, rlim: *mut Rlimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, rlim as usize, 0])
+}
+
+pub fn sys_setrlimit(resource: usize, rlim: *const Rlimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, rlim as usize, 0])
+}
+
+pub fn sys_sbrk(increment: isize) -> isize {
+    syscall(SYSCALL_SBRK, [increment as usize, 0, 0])
+}
+
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MMAP, [start, len, prot])
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0])
+}
+
+pub fn sys_mutex_create(flags: usize) -> isize {
+    syscall(SYSCALL_MUTEX_CREATE, [flags, 0, 0])
 }
 
 pub fn sys_mutex_lock(id: usize) -> isize {
@@ -163,6 +411,10 @@ pub fn sys_mutex_unlock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_lock_timeout(id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_MUTEX_LOCK_TIMEOUT, [id, timeout_ms, 0])
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
 }
@@ -175,6 +427,10 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
 }
 
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN_TIMEOUT, [sem_id, timeout_ms, 0])
+}
+
 pub fn sys_condvar_create(_arg: usize) -> isize {
     syscall(SYSCALL_CONDVAR_CREATE, [_arg, 0, 0])
 }
@@ -186,3 +442,96 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
+
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    syscall(
+        SYSCALL_CONDVAR_WAIT_TIMEOUT,
+        [condvar_id, mutex_id, timeout_ms],
+    )
+}
+
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_BROADCAST, [condvar_id, 0, 0])
+}
+
+pub fn sys_rwlock_create() -> isize {
+    syscall(SYSCALL_RWLOCK_CREATE, [0, 0, 0])
+}
+
+pub fn sys_rwlock_read(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_READ, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_write(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_WRITE, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_UNLOCK, [rwlock_id, 0, 0])
+}
+
+pub fn sys_barrier_create(count: usize) -> isize {
+    syscall(SYSCALL_BARRIER_CREATE, [count, 0, 0])
+}
+
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    syscall(SYSCALL_BARRIER_WAIT, [barrier_id, 0, 0])
+}
+
+pub fn sys_sem_open(name: &str, flags: u32, value: usize) -> isize {
+    syscall(
+        SYSCALL_SEM_OPEN,
+        [name.as_ptr() as usize, flags as usize, value],
+    )
+}
+
+pub fn sys_sem_unlink(name: &str) -> isize {
+    syscall(SYSCALL_SEM_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mq_open(name: &str, flags: u32, max_msgs: usize, msg_size: usize) -> isize {
+    syscall6(
+        SYSCALL_MQ_OPEN,
+        [
+            name.as_ptr() as usize,
+            flags as usize,
+            max_msgs,
+            msg_size,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_unlink(name: &str) -> isize {
+    syscall(SYSCALL_MQ_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mq_send(mqd: usize, msg: &[u8], prio: u32) -> isize {
+    syscall6(
+        SYSCALL_MQ_SEND,
+        [mqd, msg.as_ptr() as usize, msg.len(), prio as usize, 0, 0],
+    )
+}
+
+pub fn sys_mq_receive(mqd: usize, buf: &mut [u8], prio: &mut u32) -> isize {
+    syscall6(
+        SYSCALL_MQ_RECEIVE,
+        [
+            mqd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            prio as *mut u32 as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
+}
+
+pub fn sys_handle_restrict(handle: usize, rights: u32) -> isize {
+    syscall(SYSCALL_HANDLE_RESTRICT, [handle, rights as usize, 0])
+}
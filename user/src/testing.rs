@@ -0,0 +1,162 @@
+//! Helpers shared by the `*_tests` programs, which run each test case in a
+//! child process so that a failed assertion fails that case only.
+
+use super::*;
+use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+/// A test case and its name.
+pub type TestCase = (fn(), &'static str);
+
+/// Runs `f` in a child process, returns whether it exits with 0.
+pub fn run(f: fn()) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    } else {
+        let mut exit_code: i32 = 0;
+        waitpid(pid as usize, &mut exit_code);
+        if exit_code != 0 {
+            println!("FAILED!");
+        } else {
+            println!("OK!");
+        }
+        exit_code == 0
+    }
+}
+
+/// Runs the cases of the test program `name` in order, returns the exit
+/// code of the program.
+pub fn run_tests(name: &str, tests: &[TestCase]) -> i32 {
+    let mut fail_num = 0;
+    for test in tests {
+        println!("Testing {}", test.1);
+        if !run(test.0) {
+            fail_num += 1;
+        }
+    }
+    if fail_num == 0 {
+        println!("{} passed!", name);
+        0
+    } else {
+        println!("{} {} failed!", fail_num, name.replace('_', " "));
+        -1
+    }
+}
+
+/// Waits for the child `pid`, which must exit normally, returns its exit
+/// code.
+pub fn wait_exited(pid: isize) -> i32 {
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(WIFEXITED(exit_code));
+    WEXITSTATUS(exit_code)
+}
+
+/// Creates `N` threads running `entry` with their index, returns their tids.
+pub fn spawn_threads<const N: usize>(entry: fn(usize) -> !) -> [isize; N] {
+    let mut tids = [0; N];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(entry as usize, i);
+        assert!(*tid > 0);
+    }
+    tids
+}
+
+/// A stack given to `clone` or `sigaltstack`.
+#[repr(align(16))]
+pub struct Stack<const SIZE: usize>([u8; SIZE]);
+
+impl<const SIZE: usize> Stack<SIZE> {
+    pub const fn new() -> Self {
+        Self([0; SIZE])
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.0.as_ptr() as usize
+    }
+
+    pub fn top(&self) -> usize {
+        self.bottom() + SIZE
+    }
+}
+
+const LOG_LEN: usize = 8;
+
+static LOG: [AtomicI32; LOG_LEN] = [
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+    AtomicI32::new(0),
+];
+static LOG_POS: AtomicUsize = AtomicUsize::new(0);
+
+/// Records `event` in the order in which it happens, e.g. in a signal
+/// handler.
+pub fn log(event: i32) {
+    let pos = LOG_POS.fetch_add(1, Ordering::SeqCst);
+    assert!(pos < LOG_LEN);
+    LOG[pos].store(event, Ordering::SeqCst);
+}
+
+/// The number of events recorded.
+pub fn log_len() -> usize {
+    LOG_POS.load(Ordering::SeqCst)
+}
+
+/// Forgets the events recorded.
+pub fn clear_log() {
+    LOG_POS.store(0, Ordering::SeqCst);
+}
+
+/// Checks that exactly `events` have been recorded.
+pub fn check_log(events: &[i32]) {
+    assert_eq!(log_len(), events.len());
+    for (entry, event) in LOG.iter().zip(events) {
+        assert_eq!(entry.load(Ordering::SeqCst), *event);
+    }
+}
+
+/// A handler which records the signal.
+pub extern "C" fn log_signal(sig: i32) {
+    log(sig);
+}
+
+pub fn set_handler(signum: i32, handler: usize, mask: SignalFlags, flags: SigActionFlags) {
+    let action = SignalAction {
+        handler,
+        mask,
+        flags,
+        ..SignalAction::default()
+    };
+    let old = SignalAction::default();
+    assert_eq!(sigaction(signum, &action, &old), 0);
+}
+
+/// Forks a child which sends `SIGUSR1` to the caller after a while, and then
+/// writes a byte to `write_fd` if it is not 0. The child exits with 7.
+pub fn kill_later(write_fd: usize) -> isize {
+    let parent = getpid() as usize;
+    let pid = fork();
+    if pid == 0 {
+        sleep(20);
+        kill(parent, SIGUSR1);
+        if write_fd != 0 {
+            sleep(20);
+            write(write_fd, b"x");
+        }
+        exit(7);
+    }
+    pid
+}
+
+/// Runs until killed, as a thread entry or in a child process.
+pub fn spin(_arg: usize) -> ! {
+    loop {
+        sched_yield();
+    }
+}